@@ -0,0 +1,91 @@
+//! Times `Befunge::run` with and without the path cache.
+//!
+//! Run with `cargo bench`. Each program runs a number of times in both modes;
+//! the best time of five rounds is reported for each.
+
+use befunge_rust::{Befunge, Config, Direction, World};
+use std::hint::black_box;
+use std::io::{self, BufReader};
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 5;
+
+/// Name, source and how many runs make up one round.
+const PROGRAMS: [(&str, &str, usize); 6] = [
+    ("hello", include_str!("../examples/hello.bf"), 20_000),
+    (
+        "factorial",
+        include_str!("../examples/factorial.bf"),
+        20_000,
+    ),
+    // Counts down from a million.
+    ("countdown", "\"d\"::**>1-:#v_@\n       ^    <", 1),
+    // Stores the counter in a data cell and prints it back on every turn.
+    (
+        "data-cells",
+        "\"d\"::**>1-:03p03g.:#v_@\n       ^            <",
+        1,
+    ),
+    // The same loop laid out with long gaps.
+    (
+        "spaced-out",
+        "\"d\"::**>1-                                                  :#v_@\n       ^                                                      <",
+        1,
+    ),
+    // Rewrites a cell of its own loop on every turn, so the cache never
+    // gets to replay that part.
+    (
+        "self-modifying",
+        "\"d\"::**>1-:2%47**\" \"+91p:#v_@\n       ^                  <",
+        1,
+    ),
+];
+
+/// Runs `src` `runs` times and returns the time taken and the steps of one
+/// run.
+fn time(src: &str, runs: usize, config: &Config) -> (Duration, u64) {
+    let world = World::from_source_string(src).expect("benchmark program loads");
+    let mut steps = 0;
+    let start = Instant::now();
+    for _ in 0..runs {
+        let mut world = world.clone();
+        let mut input = BufReader::new(io::empty());
+        let mut output = io::sink();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut input, &mut output)
+            .with_config(config.clone());
+        befunge.run().expect("benchmark program runs");
+        steps = black_box(befunge.steps());
+    }
+    (start.elapsed(), steps)
+}
+
+fn best(src: &str, runs: usize, config: &Config) -> (Duration, u64) {
+    (0..ROUNDS)
+        .map(|_| time(src, runs, config))
+        .min_by_key(|&(elapsed, _)| elapsed)
+        .expect("at least one round")
+}
+
+fn main() {
+    println!(
+        "{:<16} {:>12} {:>12} {:>12} {:>8}",
+        "program", "steps", "uncached", "cached", "speedup"
+    );
+    for (name, src, runs) in PROGRAMS {
+        let (uncached, steps) = best(src, runs, &Config::default().uncached(true));
+        let (cached, cached_steps) = best(src, runs, &Config::default());
+        assert_eq!(
+            steps, cached_steps,
+            "{} takes as many steps either way",
+            name
+        );
+        println!(
+            "{:<16} {:>12} {:>12.2?} {:>12.2?} {:>7.2}x",
+            name,
+            steps,
+            uncached,
+            cached,
+            uncached.as_secs_f64() / cached.as_secs_f64()
+        );
+    }
+}
@@ -1,19 +1,154 @@
-use crate::world::World;
-use std::error::Error;
-use std::io::{self, BufRead, Write};
+use crate::cell::{Arithmetic, Cell};
+use crate::config::Config;
+use crate::error::{Error, ErrorKind, Location, Result};
+use crate::fingerprint::{self, Fingerprint};
+use crate::history::{History, Record, Schedule};
+use crate::observer::{Event, Observer};
+use crate::paths::{Fused, Key, Op, Path, PathCache, Run};
+use crate::random::{self, DirectionSource, ThreadRandom};
+use crate::snapshot::Snapshot;
+use crate::world::{Standard, World};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub struct Befunge<'w, 'io> {
+const SPACE: i32 = 0x20;
+const BEFUNGE_93_INSTRUCTIONS: &str = " 0123456789+-*/%!`><^v?_|\":\\$.,#pg&~@";
+const FUNGE_98_INSTRUCTIONS: &str = "abcdef[]rxjkn's;wz{}uyqt()iohlm=ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// A host-defined instruction; see [`Befunge::register_instruction`].
+pub type Handler<C> = dyn Fn(&mut Context<C>) -> std::result::Result<(), ErrorKind>;
+const SEMICOLON: i32 = 0x3b;
+
+pub struct Befunge<'w, 'io, C: Cell = i32> {
     world: &'w mut World,
-    stack: Vec<i32>,
-    direction: Direction,
-    x: usize,
-    y: usize,
-    mode: Mode,
+    /// The IP that the next step runs.
+    ip: Ip<C>,
+    /// The other IPs, in the order they run after `ip`.
+    queue: VecDeque<Ip<C>>,
+    next_ip_id: i32,
+    fingerprints: Vec<Rc<dyn Fingerprint<C>>>,
+    handlers: HashMap<char, Rc<Handler<C>>>,
+    config: Config,
+    random: Box<dyn DirectionSource>,
+    steps: u64,
+    exit_code: Option<i32>,
+    output_bytes: u64,
+    started: Option<Instant>,
     read: &'io mut dyn BufRead,
     write: &'io mut dyn Write,
+    observers: Vec<&'io mut dyn Observer<C>>,
+    /// The cell the current instruction was fetched from, for observers.
+    fetched: (i32, i32, i32, Mode),
+    input_log: Vec<u8>,
+    output_log: Vec<u8>,
+    /// Cells read by `g` and written by `p` in this step, for observers.
+    gets_log: Vec<(i32, i32)>,
+    puts_log: Vec<(i32, i32)>,
+    /// Time this step spent reading input and writing output.
+    io_time: Duration,
+    history: Option<History<C>>,
+    /// Input handed back by [`Befunge::step_back`], read before `read`.
+    unread: VecDeque<u8>,
+    /// How many bytes of input the program has used.
+    input_offset: u64,
+}
+
+/// An instruction pointer and the state it carries with it.
+#[derive(Debug, Clone)]
+pub(crate) struct Ip<C> {
+    pub(crate) id: i32,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) delta: Delta,
+    pub(crate) mode: Mode,
+    pub(crate) stack: Vec<C>,
+    /// The Funge-98 stacks below `stack`, bottom first.
+    pub(crate) stack_stack: Vec<Vec<C>>,
+    pub(crate) storage_offset: (i32, i32),
+    /// For each of `A` to `Z`, the loaded fingerprints that define it, as
+    /// indices into `Befunge::fingerprints`, most recent last.
+    pub(crate) semantics: [Vec<usize>; 26],
+}
+
+impl<C> Ip<C> {
+    pub(crate) fn new(id: i32, x: i32, y: i32, delta: Delta, stack: Vec<C>) -> Ip<C> {
+        Ip {
+            id,
+            x,
+            y,
+            delta,
+            mode: Mode::Interpret,
+            stack,
+            stack_stack: Vec::new(),
+            storage_offset: (0, 0),
+            semantics: Default::default(),
+        }
+    }
+}
+
+/// How far the IP moves each tick, with y growing downwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Delta {
+    pub dx: i32,
+    pub dy: i32,
+}
+
+impl Delta {
+    pub fn new(dx: i32, dy: i32) -> Delta {
+        Delta { dx, dy }
+    }
+
+    pub fn reverse(self) -> Delta {
+        Delta::new(self.dx.wrapping_neg(), self.dy.wrapping_neg())
+    }
+
+    /// Rotates 90 degrees anticlockwise as seen on screen.
+    pub fn turn_left(self) -> Delta {
+        Delta::new(self.dy, self.dx.wrapping_neg())
+    }
+
+    /// Rotates 90 degrees clockwise as seen on screen.
+    pub fn turn_right(self) -> Delta {
+        Delta::new(self.dy.wrapping_neg(), self.dx)
+    }
+
+    /// The cardinal direction this delta points in, if it is a unit step.
+    pub fn direction(self) -> Option<Direction> {
+        match (self.dx, self.dy) {
+            (0, -1) => Some(Direction::Up),
+            (0, 1) => Some(Direction::Down),
+            (-1, 0) => Some(Direction::Left),
+            (1, 0) => Some(Direction::Right),
+            _ => None,
+        }
+    }
+}
+
+impl From<Direction> for Delta {
+    fn from(direction: Direction) -> Delta {
+        direction.delta()
+    }
+}
+
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.direction() {
+            Some(Direction::Up) => write!(f, "up"),
+            Some(Direction::Down) => write!(f, "down"),
+            Some(Direction::Left) => write!(f, "left"),
+            Some(Direction::Right) => write!(f, "right"),
+            None => write!(f, "by ({}, {})", self.dx, self.dy),
+        }
+    }
 }
 
-#[derive(Debug)]
+/// The four unit deltas of Befunge-93.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Up,
     Down,
@@ -21,21 +156,79 @@ pub enum Direction {
     Right,
 }
 
-enum Mode {
+impl Direction {
+    pub fn delta(self) -> Delta {
+        match self {
+            Direction::Up => Delta::new(0, -1),
+            Direction::Down => Delta::new(0, 1),
+            Direction::Left => Delta::new(-1, 0),
+            Direction::Right => Delta::new(1, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
     Interpret,
     AsciiPush,
 }
 
-fn invalid_arithmetic_operation(message: &'static str) -> Box<dyn Error> {
-    io::Error::new(io::ErrorKind::InvalidData, message).into()
+/// Outcome of executing a single instruction with [`Befunge::step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// The instruction ran and the IP moved on to the next cell.
+    Continued,
+    /// The last IP is on `@`, or an IP ran a Funge-98 `q`; further steps
+    /// keep returning `Halted`. An `@` that leaves other IPs running gives
+    /// `Continued`.
+    Halted,
+    /// The input reader reported `WouldBlock`; the IP has not moved and the
+    /// same instruction is retried by the next step.
+    WaitingForInput,
+}
+
+/// The instruction a cell holds; values that are not characters match nothing.
+fn opcode_char(value: i32) -> char {
+    u32::try_from(value)
+        .ok()
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// A coordinate as a Befunge-93 grid index; negative ones become 0.
+fn grid(coordinate: i32) -> usize {
+    usize::try_from(coordinate).unwrap_or(0)
 }
 
-fn read_integer_token(read: &mut dyn BufRead) -> io::Result<String> {
+/// Converts days since 1970-01-01 to a proleptic Gregorian (year, month, day).
+fn civil_from_days(days: i64) -> (i32, i32, i32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year as i32, month as i32, day as i32)
+}
+
+/// The index of an `A`-`Z` instruction.
+fn letter(c: char) -> Option<usize> {
+    c.is_ascii_uppercase().then(|| c as usize - 'A' as usize)
+}
+
+/// Reads a whitespace-delimited token, adding every byte read to `consumed`.
+fn read_integer_token(read: &mut dyn BufRead, consumed: &mut Vec<u8>) -> io::Result<String> {
     let mut buf = [0_u8; 1];
     let mut token = Vec::new();
 
     loop {
-        match read.read(&mut buf)? {
+        let n = read.read(&mut buf)?;
+        consumed.extend_from_slice(&buf[..n]);
+        match n {
             0 if token.is_empty() => {
                 return Err(io::Error::new(
                     io::ErrorKind::UnexpectedEof,
@@ -58,332 +251,2179 @@ impl<'w, 'io> Befunge<'w, 'io> {
         world: &'w mut World,
         x: usize,
         y: usize,
-        direction: Direction,
+        delta: impl Into<Delta>,
         read: &'io mut dyn BufRead,
         write: &'io mut dyn Write,
     ) -> Befunge<'w, 'io> {
+        Befunge::with_stack(world, x, y, delta, read, write, Vec::new())
+    }
+}
+
+impl<'w, 'io, C: Cell> Befunge<'w, 'io, C> {
+    /// Like [`Befunge::new`], but for any cell type and with an initial stack
+    /// (bottom first).
+    pub fn with_stack(
+        world: &'w mut World,
+        x: usize,
+        y: usize,
+        delta: impl Into<Delta>,
+        read: &'io mut dyn BufRead,
+        write: &'io mut dyn Write,
+        stack: Vec<C>,
+    ) -> Befunge<'w, 'io, C> {
         Befunge {
             world,
-            stack: Vec::new(),
-            direction,
-            x,
-            y,
-            mode: Mode::Interpret,
+            ip: Ip::new(0, x as i32, y as i32, delta.into(), stack),
+            queue: VecDeque::new(),
+            next_ip_id: 1,
+            fingerprints: fingerprint::standard(),
+            handlers: HashMap::new(),
+            config: Config::default(),
+            random: Box::new(ThreadRandom),
+            steps: 0,
+            exit_code: None,
+            output_bytes: 0,
+            started: None,
             read,
             write,
+            observers: Vec::new(),
+            fetched: (x as i32, y as i32, SPACE, Mode::Interpret),
+            input_log: Vec::new(),
+            output_log: Vec::new(),
+            gets_log: Vec::new(),
+            puts_log: Vec::new(),
+            io_time: Duration::ZERO,
+            history: None,
+            unread: VecDeque::new(),
+            input_offset: 0,
+        }
+    }
+
+    pub fn with_config(mut self, config: Config) -> Befunge<'w, 'io, C> {
+        self.config = config;
+        self
+    }
+
+    /// Replaces the source of `?` directions, which defaults to [`ThreadRandom`].
+    pub fn with_random(mut self, random: Box<dyn DirectionSource>) -> Befunge<'w, 'io, C> {
+        self.random = random;
+        self
+    }
+
+    /// Reports every executed instruction to `observer`.
+    pub fn with_observer(mut self, observer: &'io mut dyn Observer<C>) -> Befunge<'w, 'io, C> {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Keeps an undo log of the last `limit` steps for [`Befunge::step_back`],
+    /// and tracks which step last wrote each cell.
+    pub fn with_history(mut self, limit: usize) -> Befunge<'w, 'io, C> {
+        self.world.set_journaling(true);
+        self.history = Some(History::new(limit));
+        self
+    }
+
+    /// Makes a fingerprint available to `(`. It takes precedence over any
+    /// registered earlier with the same ID, including the standard ones.
+    pub fn with_fingerprint(
+        mut self,
+        fingerprint: impl Fingerprint<C> + 'static,
+    ) -> Befunge<'w, 'io, C> {
+        self.fingerprints.push(Rc::new(fingerprint));
+        self
+    }
+
+    /// Binds `opcode` to a Rust closure. Instructions of the world's
+    /// language standard cannot be rebound and give
+    /// [`ErrorKind::BuiltinInstruction`]; registering the same opcode again
+    /// replaces the earlier handler.
+    pub fn register_instruction(
+        &mut self,
+        opcode: char,
+        handler: impl Fn(&mut Context<C>) -> std::result::Result<(), ErrorKind> + 'static,
+    ) -> Result<(), C> {
+        let funge98 = self.world.standard() == Standard::Funge98;
+        if BEFUNGE_93_INSTRUCTIONS.contains(opcode)
+            || (funge98 && FUNGE_98_INSTRUCTIONS.contains(opcode))
+        {
+            return Err(ErrorKind::BuiltinInstruction(opcode).into());
         }
+        self.handlers.insert(opcode, Rc::new(handler));
+        Ok(())
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The ID of the IP that the next step runs; the accessors below
+    /// describe that IP.
+    pub fn ip_id(&self) -> i32 {
+        self.ip.id
+    }
+
+    /// The IDs of all live IPs in the order they run, starting with the
+    /// current one.
+    pub fn ip_ids(&self) -> Vec<i32> {
+        let mut ids = vec![self.ip.id];
+        ids.extend(self.queue.iter().map(|ip| ip.id));
+        ids
+    }
+
+    /// The IP's position on the Befunge-93 grid; see
+    /// [`Befunge::coordinates`] for Funge-98 space.
+    pub fn position(&self) -> (usize, usize) {
+        (grid(self.ip.x), grid(self.ip.y))
+    }
+
+    /// The IP's position as signed coordinates, which Funge-98 space can
+    /// need.
+    pub fn coordinates(&self) -> (i32, i32) {
+        (self.ip.x, self.ip.y)
+    }
+
+    pub fn delta(&self) -> Delta {
+        self.ip.delta
+    }
+
+    /// The IP's direction, unless it is flying with a non-unit delta.
+    pub fn direction(&self) -> Option<Direction> {
+        self.ip.delta.direction()
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.ip.mode
+    }
+
+    pub fn stack(&self) -> &[C] {
+        &self.ip.stack
+    }
+
+    pub fn world(&self) -> &World {
+        self.world
+    }
+
+    /// The current IP's stack, for debuggers to edit between steps.
+    pub fn stack_mut(&mut self) -> &mut Vec<C> {
+        &mut self.ip.stack
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        self.world
+    }
+
+    /// The Funge-98 stacks below [`Befunge::stack`], bottom first.
+    pub fn stack_stack(&self) -> &[Vec<C>] {
+        &self.ip.stack_stack
     }
-    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
-        use rand::{thread_rng, Rng};
-        let mut rng = thread_rng();
+
+    /// The status passed to a Funge-98 `q`, once the program has quit with it.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Runs until `@`. The reader is expected to block; a non-blocking reader
+    /// that reports `WouldBlock` makes this spin, so drive [`Befunge::step`]
+    /// directly in that case.
+    ///
+    /// Unless [`Config::uncached`] is set, a Befunge-93 program with no
+    /// observers or history runs each straight stretch of code from a cache
+    /// of decoded instructions, which `p` keeps up to date.
+    pub fn run(&mut self) -> Result<(), C> {
+        let mut paths = self.cacheable().then(|| PathCache::new(self.world));
         loop {
-            match self.mode {
-                Mode::Interpret => match self.world.get(self.x, self.y) as char {
-                    // Push this number on the stack
-                    '0' => self.stack.push(0),
-                    '1' => self.stack.push(1),
-                    '2' => self.stack.push(2),
-                    '3' => self.stack.push(3),
-                    '4' => self.stack.push(4),
-                    '5' => self.stack.push(5),
-                    '6' => self.stack.push(6),
-                    '7' => self.stack.push(7),
-                    '8' => self.stack.push(8),
-                    '9' => self.stack.push(9),
-                    // Addition: Pop a and b, then push a+b
-                    '+' => {
-                        let a = self.stack.pop().unwrap_or(0);
-                        let b = self.stack.pop().unwrap_or(0);
-                        self.stack.push(a + b);
-                    }
-                    // Subtraction: Pop a and b, then push b-a
-                    '-' => {
-                        let a = self.stack.pop().unwrap_or(0);
-                        let b = self.stack.pop().unwrap_or(0);
-                        self.stack.push(b - a);
-                    }
-                    // Multiplication: Pop a and b, then push a*b
-                    '*' => {
-                        let a = self.stack.pop().unwrap_or(0);
-                        let b = self.stack.pop().unwrap_or(0);
-                        self.stack.push(a * b);
-                    }
-                    // Integer division: Pop a and b, then push b/a, rounded towards 0.
-                    '/' => {
-                        let a = self.stack.pop().unwrap_or(0);
-                        let b = self.stack.pop().unwrap_or(0);
-                        if a == 0 {
-                            return Err(invalid_arithmetic_operation("division by zero"));
-                        } else {
-                            self.stack.push(b / a);
-                        }
-                    }
-                    // Modulo: Pop a and b, then push the remainder of the integer division of b/a.
-                    '%' => {
-                        let a = self.stack.pop().unwrap_or(0);
-                        let b = self.stack.pop().unwrap_or(0);
-                        if a == 0 {
-                            return Err(invalid_arithmetic_operation("modulo by zero"));
-                        } else {
-                            self.stack.push(b % a);
-                        }
-                    }
-                    // Logical NOT: Pop a value. If the value is zero, push 1; otherwise, push zero.
-                    '!' => {
-                        let value = self.stack.pop().unwrap_or(0);
-                        self.stack.push(if value == 0 { 1 } else { 0 });
-                    }
-                    // Greater than: Pop a and b, then push 1 if b>a, otherwise zero.
-                    '`' => {
-                        let a = self.stack.pop().unwrap_or(0);
-                        let b = self.stack.pop().unwrap_or(0);
-                        self.stack.push(if b > a { 1 } else { 0 });
-                    }
-                    // Start moving right
-                    '>' => self.direction = Direction::Right,
-                    // Start moving left
-                    '<' => self.direction = Direction::Left,
-                    // Start moving up
-                    '^' => self.direction = Direction::Up,
-                    // Start moving down
-                    'v' => self.direction = Direction::Down,
-                    // Start moving in a random cardinal direction
-                    '?' => {
-                        self.direction = if rng.gen() {
-                            if rng.gen() {
-                                Direction::Up
-                            } else {
-                                Direction::Down
-                            }
-                        } else if rng.gen() {
-                            Direction::Left
-                        } else {
-                            Direction::Right
-                        }
-                    }
-                    // Pop a value; move right if value=0, left otherwise
-                    '_' => {
-                        let value = self.stack.pop().unwrap_or(0);
-                        self.direction = if value == 0 {
-                            Direction::Right
-                        } else {
-                            Direction::Left
-                        }
-                    }
-                    // Pop a value; move down if value=0, up otherwise
-                    '|' => {
-                        let value = self.stack.pop().unwrap_or(0);
-                        self.direction = if value == 0 {
-                            Direction::Down
-                        } else {
-                            Direction::Up
-                        }
-                    }
-                    // Start string mode: push each character's ASCII value all the way up to the next
-                    '"' => self.mode = Mode::AsciiPush,
-                    // Duplicate value on top of the stack
-                    ':' => {
-                        let value = self.stack.pop().unwrap_or(0);
-                        self.stack.push(value);
-                        self.stack.push(value);
-                    }
-                    '\\' => {
-                        let a = self.stack.pop().unwrap_or(0);
-                        let b = self.stack.pop().unwrap_or(0);
-                        self.stack.push(a);
-                        self.stack.push(b);
-                    }
-                    // Pop value from the stack and discard it
-                    '$' => {
-                        self.stack.pop();
-                    }
-                    // Pop value and output as an integer followed by a space
-                    '.' => {
-                        let value = self.stack.pop().unwrap_or(0);
-                        write!(&mut self.write, "{} ", value)?;
-                    }
-                    // Pop value and output as ASCII character
-                    ',' => {
-                        let value = self.stack.pop().unwrap_or(0);
-                        write!(&mut self.write, "{}", char::from(value as u8))?;
-                    }
-                    // Bridge: Skip next cell
-                    '#' => self.forward(),
-                    // A "put" call (a way to store a value for later use). Pop y, x, and v, then change the character at (x,y) in the program to the character with ASCII value v
-                    'p' => {
-                        let y = self.stack.pop().unwrap_or(0);
-                        let x = self.stack.pop().unwrap_or(0);
-                        let v = self.stack.pop().unwrap_or(0);
-                        self.world.set_signed(x, y, v as u8);
-                    }
-                    // A "get" call (a way to retrieve data in storage). Pop y and x, then push ASCII value of the character at that position in the program
-                    'g' => {
-                        let y = self.stack.pop().unwrap_or(0);
-                        let x = self.stack.pop().unwrap_or(0);
-                        let v = self.world.get_signed(x, y);
-                        self.stack.push(v as i32);
-                    }
-                    // Ask user for a number and push it
-                    '&' => {
-                        let token = read_integer_token(self.read)?;
-                        let n = token.parse::<i32>()?;
-                        self.stack.push(n);
-                    }
-                    // Ask user for a character and push its ASCII value
-                    '~' => {
-                        let mut buf: [u8; 1] = [0];
-                        if let Ok(n) = self.read.read(&mut buf) {
-                            if n > 0 {
-                                self.stack.push(i32::from(buf[0]));
-                            }
-                        }
-                    }
-                    '@' => return Ok(()),
-                    ' ' => {}
-                    _ => {}
-                },
-                Mode::AsciiPush => match self.world.get(self.x, self.y) as char {
-                    '"' => self.mode = Mode::Interpret,
-                    c => self.stack.push(i32::from(c as u8)),
-                },
+            if let Some(paths) = &mut paths {
+                let key = (self.ip.x, self.ip.y, self.ip.delta, self.ip.mode);
+                let handlers = &self.handlers;
+                let path = paths.path(self.world, key, |c| handlers.contains_key(&c));
+                if !path.ops.is_empty() {
+                    self.follow(&path, paths)?;
+                    continue;
+                }
+                // A host-defined instruction may change any cell.
+                let opcode = opcode_char(self.world.get_cell(self.ip.x, self.ip.y));
+                if self.ip.mode == Mode::Interpret && self.handlers.contains_key(&opcode) {
+                    paths.clear();
+                }
+            }
+            if self.step()? == Step::Halted {
+                return Ok(());
             }
-            self.forward();
         }
     }
-    fn forward(&mut self) {
-        match self.direction {
-            Direction::Up => {
-                self.y = if self.y == 0 {
-                    self.world.height() - 1
-                } else {
-                    self.y - 1
+
+    /// Whether [`Befunge::run`] may replay decoded paths: nothing is watching
+    /// each step, and the program is Befunge-93.
+    fn cacheable(&self) -> bool {
+        !self.config.uncached
+            && self.observers.is_empty()
+            && self.history.is_none()
+            && self.world.standard() == Standard::Befunge93
+    }
+
+    /// Runs the ops of `path` as [`Befunge::step`] would run its cells, and
+    /// leaves the IP where the path ends: after its branch, or after a `p`
+    /// that changed it.
+    fn follow(&mut self, path: &Path, paths: &mut PathCache) -> Result<(), C> {
+        // The clock is checked once per path rather than per instruction.
+        self.check_limits().map_err(|kind| self.locate(kind))?;
+        for run in &path.runs {
+            let next = match &run.fused {
+                Some(fused) if self.fits(run) => self.perform_fused(fused, path, run),
+                _ => self.perform_ops(path, run.ops.clone(), paths)?,
+            };
+            if let Some(next) = next {
+                (self.ip.x, self.ip.y, self.ip.delta, self.ip.mode) = next;
+                return Ok(());
+            }
+        }
+        (self.ip.x, self.ip.y, self.ip.delta, self.ip.mode) = path.end;
+        Ok(())
+    }
+
+    /// Whether `run` can be done in one go without reaching a step or stack
+    /// limit partway through.
+    fn fits(&self, run: &Run) -> bool {
+        let steps = self.steps + run.ops.len() as u64;
+        self.config.max_steps.is_none_or(|max| steps <= max)
+            && self
+                .config
+                .max_stack
+                .is_none_or(|max| self.ip.stack.len() + run.peak <= max)
+    }
+
+    /// Does a fused run, giving back where a branch took the IP.
+    fn perform_fused(&mut self, fused: &Fused, path: &Path, run: &Run) -> Option<Key> {
+        self.steps += run.ops.len() as u64;
+        match fused {
+            Fused::Skip => None,
+            Fused::Push(values) => {
+                let values = values.iter().map(|&value| C::from_i32(value));
+                self.ip.stack.extend(values);
+                None
+            }
+            Fused::DuplicateBranch { vertical } => {
+                if self.ip.stack.is_empty() {
+                    self.ip.stack.push(C::default());
                 }
+                let zero = self.ip.stack.last().is_none_or(Cell::is_zero);
+                Some(self.branch(path.ops[run.ops.end - 1].at, zero, *vertical))
             }
-            Direction::Down => {
-                self.y = if self.y + 1 == self.world.height() {
-                    0
-                } else {
-                    self.y + 1
+        }
+    }
+
+    /// Runs `path.ops[range]` one at a time, giving back where the IP goes
+    /// instead of the next op.
+    fn perform_ops(
+        &mut self,
+        path: &Path,
+        range: Range<usize>,
+        paths: &mut PathCache,
+    ) -> Result<Option<Key>, C> {
+        for i in range {
+            match self.perform(path, i, paths) {
+                Ok(None) => {}
+                Ok(next) => return Ok(next),
+                Err(kind) => {
+                    (self.ip.x, self.ip.y, self.ip.delta, self.ip.mode) = path.ops[i].at;
+                    return Err(self.locate(kind));
                 }
             }
-            Direction::Left => {
-                self.x = if self.x == 0 {
-                    self.world.width() - 1
-                } else {
-                    self.x - 1
+        }
+        Ok(None)
+    }
+
+    /// Runs `path.ops[i]`, checking the step and stack limits as
+    /// [`Befunge::step`] does; [`Befunge::follow`] has already checked the
+    /// step limit for the first op. Gives back where the IP goes after a
+    /// branch, or after a `p` that changed the path.
+    fn perform(
+        &mut self,
+        path: &Path,
+        i: usize,
+        paths: &mut PathCache,
+    ) -> std::result::Result<Option<Key>, ErrorKind> {
+        if let (Some(max), true) = (self.config.max_steps, i > 0) {
+            if self.steps >= max {
+                return Err(ErrorKind::StepLimitExceeded(max));
+            }
+        }
+        let decoded = &path.ops[i];
+        let mut next = None;
+        match decoded.op {
+            Op::Nop => {
+                self.steps += 1;
+                return Ok(None);
+            }
+            Op::Push(n) => self.ip.stack.push(C::from_i32(n)),
+            Op::Arithmetic(op) => self.arithmetic(op)?,
+            Op::Not => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                self.ip
+                    .stack
+                    .push(C::from_i32(if value.is_zero() { 1 } else { 0 }));
+            }
+            Op::Greater => {
+                let a = self.ip.stack.pop().unwrap_or_default();
+                let b = self.ip.stack.pop().unwrap_or_default();
+                self.ip.stack.push(C::from_i32(if b > a { 1 } else { 0 }));
+            }
+            Op::Duplicate => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                self.ip.stack.push(value.clone());
+                self.ip.stack.push(value);
+            }
+            Op::Swap => {
+                let a = self.ip.stack.pop().unwrap_or_default();
+                let b = self.ip.stack.pop().unwrap_or_default();
+                self.ip.stack.push(a);
+                self.ip.stack.push(b);
+            }
+            Op::Discard => {
+                self.ip.stack.pop();
+            }
+            Op::Put => {
+                let top = |i: usize| {
+                    let stack = &self.ip.stack;
+                    stack.len().checked_sub(i).map_or(0, |i| stack[i].to_i32())
+                };
+                let (ox, oy) = self.ip.storage_offset;
+                let (x, y) = self
+                    .world
+                    .wrap(top(2).wrapping_add(ox), top(1).wrapping_add(oy));
+                let old = self.world.get_cell(x, y);
+                self.instruction(i32::from(b'p'))?;
+                if self.world.get_cell(x, y) != old && paths.invalidate(x, y, path) {
+                    next = Some(path.ops.get(i + 1).map_or(path.end, |next| next.at));
                 }
             }
-            Direction::Right => {
-                self.x = if self.x + 1 == self.world.width() {
-                    0
-                } else {
-                    self.x + 1
+            Op::Instruction(opcode) => {
+                self.instruction(opcode)?;
+            }
+            Op::Branch { vertical } => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                next = Some(self.branch(decoded.at, value.is_zero(), vertical));
+            }
+        }
+        self.check_stack(self.ip.stack.len())?;
+        self.steps += 1;
+        Ok(next)
+    }
+
+    /// Where a `_`, or a `|` if `vertical`, at `at` sends the IP.
+    fn branch(&self, at: Key, zero: bool, vertical: bool) -> Key {
+        let direction = match (vertical, zero) {
+            (false, true) => Direction::Right,
+            (false, false) => Direction::Left,
+            (true, true) => Direction::Down,
+            (true, false) => Direction::Up,
+        };
+        let delta = direction.delta();
+        let (x, y) = self.world.next_position(at.0, at.1, delta.dx, delta.dy);
+        (x, y, delta, at.3)
+    }
+
+    /// Executes the instruction under the current IP, advances it, and hands
+    /// over to the next IP in turn.
+    pub fn step(&mut self) -> Result<Step, C> {
+        let record = self.history.is_some().then(|| self.begin_record());
+        match self.check_limits().and_then(|()| self.execute()) {
+            Ok(Step::WaitingForInput) => Ok(Step::WaitingForInput),
+            Ok(step) => {
+                self.steps += 1;
+                if !self.observers.is_empty() {
+                    self.notify().map_err(ErrorKind::from)?;
                 }
+                let record = record.map(|record| self.finish_record(record));
+                self.input_log.clear();
+                self.output_log.clear();
+                self.gets_log.clear();
+                self.puts_log.clear();
+                self.io_time = Duration::ZERO;
+                let (step, dropped) = self.schedule(step);
+                if let (Some(mut record), Some(history)) = (record, &mut self.history) {
+                    record.schedule = match dropped {
+                        Some(ip) => Schedule::Dropped(Box::new(ip)),
+                        None if self.ip.id == record.ip => Schedule::Stayed,
+                        None => Schedule::Rotated,
+                    };
+                    history.push(record);
+                }
+                Ok(step)
             }
+            Err(kind) => Err(self.locate(kind)),
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// `kind`, raised by the instruction under the current IP.
+    fn locate(&self, kind: ErrorKind) -> Error<C> {
+        let cell = self.world.get_cell(self.ip.x, self.ip.y);
+        let location = Location {
+            ip: self.ip.id,
+            x: grid(self.ip.x),
+            y: grid(self.ip.y),
+            coordinates: (self.ip.x, self.ip.y),
+            delta: self.ip.delta,
+            opcode: cell as u8,
+            cell,
+            stack: self.ip.stack.clone(),
+        };
+        Error::new(kind, location)
+    }
 
-    use super::{Befunge, Direction, World};
-    use std::error::Error;
-    use std::io::BufReader;
+    fn notify(&mut self) -> io::Result<()> {
+        let (x, y, opcode, mode) = self.fetched;
+        let event = Event {
+            step: self.steps,
+            ip: self.ip.id,
+            x,
+            y,
+            opcode,
+            mode,
+            delta: self.ip.delta,
+            stack: &self.ip.stack,
+            input: &self.input_log,
+            output: &self.output_log,
+            gets: &self.gets_log,
+            puts: &self.puts_log,
+            io_time: self.io_time,
+        };
+        for observer in &mut self.observers {
+            observer.instruction(&event)?;
+        }
+        Ok(())
+    }
 
-    #[test]
-    fn hello_world_program1() -> Result<(), Box<dyn Error>> {
-        let src =
-            ">              v\nv  ,,,,,\"Hello\"<\n>48*,          v\nv,,,,,,\"World!\"<\n>25*,@";
-        let read = Vec::new();
-        let mut buf_read = BufReader::new(&read[..]);
-        let mut write = Vec::new();
-        let mut world = World::from_source_string(src)?;
-        {
-            let mut befunge = Befunge::new(
-                &mut world,
-                0,
-                0,
-                Direction::Right,
-                &mut buf_read,
-                &mut write,
+    /// Moves on to the next IP. An IP that stopped only ends the program if
+    /// it was the last one, or if it quit with `q`; otherwise it is dropped
+    /// and returned.
+    fn schedule(&mut self, step: Step) -> (Step, Option<Ip<C>>) {
+        if step == Step::Halted && self.exit_code.is_none() {
+            if let Some(next) = self.queue.pop_front() {
+                let dropped = std::mem::replace(&mut self.ip, next);
+                return (Step::Continued, Some(dropped));
+            }
+        }
+        if step == Step::Continued {
+            if let Some(next) = self.queue.pop_front() {
+                let current = std::mem::replace(&mut self.ip, next);
+                self.queue.push_back(current);
+            }
+        }
+        (step, None)
+    }
+
+    /// Saves what the coming step may change. The part of the stack it can
+    /// reach goes to `History::stack` so that only the part it changed is
+    /// kept; the stack stack and fingerprints are only saved when the
+    /// instruction might change them.
+    fn begin_record(&mut self) -> Record<C> {
+        // Drop writes made between steps, such as a debugger's edits.
+        self.world.take_journal();
+        let reach = self.stack_reach();
+        if let Some(history) = &mut self.history {
+            let len = self.ip.stack.len();
+            history.base = reach.map_or(0, |reach| len.saturating_sub(reach));
+            history.stack.clear();
+            history
+                .stack
+                .extend_from_slice(&self.ip.stack[history.base..]);
+        }
+        let whole = reach.is_none();
+        Record {
+            steps: self.steps,
+            ip: self.ip.id,
+            x: self.ip.x,
+            y: self.ip.y,
+            delta: self.ip.delta,
+            mode: self.ip.mode,
+            storage_offset: self.ip.storage_offset,
+            kept: 0,
+            popped: Vec::new(),
+            stack_stack: whole.then(|| self.ip.stack_stack.clone()),
+            semantics: whole.then(|| Box::new(self.ip.semantics.clone())),
+            schedule: Schedule::Stayed,
+            spawned: self.next_ip_id,
+            writes: Vec::new(),
+            writers: Vec::new(),
+            bounds: self.world.bounds(),
+            input: Vec::new(),
+            exit_code: self.exit_code,
+        }
+    }
+
+    /// How many values from the top of the stack the coming step can pop or
+    /// change, or `None` if it may reach further, swap stacks or load
+    /// fingerprints.
+    fn stack_reach(&self) -> Option<usize> {
+        if self.ip.mode == Mode::AsciiPush {
+            return Some(0);
+        }
+        let (x, y) = if self.world.standard() == Standard::Funge98 {
+            self.skip_markers(self.ip.x, self.ip.y)
+        } else {
+            (self.ip.x, self.ip.y)
+        };
+        let opcode = opcode_char(self.world.get_cell(x, y));
+        if self.handlers.contains_key(&opcode) {
+            return None;
+        }
+        match opcode {
+            '0'..='9'
+            | 'a'..='f'
+            | '"'
+            | '>'
+            | '<'
+            | '^'
+            | 'v'
+            | '?'
+            | '#'
+            | '@'
+            | ' '
+            | ';'
+            | '&'
+            | '~'
+            | '\''
+            | '['
+            | ']'
+            | 'r'
+            | 't'
+            | 'z' => Some(0),
+            '!' | ':' | '_' | '|' | '$' | '.' | ',' | 'j' | 's' | 'q' => Some(1),
+            '+' | '-' | '*' | '/' | '%' | '`' | '\\' | 'g' | 'w' | 'x' => Some(2),
+            'p' => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Reduces a record from [`Befunge::begin_record`] to what the step
+    /// changed, once it has run.
+    fn finish_record(&mut self, mut record: Record<C>) -> Record<C> {
+        let Some(history) = &mut self.history else {
+            return record;
+        };
+        let before = &history.stack;
+        let after = &self.ip.stack[history.base.min(self.ip.stack.len())..];
+        let same = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+        record.kept = history.base + same;
+        record.popped = before[same..].to_vec();
+        if record.stack_stack.as_ref() == Some(&self.ip.stack_stack) {
+            record.stack_stack = None;
+        }
+        if record.semantics.as_deref() == Some(&self.ip.semantics) {
+            record.semantics = None;
+        }
+        record.spawned = self.next_ip_id - record.spawned;
+        record.writes = self.world.take_journal();
+        record.writers = record
+            .writes
+            .iter()
+            .map(|&(x, y, _)| history.last_write.insert((x, y), self.steps))
+            .collect();
+        record.input = self.input_log.clone();
+        record
+    }
+
+    /// Undoes the most recent recorded step: the IPs, their stacks and the
+    /// cells written go back to how they were, and input the step read is
+    /// read again by the next step that asks. Output cannot be taken back,
+    /// and `?` may choose differently when the step runs again. Returns
+    /// whether there was a step to undo.
+    pub fn step_back(&mut self) -> bool {
+        let Some(record) = self.history.as_mut().and_then(|h| h.records.pop_back()) else {
+            return false;
+        };
+        match record.schedule {
+            Schedule::Stayed => {}
+            Schedule::Rotated => {
+                if let Some(ip) = self.queue.pop_back() {
+                    let next = std::mem::replace(&mut self.ip, ip);
+                    self.queue.push_front(next);
+                }
+            }
+            Schedule::Dropped(ip) => {
+                let next = std::mem::replace(&mut self.ip, *ip);
+                self.queue.push_front(next);
+            }
+        }
+        for _ in 0..record.spawned {
+            self.queue.pop_back();
+        }
+        self.next_ip_id -= record.spawned;
+
+        let ip = &mut self.ip;
+        (ip.x, ip.y, ip.delta, ip.mode) = (record.x, record.y, record.delta, record.mode);
+        ip.storage_offset = record.storage_offset;
+        ip.stack.truncate(record.kept);
+        ip.stack.extend(record.popped);
+        if let Some(stack_stack) = record.stack_stack {
+            ip.stack_stack = stack_stack;
+        }
+        if let Some(semantics) = record.semantics {
+            ip.semantics = *semantics;
+        }
+
+        self.world.undo_writes(&record.writes, record.bounds);
+        if let Some(history) = &mut self.history {
+            for (&(x, y, _), writer) in record.writes.iter().zip(record.writers).rev() {
+                match writer {
+                    Some(step) => history.last_write.insert((x, y), step),
+                    None => history.last_write.remove(&(x, y)),
+                };
+            }
+        }
+        for &byte in record.input.iter().rev() {
+            self.unread.push_front(byte);
+        }
+        self.input_offset -= record.input.len() as u64;
+        self.exit_code = record.exit_code;
+        self.steps = record.steps;
+        true
+    }
+
+    /// Captures the world and every IP, with the counters, the input
+    /// position and the state of the `?` source, so that
+    /// [`Befunge::restore`] can carry on from here.
+    pub fn snapshot(&self) -> Snapshot<C> {
+        let ips: Vec<Ip<C>> = std::iter::once(&self.ip)
+            .chain(&self.queue)
+            .map(|ip| Ip {
+                semantics: Default::default(),
+                ..ip.clone()
+            })
+            .collect();
+        let semantics = std::iter::once(&self.ip)
+            .chain(&self.queue)
+            .map(|ip| {
+                ip.semantics
+                    .clone()
+                    .map(|indices| indices.iter().map(|&i| self.fingerprints[i].id()).collect())
+            })
+            .collect();
+        Snapshot {
+            world: self.world.clone(),
+            ips,
+            semantics,
+            next_ip_id: self.next_ip_id,
+            steps: self.steps,
+            exit_code: self.exit_code,
+            output_bytes: self.output_bytes,
+            input_offset: self.input_offset,
+            random: self.random.snapshot(),
+        }
+    }
+
+    /// Puts the world and the IPs back as they were in `snapshot`. The
+    /// snapshot's input position is skipped over, so the reader should
+    /// supply the same input as the run that was saved. Loaded fingerprints
+    /// must be registered with this `Befunge` too, or this fails with
+    /// [`ErrorKind::MissingFingerprint`].
+    pub fn restore(&mut self, snapshot: &Snapshot<C>) -> Result<(), C> {
+        let mut ips = snapshot.ips.clone();
+        for (ip, semantics) in ips.iter_mut().zip(&snapshot.semantics) {
+            for (indices, ids) in ip.semantics.iter_mut().zip(semantics) {
+                *indices = ids
+                    .iter()
+                    .map(|&id| {
+                        self.fingerprints
+                            .iter()
+                            .rposition(|f| f.id() == id)
+                            .ok_or(ErrorKind::MissingFingerprint(id))
+                    })
+                    .collect::<std::result::Result<_, _>>()?;
+            }
+        }
+        // A parsed snapshot always has at least one IP.
+        let mut ips = ips.into_iter();
+        if let Some(ip) = ips.next() {
+            self.ip = ip;
+        }
+        self.queue = ips.collect();
+        self.world.load(&snapshot.world);
+        self.next_ip_id = snapshot.next_ip_id;
+        self.steps = snapshot.steps;
+        self.exit_code = snapshot.exit_code;
+        self.output_bytes = snapshot.output_bytes;
+        self.fetched = (self.ip.x, self.ip.y, SPACE, self.ip.mode);
+        if let Some(Ok(random)) = snapshot.random.as_deref().map(random::from_snapshot) {
+            self.random = random;
+        }
+        if let Some(history) = &mut self.history {
+            *history = History::new(history.limit);
+            self.world.take_journal();
+        }
+        self.unread.clear();
+        io::copy(
+            &mut Read::take(&mut *self.read, snapshot.input_offset),
+            &mut io::sink(),
+        )?;
+        self.input_offset = snapshot.input_offset;
+        Ok(())
+    }
+
+    /// The step that last wrote cell `(x, y)`, if [`Befunge::with_history`]
+    /// was recording then.
+    pub fn last_write(&self, x: i32, y: i32) -> Option<u64> {
+        let history = self.history.as_ref()?;
+        history.last_write.get(&self.world.wrap(x, y)).copied()
+    }
+
+    /// Fails if a stack of `len` values is past [`Config::max_stack`].
+    fn check_stack(&self, len: usize) -> std::result::Result<(), ErrorKind> {
+        match self.config.max_stack {
+            Some(max) if len > max => Err(ErrorKind::StackLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_limits(&mut self) -> std::result::Result<(), ErrorKind> {
+        if let Some(max) = self.config.max_steps {
+            if self.steps >= max {
+                return Err(ErrorKind::StepLimitExceeded(max));
+            }
+        }
+        if let Some(timeout) = self.config.timeout {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            if started.elapsed() > timeout {
+                return Err(ErrorKind::TimeLimitExceeded(timeout));
+            }
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, text: &str) -> std::result::Result<(), ErrorKind> {
+        let len = text.len() as u64;
+        if let Some(max) = self.config.max_output {
+            if self.output_bytes + len > max {
+                return Err(ErrorKind::OutputLimitExceeded(max));
+            }
+        }
+        let started = Instant::now();
+        self.write.write_all(text.as_bytes())?;
+        self.io_time += started.elapsed();
+        self.output_bytes += len;
+        if self.logging() {
+            self.output_log.extend_from_slice(text.as_bytes());
+        }
+        Ok(())
+    }
+
+    fn arithmetic(&mut self, op: Arithmetic) -> std::result::Result<(), ErrorKind> {
+        if self.ip.stack.last().is_none_or(Cell::is_zero) {
+            match op {
+                // Funge-98 defines division by zero to give zero.
+                Arithmetic::Div | Arithmetic::Rem if self.world.standard() == Standard::Funge98 => {
+                    self.ip.stack.pop();
+                    self.ip.stack.pop();
+                    self.ip.stack.push(C::default());
+                    return Ok(());
+                }
+                Arithmetic::Div => return Err(ErrorKind::DivisionByZero),
+                Arithmetic::Rem => return Err(ErrorKind::ModuloByZero),
+                _ => {}
+            }
+        }
+        let a = self.ip.stack.pop().unwrap_or_default();
+        let b = self.ip.stack.pop().unwrap_or_default();
+        match b.arithmetic(op, &a, self.config.overflow) {
+            Some(value) => {
+                self.ip.stack.push(value);
+                Ok(())
+            }
+            None => {
+                // Leave the operands in place so the error shows them.
+                self.ip.stack.push(b);
+                self.ip.stack.push(a);
+                Err(ErrorKind::ArithmeticOverflow)
+            }
+        }
+    }
+
+    fn execute(&mut self) -> std::result::Result<Step, ErrorKind> {
+        if self.exit_code.is_some() {
+            return Ok(Step::Halted);
+        }
+        let funge98 = self.world.standard() == Standard::Funge98;
+        if funge98 && self.ip.mode == Mode::Interpret {
+            // Spaces and `;...;` take no time in Funge-98.
+            let (x, y) = self.skip_markers(self.ip.x, self.ip.y);
+            self.ip.x = x;
+            self.ip.y = y;
+        }
+        let value = self.world.get_cell(self.ip.x, self.ip.y);
+        self.fetched = (self.ip.x, self.ip.y, value, self.ip.mode);
+        match self.ip.mode {
+            Mode::Interpret => {
+                let step = self.instruction(value)?;
+                if step != Step::Continued {
+                    return Ok(step);
+                }
+            }
+            Mode::AsciiPush => match value {
+                0x22 => self.ip.mode = Mode::Interpret,
+                // Funge-98 strings collapse runs of spaces into one.
+                SPACE if funge98 => {
+                    self.ip.stack.push(C::from_i32(SPACE));
+                    let Delta { dx, dy } = self.ip.delta;
+                    if self.cell_ahead(self.ip.x, self.ip.y) == SPACE {
+                        if let Some((x, y)) = self.world.next_stored(self.ip.x, self.ip.y, dx, dy) {
+                            // Stop one short, so that moving on lands there.
+                            let back = self.ip.delta.reverse();
+                            (self.ip.x, self.ip.y) =
+                                self.world.next_position(x, y, back.dx, back.dy);
+                        }
+                    }
+                }
+                value => self.ip.stack.push(C::from_i32(value)),
+            },
+        }
+        self.check_stack(self.ip.stack.len())?;
+        self.forward();
+        Ok(Step::Continued)
+    }
+
+    /// Executes `opcode` in place; the caller moves the IP on afterwards.
+    fn instruction(&mut self, opcode: i32) -> std::result::Result<Step, ErrorKind> {
+        let funge98 = self.world.standard() == Standard::Funge98;
+        match opcode_char(opcode) {
+            // Push this number on the stack
+            '0' => self.ip.stack.push(C::from_i32(0)),
+            '1' => self.ip.stack.push(C::from_i32(1)),
+            '2' => self.ip.stack.push(C::from_i32(2)),
+            '3' => self.ip.stack.push(C::from_i32(3)),
+            '4' => self.ip.stack.push(C::from_i32(4)),
+            '5' => self.ip.stack.push(C::from_i32(5)),
+            '6' => self.ip.stack.push(C::from_i32(6)),
+            '7' => self.ip.stack.push(C::from_i32(7)),
+            '8' => self.ip.stack.push(C::from_i32(8)),
+            '9' => self.ip.stack.push(C::from_i32(9)),
+            // Addition: Pop a and b, then push a+b
+            '+' => self.arithmetic(Arithmetic::Add)?,
+            // Subtraction: Pop a and b, then push b-a
+            '-' => self.arithmetic(Arithmetic::Sub)?,
+            // Multiplication: Pop a and b, then push a*b
+            '*' => self.arithmetic(Arithmetic::Mul)?,
+            // Integer division: Pop a and b, then push b/a, rounded towards 0.
+            '/' => self.arithmetic(Arithmetic::Div)?,
+            // Modulo: Pop a and b, then push the remainder of the integer division of b/a.
+            '%' => self.arithmetic(Arithmetic::Rem)?,
+            // Logical NOT: Pop a value. If the value is zero, push 1; otherwise, push zero.
+            '!' => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                self.ip
+                    .stack
+                    .push(C::from_i32(if value.is_zero() { 1 } else { 0 }));
+            }
+            // Greater than: Pop a and b, then push 1 if b>a, otherwise zero.
+            '`' => {
+                let a = self.ip.stack.pop().unwrap_or_default();
+                let b = self.ip.stack.pop().unwrap_or_default();
+                self.ip.stack.push(C::from_i32(if b > a { 1 } else { 0 }));
+            }
+            // Start moving right
+            '>' => self.ip.delta = Direction::Right.delta(),
+            // Start moving left
+            '<' => self.ip.delta = Direction::Left.delta(),
+            // Start moving up
+            '^' => self.ip.delta = Direction::Up.delta(),
+            // Start moving down
+            'v' => self.ip.delta = Direction::Down.delta(),
+            // Start moving in a random cardinal direction
+            '?' => {
+                let direction = self.random.next_direction();
+                self.ip.delta = direction.ok_or(ErrorKind::RandomExhausted)?.delta();
+            }
+            // Pop a value; move right if value=0, left otherwise
+            '_' => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                self.ip.delta = if value.is_zero() {
+                    Direction::Right.delta()
+                } else {
+                    Direction::Left.delta()
+                }
+            }
+            // Pop a value; move down if value=0, up otherwise
+            '|' => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                self.ip.delta = if value.is_zero() {
+                    Direction::Down.delta()
+                } else {
+                    Direction::Up.delta()
+                }
+            }
+            // Start string mode: push each character's ASCII value all the way up to the next
+            '"' => self.ip.mode = Mode::AsciiPush,
+            // Duplicate value on top of the stack
+            ':' => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                self.ip.stack.push(value.clone());
+                self.ip.stack.push(value);
+            }
+            '\\' => {
+                let a = self.ip.stack.pop().unwrap_or_default();
+                let b = self.ip.stack.pop().unwrap_or_default();
+                self.ip.stack.push(a);
+                self.ip.stack.push(b);
+            }
+            // Pop value from the stack and discard it
+            '$' => {
+                self.ip.stack.pop();
+            }
+            // Pop value and output as an integer followed by a space
+            '.' => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                self.emit(&format!("{} ", value))?;
+            }
+            // Pop value and output as ASCII character
+            ',' => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                let c = char::from(value.to_i32() as u8);
+                self.emit(c.encode_utf8(&mut [0; 4]))?;
+            }
+            // Bridge: Skip next cell
+            '#' => self.forward(),
+            // A "put" call (a way to store a value for later use). Pop y, x, and v, then change the character at (x,y) in the program to the character with ASCII value v
+            'p' => {
+                let (x, y) = self.pop_vector();
+                let v = self.ip.stack.pop().unwrap_or_default();
+                let (ox, oy) = self.ip.storage_offset;
+                let (x, y) = (x.wrapping_add(ox), y.wrapping_add(oy));
+                self.world.set_cell(x, y, v.to_i32());
+                if !self.observers.is_empty() {
+                    self.puts_log.push(self.world.wrap(x, y));
+                }
+            }
+            // A "get" call (a way to retrieve data in storage). Pop y and x, then push ASCII value of the character at that position in the program
+            'g' => {
+                let (x, y) = self.pop_vector();
+                let (ox, oy) = self.ip.storage_offset;
+                let (x, y) = (x.wrapping_add(ox), y.wrapping_add(oy));
+                self.ip.stack.push(C::from_i32(self.world.get_cell(x, y)));
+                if !self.observers.is_empty() {
+                    self.gets_log.push(self.world.wrap(x, y));
+                }
+            }
+            // Ask user for a number and push it
+            '&' => {
+                let started = Instant::now();
+                if self.input_would_block()? {
+                    return Ok(Step::WaitingForInput);
+                }
+                let mut consumed = Vec::new();
+                let token = read_integer_token(&mut self.input(), &mut consumed);
+                self.record_input(&consumed, started);
+                let token = match token {
+                    Err(ref err) if funge98 && err.kind() == io::ErrorKind::UnexpectedEof => {
+                        self.ip.delta = self.ip.delta.reverse();
+                        return Ok(Step::Continued);
+                    }
+                    token => token?,
+                };
+                let n = token
+                    .parse::<C>()
+                    .map_err(|_| ErrorKind::InvalidInteger(token))?;
+                self.ip.stack.push(n);
+            }
+            // Ask user for a character and push its ASCII value
+            '~' => {
+                let started = Instant::now();
+                if self.input_would_block()? {
+                    return Ok(Step::WaitingForInput);
+                }
+                let mut buf: [u8; 1] = [0];
+                let read = self.input().read(&mut buf);
+                match read {
+                    Ok(n) if n > 0 => {
+                        self.record_input(&buf, started);
+                        self.ip.stack.push(C::from_i32(i32::from(buf[0])));
+                    }
+                    // Funge-98 reflects at end of input; Befunge-93 pushes nothing.
+                    _ if funge98 => self.ip.delta = self.ip.delta.reverse(),
+                    _ => {}
+                }
+            }
+            '@' => return Ok(Step::Halted),
+            ' ' => {}
+            c if self.handlers.contains_key(&c) => {
+                let handler = Rc::clone(&self.handlers[&c]);
+                handler(&mut Context { befunge: self })?;
+            }
+            _ if funge98 => return self.instruction_98(opcode),
+            _ => {}
+        }
+        Ok(Step::Continued)
+    }
+
+    /// Whether observers or the history want each step's I/O.
+    fn logging(&self) -> bool {
+        !self.observers.is_empty() || self.history.is_some()
+    }
+
+    /// Logs input read since `started`.
+    fn record_input(&mut self, bytes: &[u8], started: Instant) {
+        self.io_time += started.elapsed();
+        self.input_offset += bytes.len() as u64;
+        if self.logging() {
+            self.input_log.extend_from_slice(bytes);
+        }
+    }
+
+    /// The program's input, starting with anything [`Befunge::step_back`]
+    /// handed back.
+    fn input(&mut self) -> io::Chain<&mut VecDeque<u8>, &mut (dyn BufRead + 'io)> {
+        Read::chain(&mut self.unread, &mut *self.read)
+    }
+
+    fn input_would_block(&mut self) -> io::Result<bool> {
+        match self.input().fill_buf() {
+            Ok(_) => Ok(false),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn pop_vector(&mut self) -> (i32, i32) {
+        let y = self.ip.stack.pop().unwrap_or_default();
+        let x = self.ip.stack.pop().unwrap_or_default();
+        (x.to_i32(), y.to_i32())
+    }
+
+    fn push_vector(stack: &mut Vec<C>, (x, y): (i32, i32)) {
+        stack.push(C::from_i32(x));
+        stack.push(C::from_i32(y));
+    }
+
+    /// How many cells a straight walk can cover before it must repeat.
+    fn skip_budget(&self) -> usize {
+        2 * (self.world.width() + self.world.height()) + 4
+    }
+
+    fn cell_ahead(&self, x: i32, y: i32) -> i32 {
+        let Delta { dx, dy } = self.ip.delta;
+        let (x, y) = self.world.next_position(x, y, dx, dy);
+        self.world.get_cell(x, y)
+    }
+
+    /// The first cell from `(x, y)` onwards that is neither a space nor
+    /// inside `;...;`. A path of nothing but markers gives back `(x, y)`.
+    fn skip_markers(&self, x: i32, y: i32) -> (i32, i32) {
+        let Delta { dx, dy } = self.ip.delta;
+        let (mut cx, mut cy) = (x, y);
+        let mut in_comment = false;
+        // Twice round the path settles whether a lone `;` starts a comment.
+        for _ in 0..2 * self.world.stored() + 2 {
+            match self.world.get_cell(cx, cy) {
+                SEMICOLON => in_comment = !in_comment,
+                SPACE => {}
+                _ if in_comment => {}
+                _ => return (cx, cy),
+            }
+            match self.world.next_stored(cx, cy, dx, dy) {
+                Some(next) => (cx, cy) = next,
+                None => break,
+            }
+        }
+        (x, y)
+    }
+
+    /// Moves the IP `n` cells along its delta, backwards when `n` is negative.
+    fn jump(&mut self, n: i32) {
+        if n < 0 {
+            self.ip.delta = self.ip.delta.reverse();
+            self.jump(n.saturating_neg());
+            self.ip.delta = self.ip.delta.reverse();
+            return;
+        }
+        let mut n = n as usize;
+        if n <= self.skip_budget() {
+            for _ in 0..n {
+                self.forward();
+            }
+            return;
+        }
+        // After the first move the IP is on a cycle; once it comes round to
+        // where it started, only the remainder of the jump is left to walk.
+        self.forward();
+        n -= 1;
+        let start = (self.ip.x, self.ip.y);
+        let mut walked = 0;
+        while walked < n {
+            self.forward();
+            walked += 1;
+            if (self.ip.x, self.ip.y) == start {
+                n = walked + (n - walked) % walked;
+            }
+        }
+    }
+
+    /// Executes the next instruction along the IP's path `n` times. Each
+    /// repetition after the first counts as a step, and the limits are
+    /// checked before each one runs.
+    fn iterate(&mut self, n: i32) -> std::result::Result<Step, ErrorKind> {
+        let Delta { dx, dy } = self.ip.delta;
+        let (x, y) = self.world.next_position(self.ip.x, self.ip.y, dx, dy);
+        let (x, y) = self.skip_markers(x, y);
+        if n <= 0 {
+            // Land on the instruction so that it is skipped over.
+            self.ip.x = x;
+            self.ip.y = y;
+            return Ok(Step::Continued);
+        }
+        let start = (self.ip.x, self.ip.y);
+        let opcode = self.world.get_cell(x, y);
+        for i in 0..n {
+            if i > 0 {
+                self.steps += 1;
+                self.check_limits()?;
+            }
+            let step = self.instruction(opcode)?;
+            self.check_stack(self.ip.stack.len())?;
+            if step != Step::Continued {
+                return Ok(step);
+            }
+        }
+        if (self.ip.x, self.ip.y) == start {
+            self.ip.x = x;
+            self.ip.y = y;
+        }
+        Ok(Step::Continued)
+    }
+
+    fn split(&mut self) {
+        let mut child = self.ip.clone();
+        child.id = self.next_ip_id;
+        self.next_ip_id += 1;
+        child.delta = child.delta.reverse();
+        let Delta { dx, dy } = child.delta;
+        (child.x, child.y) = self.world.next_position(child.x, child.y, dx, dy);
+        // The child runs ahead of its parent from the next tick on.
+        self.queue.push_back(child);
+    }
+
+    /// `(` with `load`, `)` without.
+    fn fingerprint(&mut self, load: bool) {
+        let n = self.ip.stack.pop().unwrap_or_default().to_i32();
+        let mut id = 0_i32;
+        // Once the stack is empty each pop shifts in a zero byte, and four of
+        // those clear the ID, so there is no point popping further.
+        let n = usize::try_from(n).unwrap_or(0).min(self.ip.stack.len() + 4);
+        for _ in 0..n {
+            let cell = self.ip.stack.pop().unwrap_or_default().to_i32();
+            id = id.wrapping_mul(256).wrapping_add(cell);
+        }
+        let Some(index) = self.fingerprints.iter().rposition(|f| f.id() == id) else {
+            self.ip.delta = self.ip.delta.reverse();
+            return;
+        };
+        for c in self.fingerprints[index].instructions().chars() {
+            if let Some(semantics) = letter(c).map(|i| &mut self.ip.semantics[i]) {
+                if load {
+                    semantics.push(index);
+                } else {
+                    semantics.pop();
+                }
+            }
+        }
+        if load {
+            self.ip.stack.push(C::from_i32(id));
+            self.ip.stack.push(C::from_i32(1));
+        }
+    }
+
+    fn begin_block(&mut self) -> std::result::Result<(), ErrorKind> {
+        let n = self.ip.stack.pop().unwrap_or_default().to_i32();
+        let mut toss = Vec::new();
+        if n > 0 {
+            let n = n as usize;
+            self.check_stack(n)?;
+            let kept = self.ip.stack.len().saturating_sub(n);
+            toss.resize(n.saturating_sub(self.ip.stack.len()), C::default());
+            toss.extend(self.ip.stack.drain(kept..));
+        } else {
+            let zeros = self.ip.stack.len() + n.unsigned_abs() as usize;
+            self.check_stack(zeros)?;
+            self.ip.stack.resize(zeros, C::default());
+        }
+        let mut soss = std::mem::replace(&mut self.ip.stack, toss);
+        Self::push_vector(&mut soss, self.ip.storage_offset);
+        self.ip.stack_stack.push(soss);
+        let Delta { dx, dy } = self.ip.delta;
+        self.ip.storage_offset = self.world.next_position(self.ip.x, self.ip.y, dx, dy);
+        Ok(())
+    }
+
+    fn end_block(&mut self) -> std::result::Result<(), ErrorKind> {
+        let Some(mut soss) = self.ip.stack_stack.pop() else {
+            self.ip.delta = self.ip.delta.reverse();
+            return Ok(());
+        };
+        let n = self.ip.stack.pop().unwrap_or_default().to_i32();
+        let y = soss.pop().unwrap_or_default();
+        let x = soss.pop().unwrap_or_default();
+        self.ip.storage_offset = (x.to_i32(), y.to_i32());
+        if n > 0 {
+            let n = n as usize;
+            self.check_stack(soss.len() + n)?;
+            soss.resize(
+                soss.len() + n.saturating_sub(self.ip.stack.len()),
+                C::default(),
+            );
+            let kept = self.ip.stack.len().saturating_sub(n);
+            soss.extend(self.ip.stack.drain(kept..));
+        } else {
+            let kept = soss.len().saturating_sub(n.unsigned_abs() as usize);
+            soss.truncate(kept);
+        }
+        self.ip.stack = soss;
+        Ok(())
+    }
+
+    fn under(&mut self) -> std::result::Result<(), ErrorKind> {
+        let Some(soss_len) = self.ip.stack_stack.last().map(Vec::len) else {
+            self.ip.delta = self.ip.delta.reverse();
+            return Ok(());
+        };
+        let n = self.ip.stack.pop().unwrap_or_default().to_i32();
+        let grown = if n > 0 { self.ip.stack.len() } else { soss_len };
+        self.check_stack(grown + n.unsigned_abs() as usize)?;
+        let soss = self.ip.stack_stack.last_mut().expect("checked above");
+        if n > 0 {
+            for _ in 0..n {
+                self.ip.stack.push(soss.pop().unwrap_or_default());
+            }
+        } else {
+            for _ in 0..n.unsigned_abs() {
+                soss.push(self.ip.stack.pop().unwrap_or_default());
+            }
+        }
+        Ok(())
+    }
+
+    /// The `y` report, top of stack first.
+    fn system_info(&self) -> Vec<i32> {
+        let version = env!("CARGO_PKG_VERSION_MAJOR").parse::<i32>().unwrap_or(0) * 100
+            + env!("CARGO_PKG_VERSION_MINOR").parse::<i32>().unwrap_or(0) * 10
+            + env!("CARGO_PKG_VERSION_PATCH").parse::<i32>().unwrap_or(0);
+        let (least, greatest) = self.world.bounds();
+        let Delta { dx, dy } = self.ip.delta;
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+        let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+        let time = seconds.rem_euclid(86_400) as i32;
+        // Vectors read top first, so y comes before x.
+        let mut info = vec![
+            1,           // t, but no i, o or =
+            C::BYTES,    // bytes per cell
+            0x4246_5253, // handprint "BFRS"
+            version,
+            0, // no = paradigm
+            std::path::MAIN_SEPARATOR as i32,
+            2, // dimensions
+            self.ip.id,
+            0, // team
+            self.ip.y,
+            self.ip.x,
+            dy,
+            dx,
+            self.ip.storage_offset.1,
+            self.ip.storage_offset.0,
+            least.1,
+            least.0,
+            greatest.1.wrapping_sub(least.1),
+            greatest.0.wrapping_sub(least.0),
+            (year - 1900) * 256 * 256 + month * 256 + day,
+            time / 3600 * 256 * 256 + time / 60 % 60 * 256 + time % 60,
+            self.ip.stack_stack.len() as i32 + 1,
+            self.ip.stack.len() as i32,
+        ];
+        info.extend(
+            self.ip
+                .stack_stack
+                .iter()
+                .rev()
+                .map(|stack| stack.len() as i32),
+        );
+        // No command line arguments are passed on to the program.
+        info.extend([0, 0]);
+        for (key, value) in env::vars() {
+            info.extend(format!("{}={}", key, value).chars().map(|c| c as i32));
+            info.push(0);
+        }
+        info.push(0);
+        info
+    }
+
+    /// Funge-98 instructions that Befunge-93 does not have.
+    fn instruction_98(&mut self, opcode: i32) -> std::result::Result<Step, ErrorKind> {
+        match opcode_char(opcode) {
+            // Push the hexadecimal digit
+            c @ 'a'..='f' => self.ip.stack.push(C::from_i32(c as i32 - 'a' as i32 + 10)),
+            '[' => self.ip.delta = self.ip.delta.turn_left(),
+            ']' => self.ip.delta = self.ip.delta.turn_right(),
+            'r' => self.ip.delta = self.ip.delta.reverse(),
+            // Absolute delta: pop dy and dx and fly with them
+            'x' => {
+                let (dx, dy) = self.pop_vector();
+                self.ip.delta = Delta::new(dx, dy);
+            }
+            // Jump: pop n and move n cells along the delta
+            'j' => {
+                let n = self.ip.stack.pop().unwrap_or_default().to_i32();
+                self.jump(n);
+            }
+            // Iterate: pop n and execute the next instruction n times
+            'k' => {
+                let n = self.ip.stack.pop().unwrap_or_default().to_i32();
+                return self.iterate(n);
+            }
+            'n' => self.ip.stack.clear(),
+            // Fetch character: push the next cell and skip over it
+            '\'' => {
+                let value = self.cell_ahead(self.ip.x, self.ip.y);
+                self.ip.stack.push(C::from_i32(value));
+                self.forward();
+            }
+            // Store character: pop a value into the next cell and skip over it
+            's' => {
+                let value = self.ip.stack.pop().unwrap_or_default();
+                let Delta { dx, dy } = self.ip.delta;
+                let (x, y) = self.world.next_position(self.ip.x, self.ip.y, dx, dy);
+                self.world.set_cell(x, y, value.to_i32());
+                self.forward();
+            }
+            // Compare: pop b and a, turn left if a<b and right if a>b
+            'w' => {
+                let b = self.ip.stack.pop().unwrap_or_default();
+                let a = self.ip.stack.pop().unwrap_or_default();
+                if a < b {
+                    self.ip.delta = self.ip.delta.turn_left();
+                } else if a > b {
+                    self.ip.delta = self.ip.delta.turn_right();
+                }
+            }
+            'z' => {}
+            // Split: start a new IP going the opposite way
+            't' => self.split(),
+            '{' => self.begin_block()?,
+            '}' => self.end_block()?,
+            'u' => self.under()?,
+            // Get system info: pop n; push everything, or only the nth cell
+            'y' => {
+                let n = self.ip.stack.pop().unwrap_or_default().to_i32();
+                let below = self.ip.stack.len();
+                let info = self.system_info();
+                self.ip
+                    .stack
+                    .extend(info.into_iter().rev().map(C::from_i32));
+                if n > 0 {
+                    let picked = self
+                        .ip
+                        .stack
+                        .len()
+                        .checked_sub(n as usize)
+                        .map(|i| self.ip.stack[i].clone())
+                        .unwrap_or_default();
+                    self.ip.stack.truncate(below);
+                    self.ip.stack.push(picked);
+                }
+            }
+            // Quit: pop an exit code and end the program
+            'q' => {
+                self.exit_code = Some(self.ip.stack.pop().unwrap_or_default().to_i32());
+                return Ok(Step::Halted);
+            }
+            '(' => self.fingerprint(true),
+            ')' => self.fingerprint(false),
+            // Run whichever loaded fingerprint defines the letter
+            c @ 'A'..='Z' => match letter(c).and_then(|i| self.ip.semantics[i].last()) {
+                Some(&index) => {
+                    let fingerprint = Rc::clone(&self.fingerprints[index]);
+                    fingerprint.execute(c, &mut Context { befunge: self })?;
+                }
+                None => self.ip.delta = self.ip.delta.reverse(),
+            },
+            // Everything else, including i, o, =, h, l and m, reflects
+            _ => self.ip.delta = self.ip.delta.reverse(),
+        }
+        Ok(Step::Continued)
+    }
+
+    fn forward(&mut self) {
+        let Delta { dx, dy } = self.ip.delta;
+        let (x, y) = self.world.next_position(self.ip.x, self.ip.y, dx, dy);
+        self.ip.x = x;
+        self.ip.y = y;
+    }
+}
+
+/// What a [`Fingerprint`] instruction can see and change: the IP running it,
+/// that IP's stack, Funge-Space and the program's input and output.
+pub struct Context<'a, 'w, 'io, C: Cell> {
+    befunge: &'a mut Befunge<'w, 'io, C>,
+}
+
+impl<C: Cell> Context<'_, '_, '_, C> {
+    /// Pops a value; an empty stack gives zero.
+    pub fn pop(&mut self) -> C {
+        self.befunge.ip.stack.pop().unwrap_or_default()
+    }
+
+    pub fn push(&mut self, value: C) {
+        self.befunge.ip.stack.push(value);
+    }
+
+    /// Pops `y`, then `x`.
+    pub fn pop_vector(&mut self) -> (i32, i32) {
+        self.befunge.pop_vector()
+    }
+
+    pub fn push_vector(&mut self, vector: (i32, i32)) {
+        Befunge::push_vector(&mut self.befunge.ip.stack, vector);
+    }
+
+    /// Pops a `0gnirts`: characters up to a zero, first character on top.
+    pub fn pop_string(&mut self) -> String {
+        let mut string = String::new();
+        loop {
+            match self.pop().to_i32() {
+                0 => return string,
+                value => string.push(opcode_char(value)),
+            }
+        }
+    }
+
+    pub fn push_string(&mut self, string: &str) {
+        self.push(C::default());
+        for c in string.chars().rev() {
+            self.push(C::from_i32(c as i32));
+        }
+    }
+
+    pub fn stack(&self) -> &[C] {
+        &self.befunge.ip.stack
+    }
+
+    pub fn world(&self) -> &World {
+        self.befunge.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        self.befunge.world
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.befunge.config
+    }
+
+    pub fn position(&self) -> (i32, i32) {
+        (self.befunge.ip.x, self.befunge.ip.y)
+    }
+
+    /// Moves the IP; it still advances by its delta once the instruction is done.
+    pub fn set_position(&mut self, x: i32, y: i32) {
+        self.befunge.ip.x = x;
+        self.befunge.ip.y = y;
+    }
+
+    pub fn delta(&self) -> Delta {
+        self.befunge.ip.delta
+    }
+
+    pub fn set_delta(&mut self, delta: Delta) {
+        self.befunge.ip.delta = delta;
+    }
+
+    /// The origin that `g` and `p` coordinates are relative to.
+    pub fn storage_offset(&self) -> (i32, i32) {
+        self.befunge.ip.storage_offset
+    }
+
+    /// A number in `0..n` from the interpreter's
+    /// [`DirectionSource`](crate::DirectionSource), so that seeds, scripts and
+    /// snapshots cover it as they cover `?`; `None` once the source has run out.
+    pub fn random_below(&mut self, n: u32) -> Option<u32> {
+        self.befunge.random.next_below(n)
+    }
+
+    pub fn reflect(&mut self) {
+        self.befunge.ip.delta = self.befunge.ip.delta.reverse();
+    }
+
+    /// Moves the IP one step along its delta, as `#` does.
+    pub fn forward(&mut self) {
+        self.befunge.forward();
+    }
+
+    /// Writes to the program's output, counting towards
+    /// [`Config::max_output`].
+    pub fn output(&mut self, text: &str) -> std::result::Result<(), ErrorKind> {
+        self.befunge.emit(text)
+    }
+
+    /// Reads a line of input without its line ending; empty at end of input.
+    pub fn read_line(&mut self) -> io::Result<String> {
+        let started = Instant::now();
+        let mut line = String::new();
+        self.befunge.input().read_line(&mut line)?;
+        self.befunge.record_input(line.as_bytes(), started);
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Ok(line)
+    }
+
+    /// The raw input, for reads that [`Context::read_line`] does not cover.
+    /// What is consumed from it is recorded like the input of `&` and `~`.
+    pub fn input(&mut self) -> Box<dyn BufRead + '_> {
+        Box::new(RecordedInput {
+            befunge: self.befunge,
+        })
+    }
+}
+
+/// The input as [`Context::input`] hands it out: bytes are recorded as
+/// they are consumed, so that observers, [`Befunge::step_back`] and
+/// snapshots account for them.
+struct RecordedInput<'a, 'w, 'io, C: Cell> {
+    befunge: &'a mut Befunge<'w, 'io, C>,
+}
+
+impl<C: Cell> Read for RecordedInput<'_, '_, '_, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<C: Cell> RecordedInput<'_, '_, '_, C> {
+    /// The bytes ready to be consumed: first anything handed back by
+    /// [`Befunge::step_back`], then the reader's buffer.
+    fn buffered(&mut self) -> io::Result<&[u8]> {
+        let befunge = &mut *self.befunge;
+        if befunge.unread.is_empty() {
+            befunge.read.fill_buf()
+        } else {
+            Ok(befunge.unread.as_slices().0)
+        }
+    }
+}
+
+impl<C: Cell> BufRead for RecordedInput<'_, '_, '_, C> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Filling may wait on the reader, which counts as I/O time; asking
+        // again only hands back the bytes it already holds.
+        let started = Instant::now();
+        self.buffered()?;
+        self.befunge.io_time += started.elapsed();
+        self.buffered()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let consumed = match self.buffered() {
+            Ok(available) => available[..amt.min(available.len())].to_vec(),
+            Err(_) => Vec::new(),
+        };
+        if self.befunge.unread.is_empty() {
+            self.befunge.read.consume(consumed.len());
+        } else {
+            self.befunge.unread.drain(..consumed.len());
+        }
+        self.befunge.record_input(&consumed, Instant::now());
+    }
+}
+
+#[cfg(test)]
+// Some tests compare cells with `'1' as u8`.
+#[allow(clippy::char_lit_as_u8)]
+mod tests {
+
+    use super::{Befunge, Config, Delta, Direction, ErrorKind, Mode, Step, World};
+    use crate::config::Overflow;
+    use crate::observer::{Event, Observer};
+    use crate::random::{DirectionSource, ScriptedRandom, SeededRandom};
+    use crate::world::Standard;
+    use num_bigint::BigInt;
+    use std::error::Error;
+    use std::io::{self, BufRead, BufReader, Read};
+    use std::time::Duration;
+
+    #[test]
+    fn hello_world_program1() -> Result<(), Box<dyn Error>> {
+        let src =
+            ">              v\nv  ,,,,,\"Hello\"<\n>48*,          v\nv,,,,,,\"World!\"<\n>25*,@";
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string(src)?;
+        {
+            let mut befunge = Befunge::new(
+                &mut world,
+                0,
+                0,
+                Direction::Right,
+                &mut buf_read,
+                &mut write,
+            );
+            befunge.run()?;
+        }
+        assert_eq!(String::from_utf8_lossy(&write[..]), "Hello World!\n");
+        Ok(())
+    }
+
+    #[test]
+    fn hello_world_program2() -> Result<(), Box<dyn Error>> {
+        let src = "v @_       v\n>0\"!dlroW\"v\nv  :#     <\n>\" ,olleH\" v\n   ^       <";
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string(src)?;
+        {
+            let mut befunge = Befunge::new(
+                &mut world,
+                0,
+                0,
+                Direction::Right,
+                &mut buf_read,
+                &mut write,
+            );
+            befunge.run()?;
+        }
+        assert_eq!(String::from_utf8_lossy(&write[..]), "Hello, World!");
+        Ok(())
+    }
+
+    #[test]
+    fn factorial_of_5() -> Result<(), Box<dyn Error>> {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("5 100p:v\nv *g00:_00g.@\n>00p1-:^")?;
+        {
+            let mut befunge = Befunge::new(
+                &mut world,
+                0,
+                0,
+                Direction::Right,
+                &mut buf_read,
+                &mut write,
+            );
+            befunge.run()?;
+            assert_eq!(befunge.ip.stack, [0]);
+        }
+        assert_eq!(String::from_utf8_lossy(&write[..]), "120 ");
+        Ok(())
+    }
+
+    #[test]
+    fn control_commands() -> Result<(), Box<dyn Error>> {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("^    _| 3\n0#@07|5 >\n<@?@# _ v\n1 @     2")?;
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        befunge.run()?;
+        assert_eq!(befunge.ip.stack, [1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn literal_commands() -> Result<(), Box<dyn Error>> {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string(r#"01234"   "56789@"#)?;
+        {
+            let mut befunge = Befunge::new(
+                &mut world,
+                0,
+                0,
+                Direction::Right,
+                &mut buf_read,
+                &mut write,
+            );
+            befunge.run()?;
+            assert_eq!(
+                befunge.ip.stack,
+                [0, 1, 2, 3, 4, 0x20, 0x20, 0x20, 5, 6, 7, 8, 9]
+            );
+        }
+        assert!(write.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn arithmetic_commands() -> Result<(), Box<dyn Error>> {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("09-9*9*98+92-73*92/83%@")?;
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        befunge.run()?;
+        assert_eq!(befunge.ip.stack, [-729, 17, 7, 21, 4, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn divide_by_zero_is_a_runtime_error() {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("10/.@").expect("test program should parse");
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        let err = befunge.run().expect_err("division by zero should error");
+        assert_eq!(err.to_string(), "division by zero at (2, 0)");
+        let location = err.location().expect("runtime errors carry a location");
+        assert_eq!(location.delta, Direction::Right.delta());
+        assert_eq!(location.opcode, b'/');
+        assert_eq!(location.stack, [1, 0]);
+        assert!(write.is_empty());
+    }
+
+    #[test]
+    fn modulo_by_zero_is_a_runtime_error() {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("10%.@").expect("test program should parse");
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        let err = befunge.run().expect_err("modulo by zero should error");
+        assert_eq!(err.to_string(), "modulo by zero at (2, 0)");
+        let location = err.location().expect("runtime errors carry a location");
+        assert_eq!(location.delta, Direction::Right.delta());
+        assert_eq!(location.opcode, b'%');
+        assert_eq!(location.stack, [1, 0]);
+        assert!(write.is_empty());
+    }
+
+    #[test]
+    fn malformed_integer_input_is_a_runtime_error() {
+        let read = Vec::from("12x\n".as_bytes());
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string(" &.@").expect("test program should parse");
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        let err = befunge.run().expect_err("malformed input should error");
+        match err.kind() {
+            ErrorKind::InvalidInteger(token) => assert_eq!(token, "12x"),
+            kind => panic!("unexpected error kind: {:?}", kind),
+        }
+        assert_eq!(err.location().map(|l| (l.x, l.y)), Some((1, 0)));
+    }
+
+    #[test]
+    fn logical_operation_commands() -> Result<(), Box<dyn Error>> {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("8!0!12`21`@")?;
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        befunge.run()?;
+        assert_eq!(befunge.ip.stack, [0, 1, 0, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn stack_operation_commands() -> Result<(), Box<dyn Error>> {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string(r#"73:6\$@"#)?;
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        befunge.run()?;
+        assert_eq!(befunge.ip.stack, [7, 3, 6]);
+        Ok(())
+    }
+
+    #[test]
+    fn memory_operation_commands() -> Result<(), Box<dyn Error>> {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("00g1+70p@")?;
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        befunge.run()?;
+        assert_eq!(befunge.ip.stack, []);
+        assert_eq!(world.get(7, 0), '1' as u8);
+        Ok(())
+    }
+
+    #[test]
+    fn io_commands() -> Result<(), Box<dyn Error>> {
+        let read = Vec::from("-205\n7\n".as_bytes());
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("&~.,@")?;
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        befunge.run()?;
+        assert_eq!(befunge.ip.stack, []);
+        assert_eq!(String::from_utf8_lossy(&write[..]), "55 3");
+        Ok(())
+    }
+
+    #[test]
+    fn numeric_input_commands_are_whitespace_delimited() -> Result<(), Box<dyn Error>> {
+        let read = Vec::from("12 34\n".as_bytes());
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("&&..@")?;
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        befunge.run()?;
+        assert_eq!(befunge.ip.stack, []);
+        assert_eq!(String::from_utf8_lossy(&write[..]), "34 12 ");
+        Ok(())
+    }
+
+    #[test]
+    fn step_executes_one_instruction_at_a_time() -> Result<(), Box<dyn Error>> {
+        let read = Vec::new();
+        let mut buf_read = BufReader::new(&read[..]);
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("12v\n  \"\n  \"\n  @")?;
+        let mut befunge = Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        );
+        assert_eq!(befunge.step()?, Step::Continued);
+        assert_eq!(befunge.stack(), [1]);
+        assert_eq!(befunge.position(), (1, 0));
+        assert_eq!(befunge.step()?, Step::Continued);
+        assert_eq!(befunge.step()?, Step::Continued);
+        assert_eq!(befunge.direction(), Some(Direction::Down));
+        assert_eq!(befunge.position(), (2, 1));
+        assert_eq!(befunge.step()?, Step::Continued);
+        assert_eq!(befunge.mode(), Mode::AsciiPush);
+        assert_eq!(befunge.step()?, Step::Continued);
+        assert_eq!(befunge.mode(), Mode::Interpret);
+        assert_eq!(befunge.step()?, Step::Halted);
+        assert_eq!(befunge.step()?, Step::Halted);
+        assert_eq!(befunge.stack(), [1, 2]);
+        assert_eq!(befunge.position(), (2, 3));
+        Ok(())
+    }
+
+    struct WouldBlockOnce {
+        blocked: bool,
+        data: &'static [u8],
+    }
+
+    impl Read for WouldBlockOnce {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = {
+                let available = self.fill_buf()?;
+                let n = available.len().min(buf.len());
+                buf[..n].copy_from_slice(&available[..n]);
+                n
+            };
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl BufRead for WouldBlockOnce {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            if !self.blocked {
+                self.blocked = true;
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            Ok(self.data)
+        }
+        fn consume(&mut self, amt: usize) {
+            self.data = &self.data[amt..];
+        }
+    }
+
+    #[test]
+    fn step_reports_waiting_for_input_without_moving() -> Result<(), Box<dyn Error>> {
+        let mut read = WouldBlockOnce {
+            blocked: false,
+            data: b"A",
+        };
+        let mut write = Vec::new();
+        let mut world = World::from_source_string("~@")?;
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        assert_eq!(befunge.step()?, Step::WaitingForInput);
+        assert_eq!(befunge.position(), (0, 0));
+        assert_eq!(befunge.step()?, Step::Continued);
+        assert_eq!(befunge.stack(), [65]);
+        assert_eq!(befunge.step()?, Step::Halted);
+        Ok(())
+    }
+
+    fn run_with_overflow(
+        src: &str,
+        input: &str,
+        overflow: Overflow,
+    ) -> Result<String, crate::error::Error> {
+        let mut buf_read = BufReader::new(input.as_bytes());
+        let mut write = Vec::new();
+        let mut world = World::from_source_string(src)?;
+        Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        )
+        .with_config(Config::default().overflow(overflow))
+        .run()?;
+        Ok(String::from_utf8_lossy(&write).into_owned())
+    }
+
+    #[test]
+    fn wrapping_overflow_wraps_around() -> Result<(), Box<dyn Error>> {
+        let overflow = Overflow::Wrapping;
+        assert_eq!(
+            run_with_overflow("&1+.@", "2147483647", overflow)?,
+            "-2147483648 "
+        );
+        assert_eq!(
+            run_with_overflow("&1-.@", "-2147483648", overflow)?,
+            "2147483647 "
+        );
+        assert_eq!(run_with_overflow("&2*.@", "2147483647", overflow)?, "-2 ");
+        assert_eq!(
+            run_with_overflow("&&/.@", "-2147483648 -1", overflow)?,
+            "-2147483648 "
+        );
+        assert_eq!(
+            run_with_overflow("&&%.@", "-2147483648 -1", overflow)?,
+            "0 "
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn saturating_overflow_clamps() -> Result<(), Box<dyn Error>> {
+        let overflow = Overflow::Saturating;
+        assert_eq!(
+            run_with_overflow("&1+.@", "2147483647", overflow)?,
+            "2147483647 "
+        );
+        assert_eq!(
+            run_with_overflow("&1-.@", "-2147483648", overflow)?,
+            "-2147483648 "
+        );
+        assert_eq!(
+            run_with_overflow("&2*.@", "-2147483647", overflow)?,
+            "-2147483648 "
+        );
+        assert_eq!(
+            run_with_overflow("&&/.@", "-2147483648 -1", overflow)?,
+            "2147483647 "
+        );
+        assert_eq!(
+            run_with_overflow("&&%.@", "-2147483648 -1", overflow)?,
+            "0 "
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn checked_overflow_is_a_runtime_error() -> Result<(), Box<dyn Error>> {
+        let overflow = Overflow::Checked;
+        for (src, input) in [
+            ("&1+.@", "2147483647"),
+            ("&1-.@", "-2147483648"),
+            ("&2*.@", "2147483647"),
+            ("&&/.@", "-2147483648 -1"),
+            ("&&%.@", "-2147483648 -1"),
+        ] {
+            let err = run_with_overflow(src, input, overflow).expect_err(src);
+            assert!(
+                matches!(err.kind(), ErrorKind::ArithmeticOverflow),
+                "{}",
+                src
             );
-            befunge.run()?;
         }
-        assert_eq!(String::from_utf8_lossy(&write[..]), "Hello World!\n");
+        assert_eq!(run_with_overflow("&1+.@", "41", overflow)?, "42 ");
         Ok(())
     }
 
+    fn run_with_config(
+        src: &str,
+        input: &str,
+        config: Config,
+    ) -> Result<String, crate::error::Error> {
+        let mut buf_read = BufReader::new(input.as_bytes());
+        let mut write = Vec::new();
+        let mut world = World::from_source_string(src)?;
+        Befunge::new(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+        )
+        .with_config(config)
+        .run()?;
+        Ok(String::from_utf8_lossy(&write).into_owned())
+    }
+
+    const FACTORIAL: &str = "&>:1-:v v *_$.@\n ^    _$>\\:^";
+
     #[test]
-    fn hello_world_program2() -> Result<(), Box<dyn Error>> {
-        let src = "v @_       v\n>0\"!dlroW\"v\nv  :#     <\n>\" ,olleH\" v\n   ^       <";
-        let read = Vec::new();
+    fn i64_cells_hold_results_past_32_bits() -> Result<(), Box<dyn Error>> {
+        let read = Vec::from("20\n".as_bytes());
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string(src)?;
-        {
-            let mut befunge = Befunge::new(
-                &mut world,
-                0,
-                0,
-                Direction::Right,
-                &mut buf_read,
-                &mut write,
-            );
-            befunge.run()?;
-        }
-        assert_eq!(String::from_utf8_lossy(&write[..]), "Hello, World!");
+        let mut world = World::from_source_string(FACTORIAL)?;
+        Befunge::<i64>::with_stack(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+            Vec::new(),
+        )
+        .with_config(Config::default().overflow(Overflow::Checked))
+        .run()?;
+        assert_eq!(String::from_utf8_lossy(&write[..]), "2432902008176640000 ");
         Ok(())
     }
 
     #[test]
-    fn factorial_of_5() -> Result<(), Box<dyn Error>> {
-        let read = Vec::new();
+    fn big_integer_cells_never_overflow() -> Result<(), Box<dyn Error>> {
+        let read = Vec::from("30\n".as_bytes());
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string("5 100p:v\nv *g00:_00g.@\n>00p1-:^")?;
-        {
-            let mut befunge = Befunge::new(
-                &mut world,
-                0,
-                0,
-                Direction::Right,
-                &mut buf_read,
-                &mut write,
-            );
-            befunge.run()?;
-            assert_eq!(befunge.stack, [0]);
-        }
-        assert_eq!(String::from_utf8_lossy(&write[..]), "120 ");
+        let mut world = World::from_source_string(FACTORIAL)?;
+        Befunge::<BigInt>::with_stack(
+            &mut world,
+            0,
+            0,
+            Direction::Right,
+            &mut buf_read,
+            &mut write,
+            Vec::new(),
+        )
+        .with_config(Config::default().overflow(Overflow::Checked))
+        .run()?;
+        assert_eq!(
+            String::from_utf8_lossy(&write[..]),
+            "265252859812191058636308480000000 "
+        );
         Ok(())
     }
 
     #[test]
-    fn control_commands() -> Result<(), Box<dyn Error>> {
+    fn wide_values_are_stored_as_their_low_byte() -> Result<(), Box<dyn Error>> {
         let read = Vec::new();
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string("^    _| 3\n0#@07|5 >\n<@?@# _ v\n1 @     2")?;
-        let mut befunge = Befunge::new(
+        let mut world = World::from_source_string("@")?;
+        let stack = vec![(1_i64 << 40) + 321, 0, 1];
+        let mut befunge = Befunge::with_stack(
             &mut world,
             0,
             0,
             Direction::Right,
             &mut buf_read,
             &mut write,
+            stack,
         );
+        befunge.world.set(0, 0, b'p');
+        befunge.world.set(1, 0, b'0');
+        befunge.world.set(2, 0, b'1');
+        befunge.world.set(3, 0, b'g');
+        befunge.world.set(4, 0, b'@');
         befunge.run()?;
-        assert_eq!(befunge.stack, [1, 2, 3]);
+        assert_eq!(befunge.stack(), [65]);
         Ok(())
     }
 
     #[test]
-    fn literal_commands() -> Result<(), Box<dyn Error>> {
-        let read = Vec::new();
-        let mut buf_read = BufReader::new(&read[..]);
-        let mut write = Vec::new();
-        let mut world = World::from_source_string(r#"01234"   "56789@"#)?;
-        {
+    fn seeded_random_directions_are_reproducible() -> Result<(), Box<dyn Error>> {
+        for seed in 0..16 {
+            let read = Vec::new();
+            let mut buf_read = BufReader::new(&read[..]);
+            let mut write = Vec::new();
+            let mut world = World::from_source_string("?")?;
             let mut befunge = Befunge::new(
                 &mut world,
                 0,
@@ -391,99 +2431,144 @@ mod tests {
                 Direction::Right,
                 &mut buf_read,
                 &mut write,
-            );
-            befunge.run()?;
-            assert_eq!(
-                befunge.stack,
-                [0, 1, 2, 3, 4, 0x20, 0x20, 0x20, 5, 6, 7, 8, 9]
-            );
+            )
+            .with_random(Box::new(SeededRandom::new(seed)));
+            befunge.step()?;
+            let expected = SeededRandom::new(seed).next_direction();
+            assert_eq!(befunge.direction(), expected);
         }
-        assert!(write.is_empty());
         Ok(())
     }
 
     #[test]
-    fn arithmetic_commands() -> Result<(), Box<dyn Error>> {
+    fn scripted_random_directions_are_followed_in_order() -> Result<(), Box<dyn Error>> {
         let read = Vec::new();
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string("09-9*9*98+92-73*92/83%@")?;
-        let mut befunge = Befunge::new(
+        let mut world = World::from_source_string("v\n>?@\n ?1.@")?;
+        let script: ScriptedRandom = "v>".parse()?;
+        Befunge::new(
             &mut world,
             0,
             0,
             Direction::Right,
             &mut buf_read,
             &mut write,
-        );
-        befunge.run()?;
-        assert_eq!(befunge.stack, [-729, 17, 7, 21, 4, 2]);
+        )
+        .with_random(Box::new(script))
+        .run()?;
+        assert_eq!(String::from_utf8_lossy(&write[..]), "1 ");
         Ok(())
     }
 
     #[test]
-    fn divide_by_zero_is_a_runtime_error() {
+    fn exhausted_script_is_a_runtime_error() {
         let read = Vec::new();
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string("10/.@").expect("test program should parse");
-        let mut befunge = Befunge::new(
+        let mut world = World::from_source_string("??@").expect("test program should parse");
+        let err = Befunge::new(
             &mut world,
             0,
             0,
             Direction::Right,
             &mut buf_read,
             &mut write,
+        )
+        .with_random(Box::new(ScriptedRandom::new(vec![Direction::Right])))
+        .run()
+        .expect_err("second '?' has no direction left");
+        assert!(matches!(err.kind(), ErrorKind::RandomExhausted));
+        assert_eq!(err.location().map(|l| (l.x, l.y)), Some((1, 0)));
+    }
+
+    #[test]
+    fn blank_program_stops_at_the_step_limit() {
+        let err = run_with_config("", "", Config::default().max_steps(1000))
+            .expect_err("blank programs never halt");
+        assert!(matches!(err.kind(), ErrorKind::StepLimitExceeded(1000)));
+    }
+
+    #[test]
+    fn step_limit_counts_the_halting_instruction() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            run_with_config("1.@", "", Config::default().max_steps(3))?,
+            "1 "
         );
-        let err = befunge.run().expect_err("division by zero should error");
-        assert_eq!(err.to_string(), "division by zero");
-        assert!(write.is_empty());
+        let err = run_with_config("1.@", "", Config::default().max_steps(2))
+            .expect_err("@ is the third step");
+        assert_eq!(err.location().map(|l| l.opcode), Some(b'@'));
+        Ok(())
     }
 
     #[test]
-    fn modulo_by_zero_is_a_runtime_error() {
+    fn blank_program_stops_at_the_timeout() {
+        let timeout = Duration::from_millis(10);
+        let err = run_with_config("", "", Config::default().timeout(timeout))
+            .expect_err("blank programs never halt");
+        assert!(matches!(err.kind(), ErrorKind::TimeLimitExceeded(t) if *t == timeout));
+    }
+
+    #[test]
+    fn output_beyond_the_cap_is_not_written() {
         let read = Vec::new();
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string("10%.@").expect("test program should parse");
-        let mut befunge = Befunge::new(
+        let mut world = World::from_source_string("99+>:.v\n   ^  <").expect("should parse");
+        let err = Befunge::new(
             &mut world,
             0,
             0,
             Direction::Right,
             &mut buf_read,
             &mut write,
-        );
-        let err = befunge.run().expect_err("modulo by zero should error");
-        assert_eq!(err.to_string(), "modulo by zero");
-        assert!(write.is_empty());
+        )
+        .with_config(Config::default().max_output(10))
+        .run()
+        .expect_err("the loop prints forever");
+        assert!(matches!(err.kind(), ErrorKind::OutputLimitExceeded(10)));
+        assert_eq!(String::from_utf8_lossy(&write[..]), "18 18 18 ");
     }
 
     #[test]
-    fn logical_operation_commands() -> Result<(), Box<dyn Error>> {
+    fn stack_growth_stops_at_the_stack_limit() {
+        let err = run_with_config(">1", "", Config::default().max_stack(64))
+            .expect_err("the loop pushes forever");
+        assert!(matches!(err.kind(), ErrorKind::StackLimitExceeded(64)));
+        assert_eq!(err.location().map(|l| l.stack.len()), Some(65));
+    }
+
+    fn run_98(src: &str) -> Result<(String, World), Box<dyn Error>> {
         let read = Vec::new();
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string("8!0!12`21`@")?;
-        let mut befunge = Befunge::new(
+        let mut world = World::from_source(src, Standard::Funge98)?;
+        Befunge::new(
             &mut world,
             0,
             0,
             Direction::Right,
             &mut buf_read,
             &mut write,
-        );
-        befunge.run()?;
-        assert_eq!(befunge.stack, [0, 1, 0, 1]);
+        )
+        .run()?;
+        Ok((String::from_utf8_lossy(&write).into_owned(), world))
+    }
+
+    #[test]
+    fn funge_98_space_wraps_lahey_style_past_80_columns() -> Result<(), Box<dyn Error>> {
+        let (output, _) = run_98(&format!("<@.{}1", " ".repeat(120)))?;
+        assert_eq!(output, "1 ");
         Ok(())
     }
 
     #[test]
-    fn stack_operation_commands() -> Result<(), Box<dyn Error>> {
+    fn funge_98_coordinates_can_be_negative() -> Result<(), Box<dyn Error>> {
         let read = Vec::new();
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string(r#"73:6\$@"#)?;
+        let mut world = World::from_source("<", Standard::Funge98)?;
+        world.set_cell(-1, 0, i32::from(b'@'));
         let mut befunge = Befunge::new(
             &mut world,
             0,
@@ -492,17 +2577,59 @@ mod tests {
             &mut buf_read,
             &mut write,
         );
-        befunge.run()?;
-        assert_eq!(befunge.stack, [7, 3, 6]);
+        befunge.step()?;
+        assert_eq!(befunge.coordinates(), (-1, 0));
+        assert_eq!(befunge.position(), (0, 0));
+        assert_eq!(befunge.step()?, Step::Halted);
         Ok(())
     }
 
     #[test]
-    fn memory_operation_commands() -> Result<(), Box<dyn Error>> {
+    fn funge_98_put_and_get_reach_beyond_the_torus() -> Result<(), Box<dyn Error>> {
+        let (output, world) = run_98("599*9*0p99*9*0g.@")?;
+        assert_eq!(output, "5 ");
+        assert_eq!(world.get_cell(729, 0), 5);
+        assert_eq!(world.bounds(), ((0, 0), (729, 0)));
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_reads_hex_digits_characters_and_skips_comments() -> Result<(), Box<dyn Error>> {
+        let (output, _) = run_98("a.'A,;8.;  f.@")?;
+        assert_eq!(output, "10 A15 ");
+        let (output, world) = run_98("'Ws .@")?;
+        assert_eq!(output, "0 ");
+        assert_eq!(world.get_cell(3, 0), i32::from(b'W'));
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_skips_far_gaps_at_once() -> Result<(), Box<dyn Error>> {
+        // `p` a hundred million cells away; walking the gap would take seconds.
+        let config = || Config::default().max_steps(100);
+        assert_eq!(run_98_with_config("'@55+:*:*:*0p", config())?, 13);
+        let src = "'@55+:*:*:*0p'\"55+:*:*:*1-0p\"";
+        assert_eq!(run_98_with_config(src, config())?, 30);
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_jumps_and_iterates() -> Result<(), Box<dyn Error>> {
+        let (output, _) = run_98("1j@3.@")?;
+        assert_eq!(output, "3 ");
+        let (output, _) = run_98("10k.3k1...@")?;
+        assert_eq!(output, "1 1 1 ");
+        // A jump far longer than the space goes round the same way.
+        let (output, _) = run_98("ff*j@5.@")?;
+        assert_eq!(output, "5 ");
+        Ok(())
+    }
+
+    fn run_98_with_config(src: &str, config: Config) -> Result<u64, crate::error::Error> {
         let read = Vec::new();
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string("00g1+70p@")?;
+        let mut world = World::from_source(src, Standard::Funge98)?;
         let mut befunge = Befunge::new(
             &mut world,
             0,
@@ -510,50 +2637,414 @@ mod tests {
             Direction::Right,
             &mut buf_read,
             &mut write,
-        );
+        )
+        .with_config(config);
         befunge.run()?;
-        assert_eq!(befunge.stack, []);
-        assert_eq!(world.get(7, 0), '1' as u8);
+        Ok(befunge.steps())
+    }
+
+    #[test]
+    fn funge_98_iterations_count_as_steps() -> Result<(), Box<dyn Error>> {
+        assert_eq!(run_98_with_config("3k1@", Config::default())?, 5);
+        // A repetition that halts ends the count within the limit.
+        assert_eq!(
+            run_98_with_config("9k@", Config::default().max_steps(2))?,
+            2
+        );
+        let err = run_98_with_config("3k1@", Config::default().max_steps(4))
+            .expect_err("the repetitions take the run past four steps");
+        assert!(matches!(err.kind(), ErrorKind::StepLimitExceeded(4)));
         Ok(())
     }
 
     #[test]
-    fn io_commands() -> Result<(), Box<dyn Error>> {
-        let read = Vec::from("-205\n7\n".as_bytes());
+    fn funge_98_huge_counts_stop_at_the_limits() {
+        // Pushes 11390625 copies of the 1.
+        let err = run_98_with_config("1ff*:*ff**k:@", Config::default().max_stack(10))
+            .expect_err("the copies overflow the stack");
+        assert!(matches!(err.kind(), ErrorKind::StackLimitExceeded(10)));
+        let err = run_98_with_config("1ff*:*ff**k$@", Config::default().max_steps(1000))
+            .expect_err("the repetitions overrun the steps");
+        assert!(matches!(err.kind(), ErrorKind::StepLimitExceeded(1000)));
+        // Each grows a stack by 569531250 values in one go.
+        for src in ["ff*:*ff**a5**{@", "0{ff*:*ff**a5**}@", "0{ff*:*ff**a5**u@"] {
+            let err = run_98_with_config(src, Config::default().max_stack(10)).expect_err(src);
+            assert!(
+                matches!(err.kind(), ErrorKind::StackLimitExceeded(10)),
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn funge_98_turns_and_reflects() -> Result<(), Box<dyn Error>> {
+        // `[` turns the IP up, wrapping it to the bottom of the column.
+        let (output, _) = run_98(">[\n @\n .\n 1")?;
+        assert_eq!(output, "1 ");
+        let (output, _) = run_98(">]\n 1\n .\n @")?;
+        assert_eq!(output, "1 ");
+        let (output, _) = run_98("1#.r@")?;
+        assert_eq!(output, "1 ");
+        let (output, _) = run_98("12w\n  @\n  .\n  1")?;
+        assert_eq!(output, "1 ");
+        let (output, _) = run_98("21w\n  1\n  .\n  @")?;
+        assert_eq!(output, "1 ");
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_divides_by_zero_to_zero_and_reflects_at_end_of_input() -> Result<(), Box<dyn Error>>
+    {
+        let (output, _) = run_98("10/.10%.@")?;
+        assert_eq!(output, "0 0 ");
+        let (output, _) = run_98("#@~1.@")?;
+        assert_eq!(output, "");
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_stack_stack_moves_values_and_offsets_storage() -> Result<(), Box<dyn Error>> {
+        let (output, _) = run_98("1232{..}.@")?;
+        assert_eq!(output, "3 2 1 ");
+        // `{` leaves the old storage offset on top of the second stack.
+        let (output, _) = run_98("12 0{3u...@")?;
+        assert_eq!(output, "2 0 0 ");
+        // Inside the block `p` is relative to the cell after `{`.
+        let (_, world) = run_98("0{'X00p}@")?;
+        assert_eq!(world.get_cell(2, 0), i32::from(b'X'));
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_reports_system_info() -> Result<(), Box<dyn Error>> {
+        let (output, _) = run_98("1y.2y.7y.@")?;
+        assert_eq!(output, "1 4 2 ");
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_quit_sets_the_exit_code() -> Result<(), Box<dyn Error>> {
+        let mut world = World::from_source("7q", Standard::Funge98)?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        assert_eq!(befunge.step()?, Step::Continued);
+        assert_eq!(befunge.step()?, Step::Halted);
+        assert_eq!(befunge.exit_code(), Some(7));
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_instructions_are_no_ops_in_befunge_93() {
+        assert_eq!(
+            run_with_config("1a;r[.@", "", Config::default()).unwrap(),
+            "1 "
+        );
+    }
+
+    #[test]
+    fn deltas_turn_and_reverse() {
+        let right = Direction::Right.delta();
+        assert_eq!(right.turn_left(), Direction::Up.delta());
+        assert_eq!(right.turn_right(), Direction::Down.delta());
+        assert_eq!(right.reverse(), Direction::Left.delta());
+        assert_eq!(Delta::new(2, 1).turn_left(), Delta::new(1, -2));
+        assert_eq!(Delta::new(2, 1).direction(), None);
+        assert_eq!(Delta::new(2, 1).to_string(), "by (2, 1)");
+    }
+
+    #[test]
+    fn diagonal_deltas_cross_the_torus() -> Result<(), Box<dyn Error>> {
+        let mut world = World::from_source_string("1\n .\n  @")?;
+        let read = Vec::new();
         let mut buf_read = BufReader::new(&read[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string("&~.,@")?;
-        let mut befunge = Befunge::new(
+        Befunge::new(
             &mut world,
             0,
             0,
-            Direction::Right,
+            Delta::new(1, 1),
             &mut buf_read,
             &mut write,
-        );
+        )
+        .run()?;
+        assert_eq!(String::from_utf8(write)?, "1 ");
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_flies_with_any_delta() -> Result<(), Box<dyn Error>> {
+        let (output, _) = run_98("20x@5@.@@")?;
+        assert_eq!(output, "5 ");
+        Ok(())
+    }
+
+    #[test]
+    fn funge_98_split_runs_ips_in_turn_until_the_last_one_stops() -> Result<(), Box<dyn Error>> {
+        let mut world = World::from_source("12t.@.", Standard::Funge98)?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        for _ in 0..3 {
+            befunge.step()?;
+        }
+        // The child, going left from `t`, runs first.
+        assert_eq!(befunge.ip_ids(), [1, 0]);
+        assert_eq!(befunge.ip_id(), 1);
+        assert_eq!(befunge.position(), (1, 0));
+        assert_eq!(befunge.stack(), [1, 2]);
         befunge.run()?;
-        assert_eq!(befunge.stack, []);
-        assert_eq!(String::from_utf8_lossy(&write[..]), "55 3");
+        assert_eq!(befunge.ip_ids(), [1]);
+        assert_eq!(befunge.steps(), 9);
+        drop(befunge);
+        assert_eq!(String::from_utf8(write)?, "2 1 ");
         Ok(())
     }
 
+    /// Runs `src` to the end while recording, steps back to the start
+    /// checking every state on the way, then runs it again. Returns the
+    /// output of both runs.
+    fn retrace(src: &str, standard: Standard, input: &str) -> Result<String, Box<dyn Error>> {
+        fn state(befunge: &Befunge) -> String {
+            let mut grid = Vec::new();
+            befunge.world.render(&mut grid).unwrap();
+            format!(
+                "{:?} {:?} {:?} {} {}",
+                befunge.ip,
+                befunge.queue,
+                befunge.exit_code,
+                befunge.steps,
+                String::from_utf8_lossy(&grid)
+            )
+        }
+        let mut world = World::from_source(src, standard)?;
+        let mut read = BufReader::new(input.as_bytes());
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write)
+            .with_history(1000);
+        let mut states = vec![state(&befunge)];
+        while befunge.step()? != Step::Halted {
+            states.push(state(&befunge));
+        }
+        while befunge.step_back() {
+            assert_eq!(Some(state(&befunge)), states.pop());
+        }
+        assert!(states.is_empty());
+        befunge.run()?;
+        drop(befunge);
+        Ok(String::from_utf8(write)?)
+    }
+
     #[test]
-    fn numeric_input_commands_are_whitespace_delimited() -> Result<(), Box<dyn Error>> {
-        let read = Vec::from("12 34\n".as_bytes());
-        let mut buf_read = BufReader::new(&read[..]);
+    fn stepping_back_retraces_every_state_and_rereads_input() -> Result<(), Box<dyn Error>> {
+        let output = retrace("&~,1+:00p.00g.@", Standard::Befunge93, "41 x")?;
+        assert_eq!(output, "x42 42 x42 42 ");
+        let output = retrace("12t.@.", Standard::Funge98, "")?;
+        assert_eq!(output, "2 1 2 1 ");
+        let output = retrace("0{'X99p}'Y5 0p\"AMOR\"4(M.)7q", Standard::Funge98, "")?;
+        assert_eq!(output, "1000 1000 ");
+        // Instructions reaching into a deep stack, and ones that replace it.
+        let output = retrace("987654321\\:*+-`!$.2k:3u{7n}y.@", Standard::Funge98, "")?;
+        assert_eq!(output, "5 2 5 2 ");
+        Ok(())
+    }
+
+    #[test]
+    fn handler_reads_are_recorded() -> Result<(), Box<dyn Error>> {
+        struct Reads(Vec<u8>);
+        impl Observer<i32> for Reads {
+            fn instruction(&mut self, event: &Event<i32>) -> io::Result<()> {
+                self.0.extend_from_slice(event.input);
+                Ok(())
+            }
+        }
+        let mut world = World::from_source_string("R.R.@")?;
+        let mut read = BufReader::new(&b"ab"[..]);
         let mut write = Vec::new();
-        let mut world = World::from_source_string("&&..@")?;
-        let mut befunge = Befunge::new(
-            &mut world,
-            0,
-            0,
-            Direction::Right,
-            &mut buf_read,
-            &mut write,
-        );
+        let mut reads = Reads(Vec::new());
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write)
+            .with_observer(&mut reads)
+            .with_history(10);
+        befunge.register_instruction('R', |context| {
+            let mut byte = [0];
+            context.input().read_exact(&mut byte)?;
+            context.push(i32::from(byte[0]));
+            Ok(())
+        })?;
+        befunge.step()?;
+        assert!(befunge.step_back());
         befunge.run()?;
-        assert_eq!(befunge.stack, []);
-        assert_eq!(String::from_utf8_lossy(&write[..]), "34 12 ");
+        drop(befunge);
+        assert_eq!(String::from_utf8(write)?, "97 98 ");
+        // The byte handed back by stepping back is read again.
+        assert_eq!(reads.0, b"aab");
+        Ok(())
+    }
+
+    #[test]
+    fn stepping_back_keeps_output_counted() -> Result<(), Box<dyn Error>> {
+        let mut world = World::from_source_string("1.@")?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write)
+            .with_config(Config::default().max_output(2))
+            .with_history(10);
+        befunge.step()?;
+        befunge.step()?;
+        assert!(befunge.step_back());
+        let err = befunge
+            .step()
+            .expect_err("the output was already written once");
+        assert!(matches!(err.kind(), ErrorKind::OutputLimitExceeded(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn history_finds_the_last_write_to_a_cell() -> Result<(), Box<dyn Error>> {
+        let mut world = World::from_source_string("11p22p33p@")?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        befunge.step()?;
+        befunge = befunge.with_history(2);
+        befunge.run()?;
+        // Coordinates wrap on the torus.
+        assert_eq!(befunge.last_write(81, 26), Some(3));
+        assert_eq!(befunge.last_write(2, 2), Some(6));
+        assert_eq!(befunge.last_write(3, 3), Some(9));
+        assert_eq!(befunge.last_write(4, 4), None);
+        assert!(befunge.step_back() && befunge.step_back());
+        // Only the last two steps were kept.
+        assert!(!befunge.step_back());
+        assert_eq!(befunge.steps(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn cached_paths_run_like_the_decoder() -> Result<(), Box<dyn Error>> {
+        fn outcome(src: &str, input: &str, config: Config) -> Result<String, Box<dyn Error>> {
+            let mut world = World::from_source_string(src)?;
+            let mut read = BufReader::new(input.as_bytes());
+            let mut write = Vec::new();
+            let mut befunge =
+                Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write)
+                    .with_config(config);
+            let result = befunge.run();
+            let state = format!("{:?} {} {:?}", result, befunge.steps(), befunge.stack());
+            drop(befunge);
+            Ok(format!("{} {}", state, String::from_utf8(write)?))
+        }
+        let long = format!("1{}@", " ".repeat(60));
+        let programs = [
+            (include_str!("../examples/hello.bf"), "", Config::default()),
+            (
+                include_str!("../examples/factorial.bf"),
+                "",
+                Config::default(),
+            ),
+            // `p` rewrites a cell further along the path being run.
+            ("\"@\"70p  1.@", "", Config::default()),
+            // `p` rewrites a cell of a loop that has already been decoded.
+            (
+                "0>1+:.:5`#v_v\n ^          <\n ^  p11\"@\"<",
+                "",
+                Config::default(),
+            ),
+            (
+                "0>&:!#v_+ v\n ^      .:<\n      >$.\"@\"45*2p    \"dne\",,,@",
+                "3 4 0",
+                Config::default(),
+            ),
+            ("123456789@", "", Config::default().max_steps(5)),
+            ("12 3 4 5 6:::::::::@", "", Config::default().max_stack(8)),
+            ("10/@", "", Config::default()),
+            // Fused runs: constants, strings, spaces and `:` before a branch.
+            ("12+34*-5/67%\"x\".....@", "", Config::default()),
+            ("99*9*9*9*9*9*9*9*9*9*9*.@", "", Config::default()),
+            (
+                "99*9*9*9*9*9*9*9*9*9*9*.@",
+                "",
+                Config::default().overflow(Overflow::Checked),
+            ),
+            (":_5.@", "", Config::default()),
+            ("1v\n :\n |", "", Config::default().max_stack(10)),
+            ("\"abcdefgh\"@", "", Config::default().max_stack(5)),
+            (&long, "", Config::default().max_steps(30)),
+            // `p` rewrites a string literal it has just run.
+            (
+                ">\"a\":,1+20p v\n^           <",
+                "",
+                Config::default().max_steps(200),
+            ),
+        ];
+        for (src, input, config) in programs {
+            assert_eq!(
+                outcome(src, input, config.clone())?,
+                outcome(src, input, config.uncached(true))?,
+                "{src}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn host_instructions_use_the_stack_and_world() -> Result<(), Box<dyn Error>> {
+        let mut world = World::from_source_string("hUH.@")?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        befunge.register_instruction('h', |context| {
+            context.push(21);
+            Ok(())
+        })?;
+        befunge.register_instruction('U', |context| {
+            let value = context.pop();
+            context.push(value * 2);
+            Ok(())
+        })?;
+        // Writes the doubled value over its own opcode.
+        befunge.register_instruction('H', |context| {
+            let value = context.pop();
+            context.world_mut().set_cell(2, 0, value);
+            let (x, y) = context.position();
+            let cell = context.world().get_cell(x, y);
+            context.push(cell);
+            Ok(())
+        })?;
+        befunge.run()?;
+        drop(befunge);
+        assert_eq!(String::from_utf8(write)?, "42 ");
+        assert_eq!(world.get_cell(2, 0), 42);
+        Ok(())
+    }
+
+    #[test]
+    fn host_instructions_cannot_replace_builtins() -> Result<(), Box<dyn Error>> {
+        let mut world = World::from_source_string("@")?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        let err = befunge.register_instruction('+', |_| Ok(())).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::BuiltinInstruction('+')));
+        assert!(err.location().is_none());
+        // `a` is only taken in Funge-98.
+        befunge.register_instruction('a', |_| Ok(()))?;
+        let mut world = World::from_source("@", Standard::Funge98)?;
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        assert!(befunge.register_instruction('a', |_| Ok(())).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn host_instruction_errors_are_located() -> Result<(), Box<dyn Error>> {
+        let mut world = World::from_source_string("1X@")?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        befunge.register_instruction('X', |_| Err(ErrorKind::Custom("no X today".into())))?;
+        let err = befunge.run().unwrap_err();
+        assert_eq!(err.to_string(), "no X today at (1, 0)");
         Ok(())
     }
 }
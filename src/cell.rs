@@ -0,0 +1,193 @@
+use crate::config::Overflow;
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use std::fmt;
+use std::str::FromStr;
+
+/// A binary arithmetic instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arithmetic {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    /// Bitwise operations on the two's complement representation; these
+    /// never overflow.
+    And,
+    Or,
+    Xor,
+}
+
+/// A value that can live on the interpreter's stack.
+///
+/// Values only ever leave the stack as coordinates, characters or bytes in
+/// Funge-Space through [`Cell::to_i32`], which keeps the low 32 bits of the
+/// two's complement representation. `p` then stores the low byte of that, so
+/// `256 0 0 p` followed by `0 0 g` pushes 0 for every cell type.
+pub trait Cell:
+    Clone + Default + PartialEq + PartialOrd + fmt::Debug + fmt::Display + FromStr
+{
+    /// Size of a value in bytes as reported by Funge-98 `y`; 0 if unbounded.
+    const BYTES: i32;
+
+    fn from_i32(value: i32) -> Self;
+
+    fn to_i32(&self) -> i32;
+
+    fn is_zero(&self) -> bool;
+
+    /// Computes `self op rhs`, where `rhs` is known to be non-zero for `Div`
+    /// and `Rem`. Returns `None` when the result overflows under `Checked`.
+    fn arithmetic(&self, op: Arithmetic, rhs: &Self, overflow: Overflow) -> Option<Self>;
+}
+
+macro_rules! fixed_width_cell {
+    ($t:ty) => {
+        impl Cell for $t {
+            const BYTES: i32 = std::mem::size_of::<$t>() as i32;
+
+            fn from_i32(value: i32) -> $t {
+                value as $t
+            }
+
+            fn to_i32(&self) -> i32 {
+                *self as i32
+            }
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+
+            fn arithmetic(&self, op: Arithmetic, rhs: &$t, overflow: Overflow) -> Option<$t> {
+                let (a, b) = (*self, *rhs);
+                match (op, overflow) {
+                    (Arithmetic::Add, Overflow::Wrapping) => Some(a.wrapping_add(b)),
+                    (Arithmetic::Add, Overflow::Saturating) => Some(a.saturating_add(b)),
+                    (Arithmetic::Add, Overflow::Checked) => a.checked_add(b),
+                    (Arithmetic::Sub, Overflow::Wrapping) => Some(a.wrapping_sub(b)),
+                    (Arithmetic::Sub, Overflow::Saturating) => Some(a.saturating_sub(b)),
+                    (Arithmetic::Sub, Overflow::Checked) => a.checked_sub(b),
+                    (Arithmetic::Mul, Overflow::Wrapping) => Some(a.wrapping_mul(b)),
+                    (Arithmetic::Mul, Overflow::Saturating) => Some(a.saturating_mul(b)),
+                    (Arithmetic::Mul, Overflow::Checked) => a.checked_mul(b),
+                    (Arithmetic::Div, Overflow::Wrapping) => Some(a.wrapping_div(b)),
+                    (Arithmetic::Div, Overflow::Saturating) => Some(a.saturating_div(b)),
+                    (Arithmetic::Div, Overflow::Checked) => a.checked_div(b),
+                    // The remainder of MIN / -1 is 0 even though the quotient overflows.
+                    (Arithmetic::Rem, Overflow::Wrapping | Overflow::Saturating) => {
+                        Some(a.wrapping_rem(b))
+                    }
+                    (Arithmetic::Rem, Overflow::Checked) => a.checked_rem(b),
+                    (Arithmetic::And, _) => Some(a & b),
+                    (Arithmetic::Or, _) => Some(a | b),
+                    (Arithmetic::Xor, _) => Some(a ^ b),
+                }
+            }
+        }
+    };
+}
+
+fixed_width_cell!(i32);
+fixed_width_cell!(i64);
+
+impl Cell for BigInt {
+    const BYTES: i32 = 0;
+
+    fn from_i32(value: i32) -> BigInt {
+        BigInt::from(value)
+    }
+
+    fn to_i32(&self) -> i32 {
+        let low = self % BigInt::from(1_u64 << 32);
+        low.to_i64().map_or(0, |low| low as i32)
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+
+    fn arithmetic(&self, op: Arithmetic, rhs: &BigInt, _overflow: Overflow) -> Option<BigInt> {
+        Some(match op {
+            Arithmetic::Add => self + rhs,
+            Arithmetic::Sub => self - rhs,
+            Arithmetic::Mul => self * rhs,
+            Arithmetic::Div => self / rhs,
+            Arithmetic::Rem => self % rhs,
+            Arithmetic::And => self & rhs,
+            Arithmetic::Or => self | rhs,
+            Arithmetic::Xor => self ^ rhs,
+        })
+    }
+}
+
+/// The stack cell types selectable at runtime, e.g. by the `--cell` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CellWidth {
+    #[default]
+    I32,
+    I64,
+    Big,
+}
+
+impl FromStr for CellWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CellWidth, String> {
+        match s {
+            "i32" => Ok(CellWidth::I32),
+            "i64" => Ok(CellWidth::I64),
+            "big" => Ok(CellWidth::Big),
+            _ => Err(format!(
+                "unknown cell width '{}' (expected i32, i64 or big)",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arithmetic, Cell};
+    use crate::config::Overflow;
+    use num_bigint::BigInt;
+
+    #[test]
+    fn wide_cells_do_not_overflow_where_i32_does() {
+        let big = i64::from(i32::MAX);
+        assert_eq!(
+            i32::MAX.arithmetic(Arithmetic::Add, &1, Overflow::Checked),
+            None
+        );
+        assert_eq!(
+            big.arithmetic(Arithmetic::Add, &1, Overflow::Checked),
+            Some(big + 1)
+        );
+        let huge = BigInt::from(i64::MAX);
+        assert_eq!(
+            huge.arithmetic(Arithmetic::Mul, &huge, Overflow::Checked),
+            Some(BigInt::from(i128::from(i64::MAX) * i128::from(i64::MAX)))
+        );
+    }
+
+    #[test]
+    fn bitwise_operations_use_twos_complement() {
+        assert_eq!(
+            (-1_i32).arithmetic(Arithmetic::And, &6, Overflow::Checked),
+            Some(6)
+        );
+        assert_eq!(
+            BigInt::from(-2).arithmetic(Arithmetic::Xor, &BigInt::from(-1), Overflow::Checked),
+            Some(BigInt::from(1))
+        );
+    }
+
+    #[test]
+    fn conversions_keep_the_low_32_bits() {
+        assert_eq!((1_i64 << 32 | 65).to_i32(), 65);
+        assert_eq!((-1_i64).to_i32(), -1);
+        assert_eq!(BigInt::from(1_i64 << 40 | 65).to_i32(), 65);
+        assert_eq!(BigInt::from(-1).to_i32(), -1);
+        assert_eq!(BigInt::from(i64::from(u32::MAX)).to_i32(), -1);
+    }
+}
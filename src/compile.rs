@@ -0,0 +1,904 @@
+//! Ahead-of-time compilation of Befunge-93 programs to C or Rust, as done by
+//! `befunge_rust compile`.
+//!
+//! Each block of the [`FlowGraph`] becomes straight-line code. Cells that a
+//! `p` may rewrite are checked before they run; once one has changed, the
+//! generated program hands over to an interpreter embedded in it, which hands
+//! back whenever it reaches the start of a block again.
+
+use crate::befunge::{Delta, Mode};
+use crate::flow::{Block, FlowGraph, State};
+use crate::world::World;
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+/// The language [`compile`] writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    C,
+    #[default]
+    Rust,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Target, String> {
+        match s {
+            "c" => Ok(Target::C),
+            "rust" => Ok(Target::Rust),
+            _ => Err(format!("unknown target '{}' (expected c or rust)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Target::C => "c",
+            Target::Rust => "rust",
+        })
+    }
+}
+
+/// Source for a standalone program that behaves like running `program` with
+/// the default configuration, except that `?` draws from its own generator.
+/// `name` goes in a comment at the top.
+pub fn compile(program: &World, name: &str, target: Target) -> Result<String, String> {
+    let graph = FlowGraph::new(program)?;
+    let ((x0, y0), (x1, y1)) = program.bounds();
+    let rows: Vec<Vec<u8>> = (y0..=y1)
+        .map(|y| (x0..=x1).map(|x| program.get_signed(x, y)).collect())
+        .collect();
+    // A `p` with an unknown target might rewrite any cell.
+    let unknown = graph
+        .self_modifications()
+        .iter()
+        .any(|p| p.target.is_none());
+    let rewritable: HashSet<(i32, i32)> = graph
+        .self_modifications()
+        .iter()
+        .filter_map(|p| p.target)
+        .collect();
+    let compiler = Compiler {
+        graph: &graph,
+        program,
+        may_change: |at: (i32, i32)| unknown || rewritable.contains(&at),
+    };
+    let mut out = String::new();
+    let written = match target {
+        Target::C => compiler.c(name, &rows, &mut out),
+        Target::Rust => compiler.rust(name, &rows, &mut out),
+    };
+    written.map_err(|err| err.to_string())?;
+    Ok(out)
+}
+
+struct Compiler<'a, F> {
+    graph: &'a FlowGraph,
+    program: &'a World,
+    may_change: F,
+}
+
+/// What a state does, as far as the generated code is concerned.
+enum Op {
+    Push(i32),
+    /// A call to the runtime function of this name.
+    Call(&'static str),
+    /// `+ - * / %` and `` ` ``, by their character.
+    Binary(u8),
+    Nothing,
+}
+
+/// Where the IP goes once a block is done.
+enum Exit<'b> {
+    Halt,
+    Jump(usize),
+    /// Right if the popped value is zero, left otherwise.
+    Horizontal(usize, usize),
+    /// Down if the popped value is zero, up otherwise.
+    Vertical(usize, usize),
+    /// Up, down, left or right at random.
+    Random(&'b [usize]),
+}
+
+impl<'a, F: Fn((i32, i32)) -> bool> Compiler<'a, F> {
+    fn op(&self, state: &State) -> Op {
+        let opcode = self.program.get_signed(state.x, state.y);
+        if state.mode == Mode::AsciiPush {
+            return match opcode {
+                b'"' => Op::Nothing,
+                _ => Op::Push(i32::from(opcode)),
+            };
+        }
+        match opcode {
+            b'0'..=b'9' => Op::Push(i32::from(opcode - b'0')),
+            b'+' | b'-' | b'*' | b'/' | b'%' | b'`' => Op::Binary(opcode),
+            b'!' => Op::Call("not"),
+            b':' => Op::Call("dup"),
+            b'\\' => Op::Call("swap"),
+            b'$' => Op::Call("drop"),
+            b'.' => Op::Call("output_number"),
+            b',' => Op::Call("output_char"),
+            b'&' => Op::Call("input_number"),
+            b'~' => Op::Call("input_char"),
+            b'g' => Op::Call("get"),
+            b'p' => Op::Call("put"),
+            _ => Op::Nothing,
+        }
+    }
+
+    fn exit<'b>(&self, block: &'b Block) -> Exit<'b> {
+        let last = &block.states[block.states.len() - 1];
+        match block.successors[..] {
+            [] => Exit::Halt,
+            [next] => Exit::Jump(next),
+            [right, left] if self.program.get_signed(last.x, last.y) == b'_' => {
+                Exit::Horizontal(right, left)
+            }
+            [down, up] => Exit::Vertical(down, up),
+            _ => Exit::Random(&block.successors),
+        }
+    }
+
+    /// `(x, y, dx, dy, string)` for the runtime, with `string` as given.
+    fn ip(state: &State, string: (&str, &str)) -> String {
+        let Delta { dx, dy } = state.delta;
+        let string = match state.mode {
+            Mode::Interpret => string.0,
+            Mode::AsciiPush => string.1,
+        };
+        format!("{}, {}, {}, {}, {}", state.x, state.y, dx, dy, string)
+    }
+
+    fn rust(&self, name: &str, rows: &[Vec<u8>], out: &mut String) -> fmt::Result {
+        writeln!(out, "// Compiled from {} by befunge_rust.", name)?;
+        writeln!(out)?;
+        writeln!(out, "#![allow(dead_code)]")?;
+        writeln!(out)?;
+        writeln!(out, "const WIDTH: usize = {};", rows[0].len())?;
+        writeln!(out, "const HEIGHT: usize = {};", rows.len())?;
+        writeln!(out, "const FALLBACK: usize = usize::MAX;")?;
+        writeln!(out, "const PROGRAM: [&[u8; WIDTH]; HEIGHT] = [")?;
+        for row in rows {
+            let row: String = row.iter().map(|&b| rust_byte(b)).collect();
+            writeln!(out, "    b\"{}\",", row)?;
+        }
+        writeln!(out, "];")?;
+        writeln!(
+            out,
+            "/// (x, y, dx, dy, string mode, block) for the start of each block."
+        )?;
+        writeln!(
+            out,
+            "const LEADERS: &[(i32, i32, i32, i32, bool, usize)] = &["
+        )?;
+        for (id, block) in self.graph.blocks().iter().enumerate() {
+            let ip = Self::ip(&block.states[0], ("false", "true"));
+            writeln!(out, "    ({}, {}),", ip, id)?;
+        }
+        writeln!(out, "];")?;
+        out.push_str(RUST_RUNTIME);
+        writeln!(out)?;
+        writeln!(out, "fn main() {{")?;
+        writeln!(out, "    let mut m = Machine::new();")?;
+        writeln!(out, "    let mut block = 0;")?;
+        writeln!(out, "    'run: loop {{")?;
+        writeln!(out, "        match block {{")?;
+        for (id, block) in self.graph.blocks().iter().enumerate() {
+            writeln!(out, "            {} => {{", id)?;
+            for state in &block.states {
+                if (self.may_change)((state.x, state.y)) {
+                    writeln!(
+                        out,
+                        "                if m.modified[{}][{}] {{",
+                        state.y, state.x
+                    )?;
+                    let ip = Self::ip(state, ("false", "true"));
+                    writeln!(out, "                    m.resume({});", ip)?;
+                    writeln!(out, "                    block = FALLBACK;")?;
+                    writeln!(out, "                    continue 'run;")?;
+                    writeln!(out, "                }}")?;
+                }
+                match self.op(state) {
+                    Op::Push(n) => writeln!(out, "                m.push({});", n)?,
+                    Op::Call(f) => writeln!(out, "                m.{}();", f)?,
+                    Op::Binary(op) => {
+                        writeln!(out, "                m.binary(b'{}');", char::from(op))?
+                    }
+                    Op::Nothing => {}
+                }
+            }
+            let exit = match self.exit(block) {
+                Exit::Halt => "break 'run;".to_string(),
+                Exit::Jump(next) => format!("block = {};", next),
+                Exit::Horizontal(zero, other) | Exit::Vertical(zero, other) => format!(
+                    "block = if m.pop() == 0 {{ {} }} else {{ {} }};",
+                    zero, other
+                ),
+                Exit::Random(ways) => format!("block = {:?}[m.random_direction()];", ways),
+            };
+            writeln!(out, "                {}", exit)?;
+            writeln!(out, "            }}")?;
+        }
+        writeln!(out, "            _ => {{")?;
+        writeln!(out, "                if !m.step() {{")?;
+        writeln!(out, "                    break 'run;")?;
+        writeln!(out, "                }}")?;
+        writeln!(out, "                block = m.leader();")?;
+        writeln!(out, "            }}")?;
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "    m.flush();")?;
+        writeln!(out, "}}")
+    }
+
+    fn c(&self, name: &str, rows: &[Vec<u8>], out: &mut String) -> fmt::Result {
+        writeln!(
+            out,
+            "/* Compiled from {} by befunge_rust. */",
+            name.replace("*/", "* /")
+        )?;
+        out.push_str(C_INCLUDES);
+        writeln!(out)?;
+        writeln!(out, "#define WIDTH {}", rows[0].len())?;
+        writeln!(out, "#define HEIGHT {}", rows.len())?;
+        writeln!(out, "#define BLOCKS {}", self.graph.blocks().len())?;
+        writeln!(out, "#define FALLBACK (-1)")?;
+        writeln!(out)?;
+        writeln!(out, "static const char PROGRAM[HEIGHT][WIDTH + 1] = {{")?;
+        for row in rows {
+            let row: String = row.iter().map(|&b| c_byte(b)).collect();
+            writeln!(out, "    \"{}\",", row)?;
+        }
+        writeln!(out, "}};")?;
+        writeln!(
+            out,
+            "/* x, y, dx, dy and string mode at the start of each block. */"
+        )?;
+        writeln!(out, "static const int LEADERS[BLOCKS][5] = {{")?;
+        for block in self.graph.blocks() {
+            writeln!(out, "    {{{}}},", Self::ip(&block.states[0], ("0", "1")))?;
+        }
+        writeln!(out, "}};")?;
+        out.push_str(C_RUNTIME);
+        writeln!(out)?;
+        writeln!(out, "int main(void) {{")?;
+        writeln!(out, "    int block = 0;")?;
+        writeln!(out, "    setup();")?;
+        writeln!(out, "    for (;;) {{")?;
+        writeln!(out, "        switch (block) {{")?;
+        for (id, block) in self.graph.blocks().iter().enumerate() {
+            writeln!(out, "        case {}:", id)?;
+            for state in &block.states {
+                if (self.may_change)((state.x, state.y)) {
+                    writeln!(
+                        out,
+                        "            if (modified[{}][{}]) {{",
+                        state.y, state.x
+                    )?;
+                    writeln!(
+                        out,
+                        "                resume({});",
+                        Self::ip(state, ("0", "1"))
+                    )?;
+                    writeln!(out, "                block = FALLBACK;")?;
+                    writeln!(out, "                continue;")?;
+                    writeln!(out, "            }}")?;
+                }
+                match self.op(state) {
+                    Op::Push(n) => writeln!(out, "            push({});", n)?,
+                    Op::Call(f) => writeln!(out, "            {}();", f)?,
+                    Op::Binary(op) => writeln!(out, "            binary('{}');", c_char(op))?,
+                    Op::Nothing => {}
+                }
+            }
+            let exit = match self.exit(block) {
+                Exit::Halt => "goto done;".to_string(),
+                Exit::Jump(next) => format!("block = {};", next),
+                Exit::Horizontal(zero, other) | Exit::Vertical(zero, other) => {
+                    format!("block = pop() == 0 ? {} : {};", zero, other)
+                }
+                Exit::Random(ways) => {
+                    let ways: Vec<String> = ways.iter().map(ToString::to_string).collect();
+                    format!(
+                        "{{ static const int ways[] = {{{}}}; block = ways[random_direction()]; }}",
+                        ways.join(", ")
+                    )
+                }
+            };
+            writeln!(out, "            {}", exit)?;
+            writeln!(out, "            break;")?;
+        }
+        writeln!(out, "        default:")?;
+        writeln!(out, "            if (!step()) goto done;")?;
+        writeln!(out, "            block = leader();")?;
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "done:")?;
+        writeln!(out, "    fflush(stdout);")?;
+        writeln!(out, "    return 0;")?;
+        writeln!(out, "}}")
+    }
+}
+
+fn rust_byte(byte: u8) -> String {
+    match byte {
+        b'"' | b'\\' => format!("\\{}", char::from(byte)),
+        0x20..=0x7e => char::from(byte).to_string(),
+        _ => format!("\\x{:02x}", byte),
+    }
+}
+
+fn c_byte(byte: u8) -> String {
+    match byte {
+        // `?` is escaped so that `??` is never read as a trigraph.
+        b'"' | b'\\' | b'?' => format!("\\{}", char::from(byte)),
+        0x20..=0x7e => char::from(byte).to_string(),
+        _ => format!("\\{:03o}", byte),
+    }
+}
+
+fn c_char(byte: u8) -> String {
+    match byte {
+        b'\'' | b'\\' => format!("\\{}", char::from(byte)),
+        _ => char::from(byte).to_string(),
+    }
+}
+
+const RUST_RUNTIME: &str = r#"
+use std::io::{self, BufRead, Write};
+use std::process;
+
+struct Machine {
+    grid: [[u8; WIDTH]; HEIGHT],
+    /// Cells that `p` has changed from the program as written.
+    modified: [[bool; WIDTH]; HEIGHT],
+    stack: Vec<i32>,
+    input: io::StdinLock<'static>,
+    output: io::BufWriter<io::Stdout>,
+    random: u64,
+    /// Where the interpreter is while the compiled blocks are not running.
+    ip: (i32, i32, i32, i32, bool),
+    leaders: Vec<usize>,
+}
+
+/// Up, down, left and right.
+const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+
+fn direction_index(dx: i32, dy: i32) -> usize {
+    DIRECTIONS.iter().position(|&d| d == (dx, dy)).unwrap_or(0)
+}
+
+fn leader_index(x: i32, y: i32, dx: i32, dy: i32, string: bool) -> usize {
+    ((usize::from(string) * 4 + direction_index(dx, dy)) * HEIGHT + y as usize) * WIDTH + x as usize
+}
+
+impl Machine {
+    fn new() -> Machine {
+        let mut leaders = vec![FALLBACK; 2 * 4 * HEIGHT * WIDTH];
+        for &(x, y, dx, dy, string, block) in LEADERS {
+            leaders[leader_index(x, y, dx, dy, string)] = block;
+        }
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |t| t.as_nanos() as u64);
+        Machine {
+            grid: PROGRAM.map(|row| *row),
+            modified: [[false; WIDTH]; HEIGHT],
+            stack: Vec::new(),
+            input: io::stdin().lock(),
+            output: io::BufWriter::new(io::stdout()),
+            random: seed | 1,
+            ip: (0, 0, 1, 0, false),
+            leaders,
+        }
+    }
+
+    fn fail(&mut self, message: &str) -> ! {
+        self.flush();
+        eprintln!("Error executing Befunge program: {}", message);
+        process::exit(1);
+    }
+
+    fn flush(&mut self) {
+        let _ = self.output.flush();
+    }
+
+    fn push(&mut self, value: i32) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> i32 {
+        self.stack.pop().unwrap_or(0)
+    }
+
+    fn binary(&mut self, op: u8) {
+        if op == b'/' && self.stack.last().is_none_or(|&a| a == 0) {
+            self.fail("division by zero");
+        }
+        if op == b'%' && self.stack.last().is_none_or(|&a| a == 0) {
+            self.fail("modulo by zero");
+        }
+        let a = self.pop();
+        let b = self.pop();
+        let value = match op {
+            b'+' => b.wrapping_add(a),
+            b'-' => b.wrapping_sub(a),
+            b'*' => b.wrapping_mul(a),
+            b'/' => b.wrapping_div(a),
+            b'%' => b.wrapping_rem(a),
+            _ => i32::from(b > a),
+        };
+        self.push(value);
+    }
+
+    fn not(&mut self) {
+        let value = self.pop();
+        self.push(i32::from(value == 0));
+    }
+
+    fn dup(&mut self) {
+        let value = self.pop();
+        self.push(value);
+        self.push(value);
+    }
+
+    fn swap(&mut self) {
+        let a = self.pop();
+        let b = self.pop();
+        self.push(a);
+        self.push(b);
+    }
+
+    fn drop(&mut self) {
+        self.pop();
+    }
+
+    fn output_number(&mut self) {
+        let value = self.pop();
+        if write!(self.output, "{} ", value).is_err() {
+            self.fail("cannot write output");
+        }
+    }
+
+    fn output_char(&mut self) {
+        let value = self.pop();
+        let c = char::from(value as u8);
+        if write!(self.output, "{}", c).is_err() {
+            self.fail("cannot write output");
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.flush();
+        let byte = *self.input.fill_buf().ok()?.first()?;
+        self.input.consume(1);
+        Some(byte)
+    }
+
+    fn input_number(&mut self) {
+        let mut token = Vec::new();
+        while let Some(byte) = self.read_byte() {
+            match byte {
+                _ if byte.is_ascii_whitespace() && token.is_empty() => continue,
+                _ if byte.is_ascii_whitespace() => break,
+                _ => token.push(byte),
+            }
+        }
+        if token.is_empty() {
+            self.fail("expected integer input");
+        }
+        let token = String::from_utf8_lossy(&token).into_owned();
+        match token.parse() {
+            Ok(value) => self.push(value),
+            Err(_) => self.fail(&format!("invalid integer input {:?}", token)),
+        }
+    }
+
+    fn input_char(&mut self) {
+        if let Some(byte) = self.read_byte() {
+            self.push(i32::from(byte));
+        }
+    }
+
+    fn get(&mut self) {
+        let y = self.pop().rem_euclid(HEIGHT as i32) as usize;
+        let x = self.pop().rem_euclid(WIDTH as i32) as usize;
+        self.push(i32::from(self.grid[y][x]));
+    }
+
+    fn put(&mut self) {
+        let y = self.pop().rem_euclid(HEIGHT as i32) as usize;
+        let x = self.pop().rem_euclid(WIDTH as i32) as usize;
+        let value = self.pop() as u8;
+        self.grid[y][x] = value;
+        self.modified[y][x] = value != PROGRAM[y][x];
+    }
+
+    fn random_direction(&mut self) -> usize {
+        // xorshift64
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        (self.random % 4) as usize
+    }
+
+    fn resume(&mut self, x: i32, y: i32, dx: i32, dy: i32, string: bool) {
+        self.ip = (x, y, dx, dy, string);
+    }
+
+    /// The block starting where the interpreter is, or `FALLBACK`.
+    fn leader(&self) -> usize {
+        let (x, y, dx, dy, string) = self.ip;
+        self.leaders[leader_index(x, y, dx, dy, string)]
+    }
+
+    /// Interprets one instruction; false on `@`.
+    fn step(&mut self) -> bool {
+        let (x, y, mut dx, mut dy, mut string) = self.ip;
+        let cell = self.grid[y as usize][x as usize];
+        let mut distance = 1;
+        if string {
+            match cell {
+                b'"' => string = false,
+                _ => self.push(i32::from(cell)),
+            }
+        } else {
+            match cell {
+                b'0'..=b'9' => self.push(i32::from(cell - b'0')),
+                b'+' | b'-' | b'*' | b'/' | b'%' | b'`' => self.binary(cell),
+                b'!' => self.not(),
+                b'>' => (dx, dy) = (1, 0),
+                b'<' => (dx, dy) = (-1, 0),
+                b'^' => (dx, dy) = (0, -1),
+                b'v' => (dx, dy) = (0, 1),
+                b'?' => (dx, dy) = DIRECTIONS[self.random_direction()],
+                b'_' => (dx, dy) = if self.pop() == 0 { (1, 0) } else { (-1, 0) },
+                b'|' => (dx, dy) = if self.pop() == 0 { (0, 1) } else { (0, -1) },
+                b'"' => string = true,
+                b':' => self.dup(),
+                b'\\' => self.swap(),
+                b'$' => self.drop(),
+                b'.' => self.output_number(),
+                b',' => self.output_char(),
+                b'#' => distance = 2,
+                b'p' => self.put(),
+                b'g' => self.get(),
+                b'&' => self.input_number(),
+                b'~' => self.input_char(),
+                b'@' => return false,
+                _ => {}
+            }
+        }
+        let x = (x + dx * distance).rem_euclid(WIDTH as i32);
+        let y = (y + dy * distance).rem_euclid(HEIGHT as i32);
+        self.ip = (x, y, dx, dy, string);
+        true
+    }
+}
+"#;
+
+const C_INCLUDES: &str = r#"
+#include <ctype.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+"#;
+
+const C_RUNTIME: &str = r#"
+static unsigned char grid[HEIGHT][WIDTH];
+/* Cells that `p` has changed from the program as written. */
+static unsigned char modified[HEIGHT][WIDTH];
+static int32_t *stack;
+static size_t depth, capacity;
+static uint64_t random_state;
+/* Where the interpreter is while the compiled blocks are not running. */
+static int ip_x, ip_y, ip_dx = 1, ip_dy, ip_string;
+static int *leaders;
+
+/* Up, down, left and right. */
+static const int DIRECTIONS[4][2] = {{0, -1}, {0, 1}, {-1, 0}, {1, 0}};
+
+static inline void fail(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "Error executing Befunge program: %s\n", message);
+    exit(1);
+}
+
+static inline int wrap(int32_t value, int size) {
+    int r = (int)(value % size);
+    return r < 0 ? r + size : r;
+}
+
+static inline size_t leader_index(int x, int y, int dx, int dy, int string) {
+    int direction = 0;
+    for (int i = 0; i < 4; i++) {
+        if (DIRECTIONS[i][0] == dx && DIRECTIONS[i][1] == dy) direction = i;
+    }
+    return (((size_t)string * 4 + direction) * HEIGHT + y) * WIDTH + x;
+}
+
+static inline void setup(void) {
+    for (int y = 0; y < HEIGHT; y++) memcpy(grid[y], PROGRAM[y], WIDTH);
+    leaders = malloc(sizeof *leaders * 2 * 4 * HEIGHT * WIDTH);
+    if (!leaders) fail("out of memory");
+    for (size_t i = 0; i < (size_t)2 * 4 * HEIGHT * WIDTH; i++) leaders[i] = FALLBACK;
+    for (int b = 0; b < BLOCKS; b++) {
+        const int *l = LEADERS[b];
+        leaders[leader_index(l[0], l[1], l[2], l[3], l[4])] = b;
+    }
+    random_state = (uint64_t)time(NULL) | 1;
+}
+
+static inline void push(int32_t value) {
+    if (depth == capacity) {
+        capacity = capacity ? capacity * 2 : 64;
+        stack = realloc(stack, sizeof *stack * capacity);
+        if (!stack) fail("out of memory");
+    }
+    stack[depth++] = value;
+}
+
+static inline int32_t pop(void) {
+    return depth ? stack[--depth] : 0;
+}
+
+static inline void binary(char op) {
+    if ((op == '/' || op == '%') && (depth == 0 || stack[depth - 1] == 0)) {
+        fail(op == '/' ? "division by zero" : "modulo by zero");
+    }
+    int32_t a = pop();
+    int32_t b = pop();
+    switch (op) {
+    case '+': push((int32_t)((uint32_t)b + (uint32_t)a)); break;
+    case '-': push((int32_t)((uint32_t)b - (uint32_t)a)); break;
+    case '*': push((int32_t)((uint32_t)b * (uint32_t)a)); break;
+    case '/': push(a == -1 ? (int32_t)(0u - (uint32_t)b) : b / a); break;
+    case '%': push(a == -1 ? 0 : b % a); break;
+    default: push(b > a); break;
+    }
+}
+
+static inline void not(void) { push(pop() == 0); }
+static inline void dup(void) { int32_t v = pop(); push(v); push(v); }
+static inline void swap(void) { int32_t a = pop(); int32_t b = pop(); push(a); push(b); }
+static inline void drop(void) { pop(); }
+
+static inline void output_number(void) {
+    printf("%ld ", (long)pop());
+}
+
+/* Bytes above 127 are written as the matching Unicode character in UTF-8. */
+static inline void output_char(void) {
+    unsigned char byte = (unsigned char)pop();
+    if (byte < 0x80) {
+        putchar(byte);
+    } else {
+        putchar(0xc0 | (byte >> 6));
+        putchar(0x80 | (byte & 0x3f));
+    }
+}
+
+static inline int read_byte(void) {
+    fflush(stdout);
+    return getchar();
+}
+
+static inline void input_number(void) {
+    char token[64];
+    size_t length = 0;
+    int c;
+    while ((c = read_byte()) != EOF && isspace(c)) {
+    }
+    if (c == EOF) fail("expected integer input");
+    for (; c != EOF && !isspace(c); c = read_byte()) {
+        if (length + 1 < sizeof token) token[length++] = (char)c;
+    }
+    token[length] = '\0';
+    const char *digits = token + (token[0] == '-' || token[0] == '+');
+    int64_t value = 0;
+    int valid = *digits != '\0' && length + 1 < sizeof token;
+    for (const char *d = digits; valid && *d; d++) {
+        valid = isdigit((unsigned char)*d) && (value = value * 10 + (*d - '0')) <= 2147483648LL;
+    }
+    if (token[0] != '-' && value > INT32_MAX) valid = 0;
+    if (!valid) {
+        char message[128];
+        snprintf(message, sizeof message, "invalid integer input \"%s\"", token);
+        fail(message);
+    }
+    push((int32_t)(token[0] == '-' ? -value : value));
+}
+
+static inline void input_char(void) {
+    int c = read_byte();
+    if (c != EOF) push(c);
+}
+
+static inline void get(void) {
+    int y = wrap(pop(), HEIGHT);
+    int x = wrap(pop(), WIDTH);
+    push(grid[y][x]);
+}
+
+static inline void put(void) {
+    int y = wrap(pop(), HEIGHT);
+    int x = wrap(pop(), WIDTH);
+    unsigned char value = (unsigned char)pop();
+    grid[y][x] = value;
+    modified[y][x] = value != (unsigned char)PROGRAM[y][x];
+}
+
+static inline int random_direction(void) {
+    /* xorshift64 */
+    random_state ^= random_state << 13;
+    random_state ^= random_state >> 7;
+    random_state ^= random_state << 17;
+    return (int)(random_state % 4);
+}
+
+static inline void resume(int x, int y, int dx, int dy, int string) {
+    ip_x = x;
+    ip_y = y;
+    ip_dx = dx;
+    ip_dy = dy;
+    ip_string = string;
+}
+
+/* The block starting where the interpreter is, or FALLBACK. */
+static inline int leader(void) {
+    return leaders[leader_index(ip_x, ip_y, ip_dx, ip_dy, ip_string)];
+}
+
+/* Interprets one instruction; 0 on `@`. */
+static inline int step(void) {
+    unsigned char cell = grid[ip_y][ip_x];
+    int distance = 1;
+    if (ip_string) {
+        if (cell == '"') ip_string = 0;
+        else push(cell);
+    } else {
+        switch (cell) {
+        case '0': case '1': case '2': case '3': case '4':
+        case '5': case '6': case '7': case '8': case '9': push(cell - '0'); break;
+        case '+': case '-': case '*': case '/': case '%': case '`': binary((char)cell); break;
+        case '!': not(); break;
+        case '>': ip_dx = 1; ip_dy = 0; break;
+        case '<': ip_dx = -1; ip_dy = 0; break;
+        case '^': ip_dx = 0; ip_dy = -1; break;
+        case 'v': ip_dx = 0; ip_dy = 1; break;
+        case '?': {
+            int d = random_direction();
+            ip_dx = DIRECTIONS[d][0];
+            ip_dy = DIRECTIONS[d][1];
+            break;
+        }
+        case '_': ip_dx = pop() == 0 ? 1 : -1; ip_dy = 0; break;
+        case '|': ip_dx = 0; ip_dy = pop() == 0 ? 1 : -1; break;
+        case '"': ip_string = 1; break;
+        case ':': dup(); break;
+        case '\\': swap(); break;
+        case '$': drop(); break;
+        case '.': output_number(); break;
+        case ',': output_char(); break;
+        case '#': distance = 2; break;
+        case 'p': put(); break;
+        case 'g': get(); break;
+        case '&': input_number(); break;
+        case '~': input_char(); break;
+        case '@': return 0;
+        default: break;
+        }
+    }
+    ip_x = wrap(ip_x + ip_dx * distance, WIDTH);
+    ip_y = wrap(ip_y + ip_dy * distance, HEIGHT);
+    return 1;
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, Target};
+    use crate::befunge::{Befunge, Direction};
+    use crate::world::World;
+    use std::error::Error;
+    use std::io::{BufReader, Write};
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::{env, fs};
+
+    /// Prints a running total of the numbers read until a 0, then the total
+    /// again, then puts a `@` in front of the code that would print "end".
+    const PROGRAM: &str = "0>&:!#v_+ v\n ^      .:<\n      >$.\"@\"45*2p    \"dne\",,,@";
+
+    /// Overwrites the `.` with a 0 when `~` finds no input, so that only
+    /// the `9` is pushed before the `@`.
+    const EOF_PROGRAM: &str = concat!(
+        "1~$1p v\n",
+        ".9    <                                                                        @"
+    );
+
+    /// Each program with the inputs it is run on.
+    const CASES: [(&str, &[&[u8]]); 2] = [
+        (PROGRAM, &[b"3 4 5 0", b"0", b"7\n-7\n0\n"]),
+        (EOF_PROGRAM, &[b""]),
+    ];
+
+    fn interpret(src: &str, input: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut world = World::from_source_string(src)?;
+        let mut read = BufReader::new(input);
+        let mut write = Vec::new();
+        Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write).run()?;
+        Ok(write)
+    }
+
+    fn run(binary: &Path, input: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut child = Command::new(binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        child.stdin.take().ok_or("no stdin")?.write_all(input)?;
+        let output = child.wait_with_output()?;
+        assert!(output.status.success());
+        Ok(output.stdout)
+    }
+
+    /// Builds each compiled program with `compiler` and checks that it
+    /// prints what the interpreter does.
+    fn agrees(target: Target, compiler: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        let dir =
+            env::temp_dir().join(format!("befunge-compile-{}-{}", target, std::process::id()));
+        fs::create_dir_all(&dir)?;
+        for (src, inputs) in CASES {
+            let world = World::from_source_string(src)?;
+            let source = dir.join(match target {
+                Target::C => "program.c",
+                Target::Rust => "program.rs",
+            });
+            fs::write(&source, compile(&world, "program.bf", target)?)?;
+            let binary = dir.join("program");
+            let status = Command::new(compiler)
+                .args(args)
+                .arg("-o")
+                .arg(&binary)
+                .arg(&source)
+                .status()?;
+            assert!(status.success());
+            for &input in inputs {
+                assert_eq!(run(&binary, input)?, interpret(src, input)?, "{:?}", src);
+            }
+        }
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn compiled_rust_matches_the_interpreter() -> Result<(), Box<dyn Error>> {
+        let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        agrees(Target::Rust, &rustc, &["--edition=2021", "-O"])
+    }
+
+    #[test]
+    fn compiled_c_matches_the_interpreter() -> Result<(), Box<dyn Error>> {
+        let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        agrees(Target::C, &cc, &["-O2", "-std=c99", "-Wall", "-Werror"])
+    }
+
+    #[test]
+    fn only_cells_that_p_may_rewrite_are_checked() -> Result<(), Box<dyn Error>> {
+        let world = World::from_source_string("500p@")?;
+        let rust = compile(&world, "x.bf", Target::Rust)?;
+        assert_eq!(rust.matches("m.modified[").count(), 1);
+        assert!(rust.contains("if m.modified[0][0] {"));
+        let world = World::from_source_string("&0p@")?;
+        let c = compile(&world, "x.bf", Target::C)?;
+        assert_eq!(c.matches("if (modified[").count(), 4);
+        let world = World::from_source("@", crate::world::Standard::Funge98)?;
+        assert!(compile(&world, "x.bf", Target::C).is_err());
+        Ok(())
+    }
+}
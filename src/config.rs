@@ -0,0 +1,108 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How `+`, `-`, `*`, `/` and `%` behave when the result does not fit in a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Two's complement wrap-around, e.g. `i32::MIN / -1 == i32::MIN`.
+    #[default]
+    Wrapping,
+    /// Clamp to the nearest representable value.
+    Saturating,
+    /// Stop with an arithmetic overflow error.
+    Checked,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Overflow, String> {
+        match s {
+            "wrapping" => Ok(Overflow::Wrapping),
+            "saturating" => Ok(Overflow::Saturating),
+            "checked" => Ok(Overflow::Checked),
+            _ => Err(format!(
+                "unknown overflow policy '{}' (expected wrapping, saturating or checked)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Overflow::Wrapping => "wrapping",
+            Overflow::Saturating => "saturating",
+            Overflow::Checked => "checked",
+        })
+    }
+}
+
+/// Interpreter settings that are not part of the program itself.
+///
+/// The limits are all unset by default; each one that is set stops the
+/// program with its own [`ErrorKind`](crate::ErrorKind) once exceeded.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub overflow: Overflow,
+    /// Maximum number of instructions to execute.
+    pub max_steps: Option<u64>,
+    /// Maximum wall-clock time, measured from the first step. It is checked
+    /// between instructions, so a `&` or `~` blocked on input is not cut
+    /// short; the limit applies once the read returns.
+    pub timeout: Option<Duration>,
+    /// Maximum number of bytes written by `.` and `,`.
+    pub max_output: Option<u64>,
+    /// Maximum number of values on the stack.
+    pub max_stack: Option<usize>,
+    /// Decode every instruction as it runs, instead of replaying straight
+    /// runs of Befunge-93 decoded once; for comparison and debugging.
+    pub uncached: bool,
+}
+
+impl Config {
+    pub fn overflow(mut self, overflow: Overflow) -> Config {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: u64) -> Config {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Config {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_output(mut self, max_output: u64) -> Config {
+        self.max_output = Some(max_output);
+        self
+    }
+
+    pub fn max_stack(mut self, max_stack: usize) -> Config {
+        self.max_stack = Some(max_stack);
+        self
+    }
+
+    pub fn uncached(mut self, uncached: bool) -> Config {
+        self.uncached = uncached;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Overflow;
+
+    #[test]
+    fn overflow_policies_round_trip_through_their_names() {
+        for policy in [Overflow::Wrapping, Overflow::Saturating, Overflow::Checked] {
+            assert_eq!(policy.to_string().parse::<Overflow>(), Ok(policy));
+        }
+        assert!("panic".parse::<Overflow>().is_err());
+    }
+}
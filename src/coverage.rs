@@ -0,0 +1,324 @@
+//! Which cells of a program ran and which ways its branches went, as
+//! collected by `--coverage`.
+
+use crate::befunge::{Direction, Mode};
+use crate::observer::{Event, Observer};
+use crate::world::World;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// The directions a branch can take, in the order its counts are kept.
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// Execution counts for the cells of one program, and for each `_`, `|` and
+/// `?` the number of times it sent the IP each way it can.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Every cell that held an instruction when the program was loaded.
+    cells: BTreeMap<(i32, i32), u64>,
+    /// Counts by [`DIRECTIONS`]; `None` for directions the branch cannot take.
+    branches: BTreeMap<(i32, i32), [Option<u64>; 4]>,
+}
+
+impl Coverage {
+    /// Empty coverage of the instructions in `program`.
+    pub fn new(program: &World) -> Coverage {
+        let mut coverage = Coverage::default();
+        for ((x, y), opcode) in program.cells() {
+            coverage.cells.insert((x, y), 0);
+            if let Some(ways) = branch_ways(opcode) {
+                coverage
+                    .branches
+                    .insert((x, y), ways.map(|way| way.then_some(0)));
+            }
+        }
+        coverage
+    }
+
+    /// How many times the cell at `(x, y)` ran, or `None` if the program had
+    /// no instruction there.
+    pub fn hits(&self, x: i32, y: i32) -> Option<u64> {
+        self.cells.get(&(x, y)).copied()
+    }
+
+    /// How many times the branch at `(x, y)` went `direction`, or `None` if
+    /// it cannot go that way.
+    pub fn taken(&self, x: i32, y: i32, direction: Direction) -> Option<u64> {
+        let index = DIRECTIONS.iter().position(|&d| d == direction)?;
+        self.branches.get(&(x, y))?[index]
+    }
+
+    /// Cells that ran, and cells in total.
+    pub fn cells_run(&self) -> (usize, usize) {
+        let run = self.cells.values().filter(|&&n| n > 0).count();
+        (run, self.cells.len())
+    }
+
+    /// Branch directions taken, and branch directions in total.
+    pub fn branches_taken(&self) -> (usize, usize) {
+        let ways = self.branches.values().flatten().flatten();
+        let taken = ways.clone().filter(|&&n| n > 0).count();
+        (taken, ways.count())
+    }
+
+    /// Adds the counts from another run of the same program.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&at, &n) in &other.cells {
+            *self.cells.entry(at).or_default() += n;
+        }
+        for (&at, ways) in &other.branches {
+            let mine = self.branches.entry(at).or_default();
+            for (mine, theirs) in mine.iter_mut().zip(ways) {
+                if let Some(n) = theirs {
+                    *mine = Some(mine.unwrap_or(0) + n);
+                }
+            }
+        }
+    }
+
+    /// Draws `program` with cells that never ran on red, branches that did
+    /// not go every way on yellow and everything else that ran on green,
+    /// then the totals.
+    pub fn write_grid(&self, program: &World, out: &mut dyn Write) -> io::Result<()> {
+        program.render_styled(out, program.bounds(), |x, y| match self.hits(x, y)? {
+            0 => Some("\x1b[30;41m"),
+            _ if self
+                .branches
+                .get(&(x, y))
+                .is_some_and(|ways| ways.contains(&Some(0))) =>
+            {
+                Some("\x1b[30;43m")
+            }
+            _ => Some("\x1b[30;42m"),
+        })?;
+        let (run, cells) = self.cells_run();
+        writeln!(
+            out,
+            "cells: {} of {} run ({})",
+            run,
+            cells,
+            percent(run, cells)
+        )?;
+        let (taken, ways) = self.branches_taken();
+        writeln!(
+            out,
+            "branches: {} of {} taken ({})",
+            taken,
+            ways,
+            percent(taken, ways)
+        )
+    }
+
+    /// Writes one lcov-style record for the program at `path`. Besides the
+    /// usual line (`DA`) and branch (`BRDA`) data, where a line is a row of
+    /// the program and a block is a column, `CD:X,Y,HITS` gives each cell.
+    pub fn write_lcov(&self, path: &str, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", path)?;
+        let mut rows: BTreeMap<i32, u64> = BTreeMap::new();
+        let mut cells: Vec<_> = self.cells.iter().collect();
+        cells.sort_by_key(|&(&(x, y), _)| (y, x));
+        for (&(x, y), &n) in cells {
+            writeln!(out, "CD:{},{},{}", x, y, n)?;
+            *rows.entry(y).or_default() += n;
+        }
+        let (run, found) = self.cells_run();
+        writeln!(out, "CF:{}", found)?;
+        writeln!(out, "CH:{}", run)?;
+        let mut branches: Vec<_> = self.branches.iter().collect();
+        branches.sort_by_key(|&(&(x, y), _)| (y, x));
+        for (&(x, y), ways) in branches {
+            let reached = self.hits(x, y).unwrap_or(0) > 0;
+            for (direction, n) in DIRECTIONS.iter().zip(ways) {
+                match n {
+                    Some(n) if reached => {
+                        writeln!(out, "BRDA:{},{},{},{}", y + 1, x, name(*direction), n)?
+                    }
+                    Some(_) => writeln!(out, "BRDA:{},{},{},-", y + 1, x, name(*direction))?,
+                    None => {}
+                }
+            }
+        }
+        let (taken, ways) = self.branches_taken();
+        writeln!(out, "BRF:{}", ways)?;
+        writeln!(out, "BRH:{}", taken)?;
+        for (&y, &n) in &rows {
+            writeln!(out, "DA:{},{}", y + 1, n)?;
+        }
+        writeln!(out, "LF:{}", rows.len())?;
+        writeln!(out, "LH:{}", rows.values().filter(|&&n| n > 0).count())?;
+        writeln!(out, "end_of_record")
+    }
+
+    /// Reads the records that [`Coverage::write_lcov`] writes, by path.
+    /// Lines other than `SF`, `CD`, `BRDA` and `end_of_record` are ignored,
+    /// since the rest can be worked out from those.
+    pub fn read_lcov(text: &str) -> Result<Vec<(String, Coverage)>, String> {
+        let mut records = Vec::new();
+        let mut current: Option<(String, Coverage)> = None;
+        for (number, line) in text.lines().enumerate() {
+            let invalid = || format!("line {}: invalid '{}'", number + 1, line);
+            let (tag, value) = line.split_once(':').unwrap_or((line, ""));
+            match (tag, &mut current) {
+                ("SF", None) => current = Some((value.to_string(), Coverage::default())),
+                ("end_of_record", Some(_)) => records.extend(current.take()),
+                ("CD", Some((_, coverage))) => {
+                    let fields: Vec<&str> = value.split(',').collect();
+                    let [x, y, n] = fields[..] else {
+                        return Err(invalid());
+                    };
+                    let at = (
+                        x.parse().map_err(|_| invalid())?,
+                        y.parse().map_err(|_| invalid())?,
+                    );
+                    coverage.cells.insert(at, n.parse().map_err(|_| invalid())?);
+                }
+                ("BRDA", Some((_, coverage))) => {
+                    let fields: Vec<&str> = value.split(',').collect();
+                    let [line, x, direction, n] = fields[..] else {
+                        return Err(invalid());
+                    };
+                    let y = line.parse::<i32>().map_err(|_| invalid())? - 1;
+                    let x = x.parse().map_err(|_| invalid())?;
+                    let index = DIRECTIONS
+                        .iter()
+                        .position(|&d| name(d) == direction)
+                        .ok_or_else(invalid)?;
+                    let n = match n {
+                        "-" => 0,
+                        n => n.parse().map_err(|_| invalid())?,
+                    };
+                    coverage.branches.entry((x, y)).or_default()[index] = Some(n);
+                }
+                ("SF" | "end_of_record", _) => return Err(invalid()),
+                _ => {}
+            }
+        }
+        match current {
+            Some((path, _)) => Err(format!("record for '{}' has no end_of_record", path)),
+            None => Ok(records),
+        }
+    }
+}
+
+/// Which of [`DIRECTIONS`] a branch instruction can send the IP.
+fn branch_ways(opcode: i32) -> Option<[bool; 4]> {
+    match u8::try_from(opcode).ok()? {
+        b'_' => Some([false, false, true, true]),
+        b'|' => Some([true, true, false, false]),
+        b'?' => Some([true; 4]),
+        _ => None,
+    }
+}
+
+fn name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right",
+    }
+}
+
+fn percent(part: usize, whole: usize) -> String {
+    match whole {
+        0 => "-".to_string(),
+        _ => format!("{:.1}%", part as f64 * 100.0 / whole as f64),
+    }
+}
+
+impl<C> Observer<C> for Coverage {
+    fn instruction(&mut self, event: &Event<C>) -> io::Result<()> {
+        let at = (event.x, event.y);
+        // Cells that `p` filled in after loading are not part of the program.
+        if let Some(n) = self.cells.get_mut(&at) {
+            *n += 1;
+        }
+        if event.mode != Mode::Interpret || branch_ways(event.opcode).is_none() {
+            return Ok(());
+        }
+        let way = DIRECTIONS.iter().position(|d| d.delta() == event.delta);
+        if let (Some(ways), Some(way)) = (self.branches.get_mut(&at), way) {
+            if let Some(n) = &mut ways[way] {
+                *n += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coverage;
+    use crate::befunge::{Befunge, Direction};
+    use crate::random::ScriptedRandom;
+    use crate::world::World;
+    use std::error::Error;
+    use std::io::BufReader;
+
+    fn cover(src: &str, choices: &str) -> Result<(Coverage, World), Box<dyn Error>> {
+        let world = World::from_source_string(src)?;
+        let mut coverage = Coverage::new(&world);
+        let mut running = world.clone();
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        Befunge::new(&mut running, 0, 0, Direction::Right, &mut read, &mut write)
+            .with_random(Box::new(choices.parse::<ScriptedRandom>()?))
+            .with_observer(&mut coverage)
+            .run()?;
+        Ok((coverage, world))
+    }
+
+    // `?` going right prints 1 and going left wraps round to print 2; up
+    // and down would both reach the second row's `_`.
+    const PROGRAM: &str = "?1.@.2\n_@@";
+
+    #[test]
+    fn runs_record_cells_and_branches_and_merge() -> Result<(), Box<dyn Error>> {
+        let (mut coverage, world) = cover(PROGRAM, ">")?;
+        assert_eq!(coverage.hits(1, 0), Some(1));
+        assert_eq!(coverage.hits(4, 0), Some(0));
+        assert_eq!(coverage.hits(0, 1), Some(0));
+        assert_eq!(coverage.taken(0, 0, Direction::Right), Some(1));
+        assert_eq!(coverage.taken(0, 0, Direction::Left), Some(0));
+        assert_eq!(coverage.taken(0, 1, Direction::Up), None);
+        assert_eq!(coverage.cells_run(), (4, 9));
+        assert_eq!(coverage.branches_taken(), (1, 6));
+
+        let (other, _) = cover(PROGRAM, "<")?;
+        coverage.merge(&other);
+        assert_eq!(coverage.hits(3, 0), Some(2));
+        assert_eq!(coverage.cells_run(), (6, 9));
+        assert_eq!(coverage.branches_taken(), (2, 6));
+
+        let mut grid = Vec::new();
+        coverage.write_grid(&world, &mut grid)?;
+        let grid = String::from_utf8(grid)?;
+        assert!(grid.starts_with("\x1b[30;43m?\x1b[0m\x1b[30;42m1\x1b[0m"));
+        assert!(grid.contains("\n\x1b[30;41m_\x1b[0m"));
+        assert!(grid.ends_with("cells: 6 of 9 run (66.7%)\nbranches: 2 of 6 taken (33.3%)\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn lcov_records_round_trip() -> Result<(), Box<dyn Error>> {
+        let (coverage, _) = cover(PROGRAM, "<")?;
+        let mut lcov = Vec::new();
+        coverage.write_lcov("prog.bf", &mut lcov)?;
+        let lcov = String::from_utf8(lcov)?;
+        assert!(lcov.starts_with("TN:\nSF:prog.bf\nCD:0,0,1\nCD:1,0,0\n"));
+        assert!(lcov.contains("\nCF:9\nCH:4\n"));
+        assert!(lcov.contains("\nBRDA:1,0,up,0\nBRDA:1,0,down,0\nBRDA:1,0,left,1\n"));
+        assert!(lcov.contains("\nBRDA:2,0,left,-\nBRDA:2,0,right,-\nBRF:6\nBRH:1\n"));
+        assert!(lcov.ends_with("DA:1,4\nDA:2,0\nLF:2\nLH:1\nend_of_record\n"));
+        let records = Coverage::read_lcov(&lcov)?;
+        assert_eq!(records, vec![("prog.bf".to_string(), coverage)]);
+        assert!(Coverage::read_lcov("SF:a\nCD:1,2\nend_of_record\n").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,383 @@
+//! A line-oriented debugger driving [`Befunge::step`], as used by `--debug`.
+
+use crate::befunge::{Befunge, Step};
+use crate::cell::Cell;
+use crate::error::Result;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  s, step [N]          run N instructions (default 1)
+  c, continue          run until a breakpoint, a watched cell changes or the program ends
+  back [N]             undo the last N instructions (default 1)
+  wrote X Y            show which step last wrote cell (X, Y)
+  b, break X Y         set or clear a breakpoint on cell (X, Y)
+  w, watch X Y         set or clear a watch on cell (X, Y)
+  push VALUE           push VALUE onto the stack
+  pop                  pop the top of the stack
+  set X Y VALUE|'C'    write a number or a character into cell (X, Y)
+  l, list              show the program, stack, breakpoints and watches
+  save FILE            write a snapshot that --resume carries on from
+  q, quit              stop debugging
+  h, help              show this help
+An empty line repeats the previous command.";
+
+/// Why [`Debugger::advance`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pause {
+    Done,
+    Breakpoint,
+    Watch,
+    Halted,
+}
+
+/// Breakpoints and watches, kept across commands.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<(i32, i32)>,
+    watches: BTreeSet<(i32, i32)>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    /// Adds a breakpoint, or removes it if `(x, y)` already has one. Returns
+    /// whether the breakpoint is now set.
+    pub fn toggle_breakpoint(&mut self, x: i32, y: i32) -> bool {
+        toggle(&mut self.breakpoints, (x, y))
+    }
+
+    /// Adds a watch, or removes it if `(x, y)` is already watched. Returns
+    /// whether the watch is now set.
+    pub fn toggle_watch(&mut self, x: i32, y: i32) -> bool {
+        toggle(&mut self.watches, (x, y))
+    }
+
+    /// Reads commands until the program ends, `quit` or end of input, and
+    /// reports to `out`. Program errors end the session and are returned.
+    pub fn run<C: Cell>(
+        &mut self,
+        befunge: &mut Befunge<C>,
+        commands: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<(), C> {
+        self.show(befunge, out)?;
+        let mut previous = String::from("step");
+        loop {
+            write!(out, "(befunge) ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if commands.read_line(&mut line)? == 0 {
+                writeln!(out)?;
+                return Ok(());
+            }
+            let line = match line.trim() {
+                "" => previous.clone(),
+                line => line.to_string(),
+            };
+            previous = line.clone();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match (words[0], &words[1..]) {
+                ("s" | "step", args) => {
+                    let Ok(n) = args.first().copied().unwrap_or("1").parse() else {
+                        writeln!(out, "usage: step [N]")?;
+                        continue;
+                    };
+                    if self.advance(befunge, n, out)? == Pause::Halted {
+                        return self.halted(befunge, out);
+                    }
+                    self.show(befunge, out)?;
+                }
+                ("c" | "continue", []) => {
+                    if self.advance(befunge, u64::MAX, out)? == Pause::Halted {
+                        return self.halted(befunge, out);
+                    }
+                    self.show(befunge, out)?;
+                }
+                ("back", args) => {
+                    let Ok(n) = args.first().copied().unwrap_or("1").parse::<u64>() else {
+                        writeln!(out, "usage: back [N]")?;
+                        continue;
+                    };
+                    if !(0..n).all(|_| befunge.step_back()) {
+                        writeln!(out, "no earlier steps recorded")?;
+                    }
+                    self.show(befunge, out)?;
+                }
+                ("wrote", [x, y]) => {
+                    let (Some(x), Some(y)) = (number(x), number(y)) else {
+                        writeln!(out, "usage: wrote X Y")?;
+                        continue;
+                    };
+                    match befunge.last_write(x, y) {
+                        Some(step) => {
+                            writeln!(out, "({}, {}) was last written at step {}", x, y, step)?
+                        }
+                        None => writeln!(out, "no recorded write to ({}, {})", x, y)?,
+                    }
+                }
+                ("b" | "break", [x, y]) | ("w" | "watch", [x, y]) => {
+                    let (Some(x), Some(y)) = (number(x), number(y)) else {
+                        writeln!(out, "usage: {} X Y", words[0])?;
+                        continue;
+                    };
+                    let (what, set) = if words[0].starts_with('b') {
+                        ("breakpoint", self.toggle_breakpoint(x, y))
+                    } else {
+                        ("watch", self.toggle_watch(x, y))
+                    };
+                    let verb = if set { "set" } else { "cleared" };
+                    writeln!(out, "{} {} at ({}, {})", what, verb, x, y)?;
+                }
+                ("push", [value]) => match value.parse::<C>() {
+                    Ok(value) => {
+                        befunge.stack_mut().push(value);
+                        show_stack(befunge, out)?;
+                    }
+                    Err(_) => writeln!(out, "'{}' is not a number", value)?,
+                },
+                ("pop", []) => {
+                    befunge.stack_mut().pop();
+                    show_stack(befunge, out)?;
+                }
+                ("set", [x, y, value]) => {
+                    let (Some(x), Some(y), Some(value)) = (number(x), number(y), cell_value(value))
+                    else {
+                        writeln!(out, "usage: set X Y VALUE|'C'")?;
+                        continue;
+                    };
+                    befunge.world_mut().set_cell(x, y, value);
+                    self.show(befunge, out)?;
+                }
+                ("save", [path]) => match fs::write(path, befunge.snapshot().to_string()) {
+                    Ok(()) => writeln!(out, "saved a snapshot to {}", path)?,
+                    Err(err) => writeln!(out, "cannot save to {}: {}", path, err)?,
+                },
+                ("l" | "list", []) => self.show(befunge, out)?,
+                ("q" | "quit", []) => return Ok(()),
+                ("h" | "help", []) => writeln!(out, "{}", HELP)?,
+                _ => writeln!(out, "unknown command '{}'; try 'help'", line)?,
+            }
+        }
+    }
+
+    /// Steps up to `n` times, stopping early at a breakpoint, a changed watch
+    /// or the end of the program.
+    fn advance<C: Cell>(
+        &self,
+        befunge: &mut Befunge<C>,
+        n: u64,
+        out: &mut dyn Write,
+    ) -> Result<Pause, C> {
+        for _ in 0..n {
+            let before = self.watched(befunge);
+            if befunge.step()? == Step::Halted {
+                return Ok(Pause::Halted);
+            }
+            let after = self.watched(befunge);
+            let mut pause = Pause::Done;
+            for ((&(x, y), old), new) in self.watches.iter().zip(before).zip(after) {
+                if old != new {
+                    writeln!(
+                        out,
+                        "watch: ({}, {}) changed from {} to {} at step {}",
+                        x,
+                        y,
+                        old,
+                        new,
+                        befunge.steps()
+                    )?;
+                    pause = Pause::Watch;
+                }
+            }
+            if pause == Pause::Done && self.breakpoints.contains(&befunge.coordinates()) {
+                let (x, y) = befunge.coordinates();
+                writeln!(out, "breakpoint at ({}, {})", x, y)?;
+                pause = Pause::Breakpoint;
+            }
+            if pause != Pause::Done {
+                return Ok(pause);
+            }
+        }
+        Ok(Pause::Done)
+    }
+
+    fn watched<C: Cell>(&self, befunge: &Befunge<C>) -> Vec<i32> {
+        self.watches
+            .iter()
+            .map(|&(x, y)| befunge.world().get_cell(x, y))
+            .collect()
+    }
+
+    fn halted<C: Cell>(&self, befunge: &Befunge<C>, out: &mut dyn Write) -> Result<(), C> {
+        self.show(befunge, out)?;
+        writeln!(out, "program halted after {} steps", befunge.steps())?;
+        Ok(())
+    }
+
+    fn show<C: Cell>(&self, befunge: &Befunge<C>, out: &mut dyn Write) -> Result<(), C> {
+        // Funge-98 space can stretch far beyond the screen.
+        let (world, at) = (befunge.world(), befunge.coordinates());
+        let window = world.window(at);
+        world.render_styled(out, window, |x, y| ((x, y) == at).then_some("\x1b[7m"))?;
+        if window != world.bounds() {
+            let ((x0, y0), (x1, y1)) = window;
+            writeln!(out, "showing ({}, {}) to ({}, {})", x0, y0, x1, y1)?;
+        }
+        let (x, y) = at;
+        writeln!(
+            out,
+            "step {}, ip {} at ({}, {}) moving {}, mode {:?}",
+            befunge.steps(),
+            befunge.ip_id(),
+            x,
+            y,
+            befunge.delta(),
+            befunge.mode()
+        )?;
+        show_stack(befunge, out)?;
+        if !self.breakpoints.is_empty() {
+            writeln!(out, "breakpoints {:?}", self.breakpoints)?;
+        }
+        if !self.watches.is_empty() {
+            writeln!(out, "watches {:?}", self.watches)?;
+        }
+        Ok(())
+    }
+}
+
+fn toggle(set: &mut BTreeSet<(i32, i32)>, cell: (i32, i32)) -> bool {
+    !set.remove(&cell) && set.insert(cell)
+}
+
+fn number(word: &str) -> Option<i32> {
+    word.parse().ok()
+}
+
+/// A number, or a character in single quotes.
+fn cell_value(word: &str) -> Option<i32> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('\''), Some(c), Some('\''), None) => Some(c as i32),
+        _ => number(word),
+    }
+}
+
+fn show_stack<C: Cell>(befunge: &Befunge<C>, out: &mut dyn Write) -> Result<(), C> {
+    writeln!(out, "stack {:?}", befunge.stack())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Debugger;
+    use crate::befunge::{Befunge, Direction};
+    use crate::snapshot::Snapshot;
+    use crate::world::{Standard, World};
+    use std::error::Error;
+    use std::fs;
+    use std::io::BufReader;
+
+    /// Runs a debugging session over `src`, returning the debugger's report
+    /// and the program's output.
+    fn debug(src: &str, commands: &str) -> Result<(String, String), Box<dyn Error>> {
+        debug_as(src, Standard::Befunge93, commands)
+    }
+
+    fn debug_as(
+        src: &str,
+        standard: Standard,
+        commands: &str,
+    ) -> Result<(String, String), Box<dyn Error>> {
+        let mut world = World::from_source(src, standard)?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        let mut report = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write)
+            .with_history(100);
+        Debugger::new().run(&mut befunge, &mut commands.as_bytes(), &mut report)?;
+        drop(befunge);
+        Ok((String::from_utf8(report)?, String::from_utf8(write)?))
+    }
+
+    #[test]
+    fn continue_stops_at_breakpoints_and_watched_writes() -> Result<(), Box<dyn Error>> {
+        let (report, output) = debug("1 2+:00p.@", "b 4 0\nw 0 0\nc\nc\nc\n")?;
+        assert!(report.contains("breakpoint at (4, 0)\n"));
+        assert!(report.contains("step 4, ip 0 at (4, 0) moving right, mode Interpret\nstack [3]\n"));
+        assert!(report.contains("watch: (0, 0) changed from 49 to 3 at step 8\n"));
+        assert!(report.ends_with("program halted after 10 steps\n"));
+        assert_eq!(output, "3 ");
+        Ok(())
+    }
+
+    #[test]
+    fn stepping_highlights_the_ip() -> Result<(), Box<dyn Error>> {
+        let (report, _) = debug("12@", "step 2\nq\n")?;
+        assert!(report.contains("12\x1b[7m@\x1b[0m"));
+        Ok(())
+    }
+
+    #[test]
+    fn only_the_cells_round_the_ip_are_drawn() -> Result<(), Box<dyn Error>> {
+        let src = "'@55+:*:*:*0p";
+        let (report, _) = debug_as(src, Standard::Funge98, "s 12\ns\n")?;
+        let row = format!("'@55+:*:*:*0p\x1b[7m \x1b[0m{}\n", " ".repeat(66));
+        assert!(report.contains(&row));
+        assert!(report.contains("showing (0, 0) to (79, 0)\n"));
+        let row = format!("{}\x1b[7m@\x1b[0m\n", " ".repeat(79));
+        assert!(report.contains(&row));
+        assert!(report.contains("showing (99999921, 0) to (100000000, 0)\n"));
+        assert!(report.len() < 2_000);
+        Ok(())
+    }
+
+    #[test]
+    fn the_stack_and_grid_can_be_edited_while_paused() -> Result<(), Box<dyn Error>> {
+        let (report, output) = debug(" .@", "push 7\npop\npush 5\nset 0 0 '9'\nc\n")?;
+        assert!(report.contains("stack [7]\n"));
+        assert!(report.contains("stack []\n"));
+        assert!(report.contains("\x1b[7m9\x1b[0m.@"));
+        assert_eq!(output, "9 ");
+        Ok(())
+    }
+
+    #[test]
+    fn stepping_back_undoes_writes_and_finds_the_writer() -> Result<(), Box<dyn Error>> {
+        let (report, output) = debug(
+            "1 2+:00p.@",
+            "w 0 0\nc\nwrote 0 0\nback 2\nwrote 0 0\nc\nc\n",
+        )?;
+        assert!(report.contains("(0, 0) was last written at step 8\n"));
+        assert!(report
+            .contains("step 6, ip 0 at (6, 0) moving right, mode Interpret\nstack [3, 3, 0]\n"));
+        assert!(report.contains("no recorded write to (0, 0)\n"));
+        assert!(report.ends_with("program halted after 10 steps\n"));
+        assert_eq!(output, "3 ");
+        Ok(())
+    }
+
+    #[test]
+    fn a_paused_program_can_be_saved() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("debugger-save-{}.snap", std::process::id()));
+        let commands = format!("s 3\nsave {}\n", path.display());
+        let (report, _) = debug("123@", &commands)?;
+        assert!(report.contains("saved a snapshot to "));
+        let snapshot: Snapshot = fs::read_to_string(&path)?.parse()?;
+        fs::remove_file(&path)?;
+        assert_eq!(snapshot.steps(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn an_empty_line_repeats_the_previous_command() -> Result<(), Box<dyn Error>> {
+        let (report, _) = debug("12345@", "s 2\n\nbogus\n")?;
+        assert!(report.contains("step 4,"));
+        assert!(report.contains("unknown command 'bogus'"));
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use crate::befunge::Delta;
+use std::error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+pub type Result<T, C = i32> = std::result::Result<T, Error<C>>;
+
+/// What went wrong while executing a program.
+#[derive(Debug)]
+pub enum ErrorKind {
+    DivisionByZero,
+    ModuloByZero,
+    /// An arithmetic result did not fit under [`Overflow::Checked`](crate::Overflow::Checked).
+    ArithmeticOverflow,
+    /// `?` asked a [`DirectionSource`](crate::random::DirectionSource) that had run out.
+    RandomExhausted,
+    /// `&` read a token that is not a valid integer.
+    InvalidInteger(String),
+    Io(io::Error),
+    /// [`Config::max_steps`](crate::Config::max_steps) instructions have run.
+    StepLimitExceeded(u64),
+    /// [`Config::timeout`](crate::Config::timeout) has elapsed.
+    TimeLimitExceeded(Duration),
+    /// Writing would exceed [`Config::max_output`](crate::Config::max_output) bytes.
+    OutputLimitExceeded(u64),
+    /// The stack grew beyond [`Config::max_stack`](crate::Config::max_stack) values.
+    StackLimitExceeded(usize),
+    /// A handler was registered for an instruction the language already has.
+    BuiltinInstruction(char),
+    /// A snapshot uses a fingerprint that is not registered.
+    MissingFingerprint(i32),
+    /// A custom instruction handler failed.
+    Custom(String),
+}
+
+/// Where the interpreter was when an error was raised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location<C = i32> {
+    /// The ID of the IP that failed; 0 unless Funge-98 `t` has run.
+    pub ip: i32,
+    /// The position on the Befunge-93 grid; a negative Funge-98 coordinate
+    /// shows as 0 here.
+    pub x: usize,
+    pub y: usize,
+    /// The position as signed coordinates, which Funge-98 space can need.
+    pub coordinates: (i32, i32),
+    pub delta: Delta,
+    /// The low byte of the cell under the IP, normally an ASCII character.
+    pub opcode: u8,
+    /// The whole cell under the IP, which Funge-98 lets grow past a byte.
+    pub cell: i32,
+    /// The stack as it was when the failing instruction gave up, bottom first.
+    pub stack: Vec<C>,
+}
+
+#[derive(Debug)]
+pub struct Error<C = i32> {
+    kind: ErrorKind,
+    location: Option<Location<C>>,
+}
+
+impl<C> Error<C> {
+    pub fn new(kind: ErrorKind, location: Location<C>) -> Error<C> {
+        Error {
+            kind,
+            location: Some(location),
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// `None` for errors that did not come from a running program.
+    pub fn location(&self) -> Option<&Location<C>> {
+        self.location.as_ref()
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::ModuloByZero => write!(f, "modulo by zero"),
+            ErrorKind::ArithmeticOverflow => write!(f, "arithmetic overflow"),
+            ErrorKind::RandomExhausted => write!(f, "no more directions for '?'"),
+            ErrorKind::InvalidInteger(token) => write!(f, "invalid integer input {:?}", token),
+            ErrorKind::Io(err) => write!(f, "{}", err),
+            ErrorKind::StepLimitExceeded(max) => write!(f, "step limit of {} exceeded", max),
+            ErrorKind::TimeLimitExceeded(max) => {
+                write!(f, "time limit of {:?} exceeded", max)
+            }
+            ErrorKind::OutputLimitExceeded(max) => {
+                write!(f, "output limit of {} bytes exceeded", max)
+            }
+            ErrorKind::BuiltinInstruction(c) => {
+                write!(
+                    f,
+                    "'{}' is a built-in instruction and cannot be redefined",
+                    c
+                )
+            }
+            ErrorKind::MissingFingerprint(id) => {
+                write!(f, "fingerprint {:#x} is not registered", id)
+            }
+            ErrorKind::Custom(message) => write!(f, "{}", message),
+            ErrorKind::StackLimitExceeded(max) => {
+                write!(f, "stack limit of {} values exceeded", max)
+            }
+        }
+    }
+}
+
+/// How many values from the top of the stack a [`Location`] shows.
+const SHOWN_STACK: usize = 8;
+
+impl<C: fmt::Debug> fmt::Display for Location<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opcode = if (0x20..=0x7e).contains(&self.cell) {
+            format!("'{}'", char::from(self.opcode))
+        } else {
+            format!("cell value {}", self.cell)
+        };
+        let (x, y) = self.coordinates;
+        write!(
+            f,
+            "({}, {}) moving {} on {} with stack ",
+            x, y, self.delta, opcode
+        )?;
+        match self.stack.len().checked_sub(SHOWN_STACK) {
+            Some(hidden) if hidden > 0 => {
+                write!(f, "[...")?;
+                for value in &self.stack[hidden..] {
+                    write!(f, ", {:?}", value)?;
+                }
+                write!(f, "] (depth {})", self.stack.len())
+            }
+            _ => write!(f, "{:?}", self.stack),
+        }
+    }
+}
+
+impl<C> fmt::Display for Error<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.location {
+            Some(location) => {
+                let (x, y) = location.coordinates;
+                write!(f, "{} at ({}, {})", self.kind, x, y)
+            }
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl<C: fmt::Debug> error::Error for Error<C> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ErrorKind {
+    fn from(err: io::Error) -> ErrorKind {
+        ErrorKind::Io(err)
+    }
+}
+
+impl<C> From<ErrorKind> for Error<C> {
+    fn from(kind: ErrorKind) -> Error<C> {
+        Error {
+            kind,
+            location: None,
+        }
+    }
+}
+
+impl<C> From<io::Error> for Error<C> {
+    fn from(err: io::Error) -> Error<C> {
+        ErrorKind::Io(err).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorKind, Location};
+    use crate::befunge::Direction;
+
+    #[test]
+    fn located_errors_describe_the_failing_cell() {
+        let location = Location {
+            ip: 0,
+            x: 2,
+            y: 0,
+            coordinates: (2, 0),
+            delta: Direction::Right.delta(),
+            opcode: b'/',
+            cell: i32::from(b'/'),
+            stack: vec![1, 0],
+        };
+        let err: Error = Error::new(ErrorKind::DivisionByZero, location);
+        assert_eq!(err.to_string(), "division by zero at (2, 0)");
+        assert_eq!(
+            err.location().map(ToString::to_string).as_deref(),
+            Some("(2, 0) moving right on '/' with stack [1, 0]")
+        );
+    }
+
+    #[test]
+    fn deep_stacks_are_cut_to_their_top() {
+        let location = Location {
+            ip: 0,
+            x: 0,
+            y: 0,
+            coordinates: (0, 0),
+            delta: Direction::Right.delta(),
+            opcode: b':',
+            cell: i32::from(b':'),
+            stack: (1..=20).collect(),
+        };
+        assert_eq!(
+            location.to_string(),
+            "(0, 0) moving right on ':' with stack [..., 13, 14, 15, 16, 17, 18, 19, 20] (depth 20)"
+        );
+    }
+
+    #[test]
+    fn unlocated_errors_only_describe_the_kind() {
+        let err: Error = Error::from(ErrorKind::InvalidInteger("x".to_string()));
+        assert_eq!(err.to_string(), "invalid integer input \"x\"");
+        assert!(err.location().is_none());
+    }
+}
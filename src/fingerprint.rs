@@ -0,0 +1,546 @@
+//! Funge-98 fingerprints: sets of `A`-`Z` instructions that a program loads
+//! with `(` and unloads with `)`.
+//!
+//! The standard fingerprints below are registered with every [`Befunge`];
+//! embedders add their own with [`Befunge::with_fingerprint`].
+//!
+//! [`Befunge`]: crate::Befunge
+//! [`Befunge::with_fingerprint`]: crate::Befunge::with_fingerprint
+
+use crate::befunge::{Context, Delta};
+use crate::cell::{Arithmetic, Cell};
+use crate::error::ErrorKind;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+pub trait Fingerprint<C: Cell = i32> {
+    /// The ID programs load this by, normally four letters packed by [`id`].
+    fn id(&self) -> i32;
+
+    /// The letters this fingerprint defines, e.g. `"CDILMVX"`.
+    fn instructions(&self) -> &str;
+
+    /// Runs `instruction`, one of [`Fingerprint::instructions`]. The IP moves
+    /// on by its delta afterwards, as for any other instruction.
+    fn execute(&self, instruction: char, context: &mut Context<C>) -> Result<(), ErrorKind>;
+}
+
+/// Packs a fingerprint name such as `"ROMA"` into its ID. Programs load it
+/// with the name reversed, as in `"AMOR"4(`.
+pub fn id(name: &str) -> i32 {
+    name.bytes().fold(0_i32, |id, b| {
+        id.wrapping_mul(256).wrapping_add(i32::from(b))
+    })
+}
+
+/// NULL, ROMA, MODU, BOOL, ORTH, STRN and FIXP.
+pub fn standard<C: Cell>() -> Vec<Rc<dyn Fingerprint<C>>> {
+    vec![
+        Rc::new(Null),
+        Rc::new(Roma),
+        Rc::new(Modu),
+        Rc::new(Bool),
+        Rc::new(Orth),
+        Rc::new(Strn),
+        Rc::new(Fixp),
+    ]
+}
+
+/// Pops `b` and `a`, then pushes `a op b`.
+fn binary<C: Cell>(context: &mut Context<C>, op: Arithmetic) -> Result<(), ErrorKind> {
+    let b = context.pop();
+    let a = context.pop();
+    let overflow = context.config().overflow;
+    let value = a
+        .arithmetic(op, &b, overflow)
+        .ok_or(ErrorKind::ArithmeticOverflow)?;
+    context.push(value);
+    Ok(())
+}
+
+/// Every letter reflects, hiding whatever else was loaded.
+#[derive(Debug, Default)]
+pub struct Null;
+
+impl<C: Cell> Fingerprint<C> for Null {
+    fn id(&self) -> i32 {
+        id("NULL")
+    }
+
+    fn instructions(&self) -> &str {
+        "ABCDEFGHIJKLMNOPQRSTUVWXYZ"
+    }
+
+    fn execute(&self, _instruction: char, context: &mut Context<C>) -> Result<(), ErrorKind> {
+        context.reflect();
+        Ok(())
+    }
+}
+
+/// Roman numerals: each letter pushes its value.
+#[derive(Debug, Default)]
+pub struct Roma;
+
+impl<C: Cell> Fingerprint<C> for Roma {
+    fn id(&self) -> i32 {
+        id("ROMA")
+    }
+
+    fn instructions(&self) -> &str {
+        "CDILMVX"
+    }
+
+    fn execute(&self, instruction: char, context: &mut Context<C>) -> Result<(), ErrorKind> {
+        let value = match instruction {
+            'C' => 100,
+            'D' => 500,
+            'I' => 1,
+            'L' => 50,
+            'M' => 1000,
+            'V' => 5,
+            _ => 10,
+        };
+        context.push(C::from_i32(value));
+        Ok(())
+    }
+}
+
+/// Modulo variants: `M` takes the sign of the divisor, `U` is never
+/// negative and `R` takes the sign of the dividend, like `%`. Modulo by zero
+/// gives zero.
+#[derive(Debug, Default)]
+pub struct Modu;
+
+impl<C: Cell> Fingerprint<C> for Modu {
+    fn id(&self) -> i32 {
+        id("MODU")
+    }
+
+    fn instructions(&self) -> &str {
+        "MRU"
+    }
+
+    fn execute(&self, instruction: char, context: &mut Context<C>) -> Result<(), ErrorKind> {
+        let b = context.pop();
+        let a = context.pop();
+        if b.is_zero() {
+            context.push(C::default());
+            return Ok(());
+        }
+        let overflow = context.config().overflow;
+        let zero = C::default();
+        let r = a
+            .arithmetic(Arithmetic::Rem, &b, overflow)
+            .ok_or(ErrorKind::ArithmeticOverflow)?;
+        // |r| < |b|, so neither adjustment can overflow.
+        let r = match instruction {
+            'M' if !r.is_zero() && (r < zero) != (b < zero) => {
+                r.arithmetic(Arithmetic::Add, &b, overflow)
+            }
+            'U' if r < zero && b < zero => r.arithmetic(Arithmetic::Sub, &b, overflow),
+            'U' if r < zero => r.arithmetic(Arithmetic::Add, &b, overflow),
+            _ => Some(r),
+        };
+        context.push(r.unwrap_or_default());
+        Ok(())
+    }
+}
+
+/// Bitwise logic on whole cells.
+#[derive(Debug, Default)]
+pub struct Bool;
+
+impl<C: Cell> Fingerprint<C> for Bool {
+    fn id(&self) -> i32 {
+        id("BOOL")
+    }
+
+    fn instructions(&self) -> &str {
+        "ANOX"
+    }
+
+    fn execute(&self, instruction: char, context: &mut Context<C>) -> Result<(), ErrorKind> {
+        match instruction {
+            'A' => binary(context, Arithmetic::And),
+            'O' => binary(context, Arithmetic::Or),
+            'X' => binary(context, Arithmetic::Xor),
+            _ => {
+                context.push(C::from_i32(-1));
+                binary(context, Arithmetic::Xor)
+            }
+        }
+    }
+}
+
+/// The Orthogonal easement library: bitwise logic, `g` and `p` with the
+/// coordinates swapped, and direct control over the IP.
+#[derive(Debug, Default)]
+pub struct Orth;
+
+impl<C: Cell> Fingerprint<C> for Orth {
+    fn id(&self) -> i32 {
+        id("ORTH")
+    }
+
+    fn instructions(&self) -> &str {
+        "AEGOPSVWXYZ"
+    }
+
+    fn execute(&self, instruction: char, context: &mut Context<C>) -> Result<(), ErrorKind> {
+        match instruction {
+            'A' => return binary(context, Arithmetic::And),
+            'E' => return binary(context, Arithmetic::Xor),
+            'O' => return binary(context, Arithmetic::Or),
+            'G' => {
+                let x = context.pop().to_i32();
+                let y = context.pop().to_i32();
+                let (ox, oy) = context.storage_offset();
+                let value = context
+                    .world()
+                    .get_cell(x.wrapping_add(ox), y.wrapping_add(oy));
+                context.push(C::from_i32(value));
+            }
+            'P' => {
+                let x = context.pop().to_i32();
+                let y = context.pop().to_i32();
+                let value = context.pop().to_i32();
+                let (ox, oy) = context.storage_offset();
+                context
+                    .world_mut()
+                    .set_cell(x.wrapping_add(ox), y.wrapping_add(oy), value);
+            }
+            'S' => {
+                let string = context.pop_string();
+                context.output(&string)?;
+            }
+            'V' => {
+                let dx = context.pop().to_i32();
+                let Delta { dy, .. } = context.delta();
+                context.set_delta(Delta::new(dx, dy));
+            }
+            'W' => {
+                let dy = context.pop().to_i32();
+                let Delta { dx, .. } = context.delta();
+                context.set_delta(Delta::new(dx, dy));
+            }
+            'X' => {
+                let x = context.pop().to_i32();
+                let (_, y) = context.position();
+                context.set_position(x, y);
+            }
+            'Y' => {
+                let y = context.pop().to_i32();
+                let (x, _) = context.position();
+                context.set_position(x, y);
+            }
+            // Ramp if zero: skip the next cell like `#` when popping zero
+            _ => {
+                if context.pop().is_zero() {
+                    context.forward();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// String handling on `0gnirts`.
+#[derive(Debug, Default)]
+pub struct Strn;
+
+impl<C: Cell> Fingerprint<C> for Strn {
+    fn id(&self) -> i32 {
+        id("STRN")
+    }
+
+    fn instructions(&self) -> &str {
+        "ACDFGILMNPRSV"
+    }
+
+    fn execute(&self, instruction: char, context: &mut Context<C>) -> Result<(), ErrorKind> {
+        match instruction {
+            // Append the second string to the top one
+            'A' => {
+                let mut top = context.pop_string();
+                top.push_str(&context.pop_string());
+                context.push_string(&top);
+            }
+            // Compare the top string with the second
+            'C' => {
+                let top = context.pop_string();
+                let second = context.pop_string();
+                context.push(C::from_i32(top.cmp(&second) as i32));
+            }
+            'D' => {
+                let string = context.pop_string();
+                context.output(&string)?;
+            }
+            // Find the second string in the top one, pushing the rest of the
+            // top string from there on, or an empty string
+            'F' => {
+                let top = context.pop_string();
+                let second = context.pop_string();
+                let found = top.find(&second).map_or("", |i| &top[i..]);
+                context.push_string(found);
+            }
+            // Read a string from Funge-Space, left to right
+            'G' => {
+                let (x, y) = context.pop_vector();
+                let (ox, oy) = context.storage_offset();
+                let (x, y) = (x.wrapping_add(ox), y.wrapping_add(oy));
+                let (_, greatest) = context.world().bounds();
+                let mut string = String::new();
+                for x in x..=greatest.0.max(x) {
+                    match context.world().get_cell(x, y) {
+                        0 => break,
+                        value => string.push(char::from_u32(value as u32).unwrap_or('\u{fffd}')),
+                    }
+                }
+                context.push_string(&string);
+            }
+            // Read a line of input
+            'I' => {
+                let line = context.read_line()?;
+                context.push_string(&line);
+            }
+            'L' | 'R' => {
+                let n = context.pop().to_i32();
+                let string = context.pop_string();
+                if n < 0 {
+                    context.reflect();
+                    return Ok(());
+                }
+                let chars: Vec<char> = string.chars().collect();
+                let n = (n as usize).min(chars.len());
+                let part: String = if instruction == 'L' {
+                    chars[..n].iter().collect()
+                } else {
+                    chars[chars.len() - n..].iter().collect()
+                };
+                context.push_string(&part);
+            }
+            // Take n characters starting at s
+            'M' => {
+                let n = context.pop().to_i32();
+                let s = context.pop().to_i32();
+                let string = context.pop_string();
+                if n < 0 || s < 0 {
+                    context.reflect();
+                    return Ok(());
+                }
+                let part: String = string.chars().skip(s as usize).take(n as usize).collect();
+                context.push_string(&part);
+            }
+            // Push the length, keeping the string
+            'N' => {
+                let string = context.pop_string();
+                context.push_string(&string);
+                context.push(C::from_i32(string.chars().count() as i32));
+            }
+            // Write a string and its terminating zero into Funge-Space
+            'P' => {
+                let (x, y) = context.pop_vector();
+                let string = context.pop_string();
+                let (ox, oy) = context.storage_offset();
+                let (x, y) = (x.wrapping_add(ox), y.wrapping_add(oy));
+                let cells = string.chars().map(|c| c as i32).chain([0]);
+                for (i, value) in cells.enumerate() {
+                    context
+                        .world_mut()
+                        .set_cell(x.wrapping_add(i as i32), y, value);
+                }
+            }
+            // Convert a number to a string
+            'S' => {
+                let value = context.pop();
+                context.push_string(&value.to_string());
+            }
+            // Convert the number a string starts with, or zero
+            _ => {
+                let string = context.pop_string();
+                let string = string.trim_start();
+                let end = string
+                    .char_indices()
+                    .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && (c == '-' || c == '+'))))
+                    .map_or(string.len(), |(i, _)| i);
+                context.push(string[..end].parse().unwrap_or_default());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fixed-point maths on values scaled by 10000, with angles in degrees.
+/// Values pass through 32 bits whatever the cell type.
+#[derive(Debug, Default)]
+pub struct Fixp;
+
+impl<C: Cell> Fingerprint<C> for Fixp {
+    fn id(&self) -> i32 {
+        id("FIXP")
+    }
+
+    fn instructions(&self) -> &str {
+        "ABCDIJNOPQRSTUVX"
+    }
+
+    fn execute(&self, instruction: char, context: &mut Context<C>) -> Result<(), ErrorKind> {
+        let scale = 10_000.0;
+        let value = match instruction {
+            'A' => return binary(context, Arithmetic::And),
+            'O' => return binary(context, Arithmetic::Or),
+            'X' => return binary(context, Arithmetic::Xor),
+            'R' => {
+                let b = context.pop().to_i32();
+                let a = context.pop().to_i32();
+                f64::from(a).powi(b)
+            }
+            _ => {
+                let n = f64::from(context.pop().to_i32());
+                match instruction {
+                    'B' => (n / scale).acos().to_degrees() * scale,
+                    'C' => (n / scale).to_radians().cos() * scale,
+                    'D' if n > 0.0 => f64::from(
+                        context
+                            .random_below(n as u32)
+                            .ok_or(ErrorKind::RandomExhausted)?,
+                    ),
+                    'D' => 0.0,
+                    'I' => (n / scale).to_radians().sin() * scale,
+                    'J' => (n / scale).asin().to_degrees() * scale,
+                    'N' => -n,
+                    'P' => n * PI,
+                    'Q' => n.sqrt(),
+                    'S' => n.signum() * f64::from(n != 0.0),
+                    'T' => (n / scale).to_radians().tan() * scale,
+                    'U' => (n / scale).atan().to_degrees() * scale,
+                    _ => n.abs(),
+                }
+            }
+        };
+        if value.is_nan() {
+            context.reflect();
+        } else {
+            context.push(C::from_i32(value as i32));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{id, Fingerprint};
+    use crate::befunge::{Befunge, Context, Direction};
+    use crate::error::ErrorKind;
+    use crate::random::ScriptedRandom;
+    use crate::world::{Standard, World};
+    use std::error::Error;
+    use std::io::BufReader;
+
+    fn run_with(
+        src: &str,
+        register: for<'w, 'io> fn(Befunge<'w, 'io>) -> Befunge<'w, 'io>,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut world = World::from_source(src, Standard::Funge98)?;
+        let mut read = BufReader::new(&b"typed\n"[..]);
+        let mut write = Vec::new();
+        let befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        let mut befunge = register(befunge);
+        befunge.run()?;
+        drop(befunge);
+        Ok(String::from_utf8(write)?)
+    }
+
+    fn run(src: &str) -> Result<String, Box<dyn Error>> {
+        run_with(src, |befunge| befunge)
+    }
+
+    #[test]
+    fn ids_pack_names_big_endian() {
+        assert_eq!(id("ROMA"), 0x524f_4d41);
+    }
+
+    #[test]
+    fn loading_pushes_the_id_and_defines_letters() -> Result<(), Box<dyn Error>> {
+        assert_eq!(run("\"AMOR\"4(..MD+.@")?, format!("1 {} 1500 ", id("ROMA")));
+        // Unknown fingerprints and undefined letters reflect into the `@`.
+        assert_eq!(run("\"KNUJ\"4(1.@")?, "");
+        assert_eq!(run("#@M1.@")?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn later_fingerprints_shadow_earlier_ones_until_unloaded() -> Result<(), Box<dyn Error>> {
+        assert_eq!(run("\"AMOR\"4(\"LLUN\"4(#@M.@")?, "");
+        assert_eq!(run("\"AMOR\"4($$\"LLUN\"4($$\"LLUN\"4)M.@")?, "1000 ");
+        Ok(())
+    }
+
+    #[test]
+    fn modu_rounds_three_ways() -> Result<(), Box<dyn Error>> {
+        assert_eq!(run("\"UDOM\"4($$703-M.07-3U.07-3R.50M.@")?, "-2 2 -1 0 ");
+        Ok(())
+    }
+
+    #[test]
+    fn bool_works_bitwise() -> Result<(), Box<dyn Error>> {
+        assert_eq!(run("\"LOOB\"4($$65A.65O.65X.0N.@")?, "4 7 3 -1 ");
+        Ok(())
+    }
+
+    #[test]
+    fn orth_prints_strings_and_ramps_on_zero() -> Result<(), Box<dyn Error>> {
+        assert_eq!(run("\"HTRO\"4($$0\"ih\"S0Z@1.@")?, "hi1 ");
+        Ok(())
+    }
+
+    #[test]
+    fn strn_measures_prints_reads_and_converts() -> Result<(), Box<dyn Error>> {
+        assert_eq!(run("\"NRTS\"4($$0\"cba\"N.D0\"21\"V.ID@")?, "3 abc12 typed");
+        Ok(())
+    }
+
+    #[test]
+    fn fixp_takes_roots_and_signs() -> Result<(), Box<dyn Error>> {
+        assert_eq!(run("\"PXIF\"4($$9Q.09-V.5N.09-S.@")?, "3 9 -5 -1 ");
+        Ok(())
+    }
+
+    #[test]
+    fn fixp_draws_from_the_direction_source() -> Result<(), Box<dyn Error>> {
+        let output = run_with("\"PXIF\"4($$44*D.@", |befunge| {
+            befunge.with_random(Box::new(ScriptedRandom::new(vec![
+                Direction::Down,
+                Direction::Left,
+            ])))
+        })?;
+        assert_eq!(output, "6 ");
+        Ok(())
+    }
+
+    struct Answer;
+
+    impl Fingerprint for Answer {
+        fn id(&self) -> i32 {
+            id("ROMA")
+        }
+
+        fn instructions(&self) -> &str {
+            "A"
+        }
+
+        fn execute(&self, _instruction: char, context: &mut Context<i32>) -> Result<(), ErrorKind> {
+            context.push(42);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn embedders_can_replace_standard_fingerprints() -> Result<(), Box<dyn Error>> {
+        let output = run_with("\"AMOR\"4($$A.#@M.@", |befunge| {
+            befunge.with_fingerprint(Answer)
+        })?;
+        assert_eq!(output, "42 ");
+        Ok(())
+    }
+}
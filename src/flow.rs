@@ -0,0 +1,588 @@
+//! A static control-flow graph of a Befunge-93 program, as printed by
+//! `--flow-graph`.
+
+use crate::befunge::{Delta, Direction, Mode};
+use crate::trace::json_string;
+use crate::world::{Standard, World};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// How [`FlowGraph::write`] presents the graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphFormat {
+    /// Graphviz, one node per block.
+    #[default]
+    Dot,
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GraphFormat, String> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(format!(
+                "unknown graph format '{}' (expected dot or json)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for GraphFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Json => "json",
+        })
+    }
+}
+
+/// The IP about to run the cell at `(x, y)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct State {
+    pub x: i32,
+    pub y: i32,
+    pub delta: Delta,
+    pub mode: Mode,
+}
+
+/// States that always run one after another, and the blocks that can follow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub states: Vec<State>,
+    /// Indices into [`FlowGraph::blocks`]; empty for a block ending on `@`.
+    pub successors: Vec<usize>,
+}
+
+/// A `p` that may change code the program runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfModification {
+    /// Where the `p` is.
+    pub at: (i32, i32),
+    /// The cell it writes, or `None` if that depends on more than constants
+    /// pushed earlier in its block.
+    pub target: Option<(i32, i32)>,
+}
+
+/// Every state the program can reach from `(0, 0)` going right, grouped into
+/// blocks, assuming the program does not change as it runs.
+#[derive(Debug, Clone)]
+pub struct FlowGraph {
+    blocks: Vec<Block>,
+    unreachable: Vec<(i32, i32)>,
+    self_modifications: Vec<SelfModification>,
+    infinite_loops: Vec<Vec<(i32, i32)>>,
+    /// The states the infinite loops are made of.
+    trapped: HashSet<State>,
+}
+
+impl FlowGraph {
+    /// Analyses a Befunge-93 `program`; Funge-98 space is not supported.
+    pub fn new(program: &World) -> Result<FlowGraph, String> {
+        if program.standard() != Standard::Befunge93 {
+            return Err("flow graphs are only built for Befunge-93 programs".to_string());
+        }
+        let start = State {
+            x: 0,
+            y: 0,
+            delta: Direction::Right.delta(),
+            mode: Mode::Interpret,
+        };
+        // Every reachable state, in the order first reached.
+        let mut order = vec![start];
+        let mut successors: HashMap<State, Vec<State>> = HashMap::new();
+        let mut predecessors: HashMap<State, Vec<State>> = HashMap::new();
+        predecessors.insert(start, Vec::new());
+        let mut queue = VecDeque::from([start]);
+        while let Some(state) = queue.pop_front() {
+            let next = next_states(program, state);
+            for &to in &next {
+                let from = predecessors.entry(to).or_default();
+                if from.is_empty() && to != start {
+                    order.push(to);
+                    queue.push_back(to);
+                }
+                from.push(state);
+            }
+            successors.insert(state, next);
+        }
+
+        let leaders: HashSet<State> = order
+            .iter()
+            .copied()
+            .filter(|state| {
+                let from = &predecessors[state];
+                *state == start || from.len() != 1 || successors[&from[0]].len() != 1
+            })
+            .collect();
+        let mut blocks = Vec::new();
+        let mut block_of: HashMap<State, usize> = HashMap::new();
+        for &leader in order.iter().filter(|state| leaders.contains(state)) {
+            let mut states = vec![leader];
+            let mut state = leader;
+            while let [next] = successors[&state][..] {
+                if leaders.contains(&next) {
+                    break;
+                }
+                states.push(next);
+                state = next;
+            }
+            block_of.insert(leader, blocks.len());
+            blocks.push(Block {
+                states,
+                successors: Vec::new(),
+            });
+        }
+        for block in &mut blocks {
+            let last = block.states[block.states.len() - 1];
+            block.successors = successors[&last].iter().map(|s| block_of[s]).collect();
+        }
+
+        let executed: HashSet<(i32, i32)> = order.iter().map(|s| (s.x, s.y)).collect();
+        let unreachable = program
+            .cells()
+            .into_iter()
+            .map(|(at, _)| at)
+            .filter(|at| !executed.contains(at))
+            .collect();
+        let self_modifications = blocks
+            .iter()
+            .flat_map(|block| puts(program, block))
+            .filter(|put| put.target.is_none_or(|at| executed.contains(&at)))
+            .collect();
+        let trapped = trapped(program, &order, &predecessors);
+        let infinite_loops = groups(&order, &trapped, &predecessors, &successors);
+        Ok(FlowGraph {
+            blocks,
+            unreachable,
+            self_modifications,
+            infinite_loops,
+            trapped,
+        })
+    }
+
+    /// The first block starts at `(0, 0)`.
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Cells holding something other than a space that no state runs.
+    pub fn unreachable(&self) -> &[(i32, i32)] {
+        &self.unreachable
+    }
+
+    pub fn self_modifications(&self) -> &[SelfModification] {
+        &self.self_modifications
+    }
+
+    /// Groups of cells that, once reached, keep the program running forever
+    /// without `@`, I/O or a `p` that could break the loop. Errors such as a
+    /// division by zero can still end it.
+    pub fn infinite_loops(&self) -> &[Vec<(i32, i32)>] {
+        &self.infinite_loops
+    }
+
+    pub fn write(
+        &self,
+        format: GraphFormat,
+        program: &World,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        match format {
+            GraphFormat::Dot => self.write_dot(program, out),
+            GraphFormat::Json => self.write_json(program, out),
+        }
+    }
+
+    /// Whether the block is part of one of the infinite loops.
+    fn loops(&self, block: &Block) -> bool {
+        self.trapped.contains(&block.states[0])
+    }
+
+    fn write_dot(&self, program: &World, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "digraph befunge {{")?;
+        writeln!(out, "    node [shape=box, fontname=monospace];")?;
+        for (id, block) in self.blocks.iter().enumerate() {
+            let first = &block.states[0];
+            let label = format!(
+                "({}, {}) {}\n{}",
+                first.x,
+                first.y,
+                first.delta,
+                code(program, block)
+            );
+            let mut attributes = vec![format!("label={}", dot_string(&label))];
+            if block.successors.is_empty() {
+                attributes.push("peripheries=2".to_string());
+            }
+            if self.loops(block) {
+                attributes.push("color=red".to_string());
+            }
+            writeln!(out, "    b{} [{}];", id, attributes.join(", "))?;
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            for &to in &block.successors {
+                match block.successors.len() {
+                    1 => writeln!(out, "    b{} -> b{};", id, to)?,
+                    _ => writeln!(
+                        out,
+                        "    b{} -> b{} [label=\"{}\"];",
+                        id, to, self.blocks[to].states[0].delta
+                    )?,
+                }
+            }
+        }
+        writeln!(out, "}}")
+    }
+
+    fn write_json(&self, program: &World, out: &mut dyn Write) -> io::Result<()> {
+        let point = |(x, y): (i32, i32)| format!("[{},{}]", x, y);
+        let points = |cells: &[(i32, i32)]| {
+            let cells: Vec<String> = cells.iter().map(|&at| point(at)).collect();
+            format!("[{}]", cells.join(","))
+        };
+        let blocks: Vec<String> = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(id, block)| {
+                let first = &block.states[0];
+                let cells: Vec<(i32, i32)> = block.states.iter().map(|s| (s.x, s.y)).collect();
+                let successors: Vec<String> =
+                    block.successors.iter().map(ToString::to_string).collect();
+                format!(
+                    "{{\"id\":{},\"start\":{},\"direction\":\"{}\",\"code\":{},\"cells\":{},\
+                     \"successors\":[{}],\"loops\":{}}}",
+                    id,
+                    point((first.x, first.y)),
+                    first.delta,
+                    json_string(&code(program, block)),
+                    points(&cells),
+                    successors.join(","),
+                    self.loops(block)
+                )
+            })
+            .collect();
+        let puts: Vec<String> = self
+            .self_modifications
+            .iter()
+            .map(|put| {
+                format!(
+                    "{{\"at\":{},\"target\":{}}}",
+                    point(put.at),
+                    put.target.map_or("null".to_string(), point)
+                )
+            })
+            .collect();
+        let loops: Vec<String> = self.infinite_loops.iter().map(|l| points(l)).collect();
+        writeln!(
+            out,
+            "{{\"blocks\":[{}],\"unreachable\":{},\"self_modifications\":[{}],\
+             \"infinite_loops\":[{}]}}",
+            blocks.join(","),
+            points(&self.unreachable),
+            puts.join(","),
+            loops.join(",")
+        )
+    }
+}
+
+/// The states that can follow `state`, which is one for all but `_`, `|`,
+/// `?` and `@`.
+fn next_states(program: &World, state: State) -> Vec<State> {
+    let State { x, y, delta, mode } = state;
+    let step = |delta: Delta, mode: Mode, distance: i32| {
+        let (x, y) = program.wrap(
+            x.wrapping_add(delta.dx.wrapping_mul(distance)),
+            y.wrapping_add(delta.dy.wrapping_mul(distance)),
+        );
+        State { x, y, delta, mode }
+    };
+    let opcode = program.get_signed(x, y);
+    if mode == Mode::AsciiPush {
+        return match opcode {
+            b'"' => vec![step(delta, Mode::Interpret, 1)],
+            _ => vec![step(delta, mode, 1)],
+        };
+    }
+    let turn = |directions: &[Direction]| {
+        directions
+            .iter()
+            .map(|d| step(d.delta(), mode, 1))
+            .collect()
+    };
+    match opcode {
+        b'>' => turn(&[Direction::Right]),
+        b'<' => turn(&[Direction::Left]),
+        b'^' => turn(&[Direction::Up]),
+        b'v' => turn(&[Direction::Down]),
+        b'_' => turn(&[Direction::Right, Direction::Left]),
+        b'|' => turn(&[Direction::Down, Direction::Up]),
+        b'?' => turn(&[
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ]),
+        b'#' => vec![step(delta, mode, 2)],
+        b'"' => vec![step(delta, Mode::AsciiPush, 1)],
+        b'@' => Vec::new(),
+        _ => vec![step(delta, mode, 1)],
+    }
+}
+
+/// The instructions a block runs, with string mode in quotes as written.
+fn code(program: &World, block: &Block) -> String {
+    block
+        .states
+        .iter()
+        .map(|s| match program.get_signed(s.x, s.y) {
+            byte @ 0x20..=0x7e => char::from(byte),
+            _ => '□',
+        })
+        .collect()
+}
+
+fn dot_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\l"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push_str("\\l\"");
+    quoted
+}
+
+/// Each `p` in `block`, with its target if the values it pops were pushed as
+/// constants earlier in the block.
+fn puts(program: &World, block: &Block) -> Vec<SelfModification> {
+    let mut found = Vec::new();
+    // Values from before the block are unknown, as is anything read in.
+    let mut stack: Vec<Option<i32>> = Vec::new();
+    let pop = |stack: &mut Vec<Option<i32>>| stack.pop().flatten();
+    for state in &block.states {
+        let opcode = program.get_signed(state.x, state.y);
+        if state.mode == Mode::AsciiPush {
+            if opcode != b'"' {
+                stack.push(Some(i32::from(opcode)));
+            }
+            continue;
+        }
+        match opcode {
+            b'0'..=b'9' => stack.push(Some(i32::from(opcode - b'0'))),
+            b'+' | b'-' | b'*' | b'/' | b'%' | b'`' => {
+                let a = pop(&mut stack);
+                let b = pop(&mut stack);
+                let value = a.zip(b).and_then(|(a, b)| match opcode {
+                    b'+' => b.checked_add(a),
+                    b'-' => b.checked_sub(a),
+                    b'*' => b.checked_mul(a),
+                    b'/' => b.checked_div(a),
+                    b'%' => b.checked_rem(a),
+                    _ => Some(i32::from(b > a)),
+                });
+                stack.push(value);
+            }
+            b'!' => {
+                let value = pop(&mut stack);
+                stack.push(value.map(|v| i32::from(v == 0)));
+            }
+            b':' => {
+                let value = pop(&mut stack);
+                stack.extend([value, value]);
+            }
+            b'\\' => {
+                let a = pop(&mut stack);
+                let b = pop(&mut stack);
+                stack.extend([a, b]);
+            }
+            b'$' | b'.' | b',' | b'_' | b'|' => {
+                pop(&mut stack);
+            }
+            b'&' => stack.push(None),
+            // At the end of input `~` pushes nothing, so what lies below is
+            // unknown from here on.
+            b'~' => stack.clear(),
+            b'g' => {
+                pop(&mut stack);
+                pop(&mut stack);
+                stack.push(None);
+            }
+            b'p' => {
+                let y = pop(&mut stack);
+                let x = pop(&mut stack);
+                pop(&mut stack);
+                found.push(SelfModification {
+                    at: (state.x, state.y),
+                    target: x.zip(y).map(|(x, y)| program.wrap(x, y)),
+                });
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// The states from which neither `@`, I/O nor `p` can be reached.
+fn trapped(
+    program: &World,
+    order: &[State],
+    predecessors: &HashMap<State, Vec<State>>,
+) -> HashSet<State> {
+    let escapes = |state: &State| {
+        state.mode == Mode::Interpret
+            && matches!(
+                program.get_signed(state.x, state.y),
+                b'@' | b'p' | b'.' | b',' | b'&' | b'~'
+            )
+    };
+    let mut escaping: HashSet<State> = order.iter().copied().filter(escapes).collect();
+    let mut queue: VecDeque<State> = escaping.iter().copied().collect();
+    while let Some(state) = queue.pop_front() {
+        for &from in &predecessors[&state] {
+            if escaping.insert(from) {
+                queue.push_back(from);
+            }
+        }
+    }
+    order
+        .iter()
+        .copied()
+        .filter(|state| !escaping.contains(state))
+        .collect()
+}
+
+/// The cells of each connected group of `trapped` states.
+fn groups(
+    order: &[State],
+    trapped: &HashSet<State>,
+    predecessors: &HashMap<State, Vec<State>>,
+    successors: &HashMap<State, Vec<State>>,
+) -> Vec<Vec<(i32, i32)>> {
+    let mut grouped: HashSet<State> = HashSet::new();
+    let mut loops = Vec::new();
+    for &start in order {
+        if !trapped.contains(&start) || !grouped.insert(start) {
+            continue;
+        }
+        let mut cells = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(state) = queue.pop_front() {
+            cells.push((state.x, state.y));
+            let neighbours = predecessors[&state].iter().chain(&successors[&state]);
+            for &next in neighbours {
+                if trapped.contains(&next) && grouped.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        cells.sort_by_key(|&(x, y)| (y, x));
+        cells.dedup();
+        loops.push(cells);
+    }
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlowGraph, GraphFormat, SelfModification};
+    use crate::world::{Standard, World};
+    use std::error::Error;
+
+    #[test]
+    fn conditionals_split_blocks_and_dead_code_is_found() -> Result<(), Box<dyn Error>> {
+        // `_` goes right to print and stop, or left round the torus; the
+        // second row never runs.
+        let world = World::from_source_string("&_.@\n  5.")?;
+        let graph = FlowGraph::new(&world)?;
+        let blocks = graph.blocks();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].states.len(), 2);
+        assert_eq!(blocks[0].successors, vec![1, 2]);
+        assert_eq!(blocks[1].states.len(), 2);
+        assert!(blocks[1].successors.is_empty());
+        assert_eq!(graph.unreachable(), &[(2, 1), (3, 1)]);
+        assert!(graph.infinite_loops().is_empty());
+
+        let mut dot = Vec::new();
+        graph.write(GraphFormat::Dot, &world, &mut dot)?;
+        let dot = String::from_utf8(dot)?;
+        assert!(dot.contains("    b0 [label=\"(0, 0) right\\l&_\\l\"];\n"));
+        assert!(dot.contains("    b1 [label=\"(2, 0) right\\l.@\\l\", peripheries=2];\n"));
+        assert!(dot.contains("    b0 -> b2 [label=\"left\"];\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn silent_loops_and_self_modification_are_flagged() -> Result<(), Box<dyn Error>> {
+        // Strings and bridges are followed past the `@` into a loop on the
+        // second row that never leaves.
+        let world = World::from_source_string("\"a\"#@v\n> 1+ <")?;
+        let graph = FlowGraph::new(&world)?;
+        assert!(graph.self_modifications().is_empty());
+        assert_eq!(
+            graph.infinite_loops(),
+            &[vec![
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (3, 0),
+                (5, 0),
+                (0, 1),
+                (1, 1),
+                (2, 1),
+                (3, 1),
+                (4, 1),
+                (5, 1)
+            ]]
+        );
+        assert_eq!(graph.unreachable(), &[(4, 0)]);
+
+        // A `p` whose target is read in might write anywhere.
+        let world = World::from_source_string("&0p@")?;
+        let graph = FlowGraph::new(&world)?;
+        assert_eq!(
+            graph.self_modifications(),
+            &[SelfModification {
+                at: (2, 0),
+                target: None,
+            }]
+        );
+        // Writing cells that never run is not self-modification.
+        let world = World::from_source_string("901p@")?;
+        assert!(FlowGraph::new(&world)?.self_modifications().is_empty());
+        let world = World::from_source_string("500p@")?;
+        let graph = FlowGraph::new(&world)?;
+        assert_eq!(
+            graph.self_modifications(),
+            &[SelfModification {
+                at: (3, 0),
+                target: Some((0, 0)),
+            }]
+        );
+        let mut json = Vec::new();
+        graph.write(GraphFormat::Json, &world, &mut json)?;
+        assert_eq!(
+            String::from_utf8(json)?,
+            "{\"blocks\":[{\"id\":0,\"start\":[0,0],\"direction\":\"right\",\
+             \"code\":\"500p@\",\"cells\":[[0,0],[1,0],[2,0],[3,0],[4,0]],\
+             \"successors\":[],\"loops\":false}],\"unreachable\":[],\
+             \"self_modifications\":[{\"at\":[3,0],\"target\":[0,0]}],\"infinite_loops\":[]}\n"
+        );
+
+        let world = World::from_source("@", Standard::Funge98)?;
+        assert!(FlowGraph::new(&world).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,423 @@
+//! Golden-file tests: Befunge programs run by the `befunge_rust` binary and
+//! checked against the output and exit status they are expected to give.
+//!
+//! A case is a program `NAME.bf` with, next to it, an optional `NAME.in`
+//! holding its standard input, `NAME.out` holding the standard output it
+//! should write (empty if missing), `NAME.status` holding its exit status
+//! (0 if missing) and `NAME.args` holding options to run it with, such as
+//! `--seed 7 --max-steps 10000`.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a case may run by default before it is killed and counted as
+/// failed.
+pub const TIME_LIMIT: Duration = Duration::from_secs(10);
+
+/// One program and what it is expected to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    /// The program's path relative to the suite's directory, without `.bf`.
+    pub name: String,
+    pub program: PathBuf,
+    /// Options passed to the interpreter before the program.
+    pub args: Vec<String>,
+    pub input: Vec<u8>,
+    pub output: Vec<u8>,
+    pub status: i32,
+    /// How long the program may run; [`TIME_LIMIT`] unless changed.
+    pub time_limit: Duration,
+}
+
+/// What running a case gave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub output: Vec<u8>,
+    /// `None` if the interpreter was killed by a signal.
+    pub status: Option<i32>,
+    pub errors: Vec<u8>,
+    /// The case ran past its time limit and was killed.
+    pub timed_out: bool,
+}
+
+/// Reads `path`, or gives `None` if there is no such file.
+fn read_optional(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+impl Case {
+    /// Loads the case for the program at `program`, named `name`.
+    pub fn load(name: &str, program: &Path) -> io::Result<Case> {
+        let args = read_optional(&program.with_extension("args"))?.unwrap_or_default();
+        let args = String::from_utf8_lossy(&args)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        let input = read_optional(&program.with_extension("in"))?.unwrap_or_default();
+        let output = read_optional(&program.with_extension("out"))?.unwrap_or_default();
+        let status = match read_optional(&program.with_extension("status"))? {
+            Some(bytes) => String::from_utf8_lossy(&bytes)
+                .trim()
+                .parse()
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid exit status for {}", name),
+                    )
+                })?,
+            None => 0,
+        };
+        Ok(Case {
+            name: name.to_string(),
+            program: program.to_path_buf(),
+            args,
+            input,
+            output,
+            status,
+            time_limit: TIME_LIMIT,
+        })
+    }
+
+    /// Runs the program with the interpreter binary at `interpreter`,
+    /// feeding it the case's input, and kills it once its time limit is up.
+    /// `defaults` go before the case's own options, which therefore win.
+    pub fn run(&self, interpreter: &Path, defaults: &[String]) -> io::Result<Outcome> {
+        let mut child = Command::new(interpreter)
+            .args(defaults)
+            .args(&self.args)
+            .arg(&self.program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Written from another thread, so that a program writing a lot
+        // before it reads cannot stall both ends.
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = self.input.clone();
+        let writer = thread::spawn(move || {
+            // The program may exit without reading all of its input.
+            let _ = stdin.write_all(&input);
+        });
+        let stdout = drain(child.stdout.take().expect("stdout is piped"));
+        let stderr = drain(child.stderr.take().expect("stderr is piped"));
+        let started = Instant::now();
+        let mut timed_out = false;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if started.elapsed() > self.time_limit {
+                timed_out = true;
+                child.kill()?;
+                break child.wait()?;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        let _ = writer.join();
+        Ok(Outcome {
+            output: stdout.join().expect("reader thread panicked")?,
+            status: status.code(),
+            errors: stderr.join().expect("reader thread panicked")?,
+            timed_out,
+        })
+    }
+
+    /// Describes how `outcome` differs from what was expected, if it does.
+    pub fn check(&self, outcome: &Outcome) -> Result<(), String> {
+        let mut problems = Vec::new();
+        if outcome.timed_out {
+            problems.push(format!("killed after running for {:?}", self.time_limit));
+        } else if outcome.status != Some(self.status) {
+            let status = outcome
+                .status
+                .map_or("killed by a signal".to_string(), |code| {
+                    format!("exit status {}", code)
+                });
+            problems.push(format!("{}, expected {}", status, self.status));
+        }
+        if outcome.output != self.output {
+            problems.push(format!(
+                "output differs:\n{}",
+                diff(
+                    &String::from_utf8_lossy(&self.output),
+                    &String::from_utf8_lossy(&outcome.output)
+                )
+                .trim_end()
+            ));
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        if !outcome.errors.is_empty() {
+            problems.push(format!(
+                "standard error:\n{}",
+                String::from_utf8_lossy(&outcome.errors).trim_end()
+            ));
+        }
+        Err(problems.join("\n"))
+    }
+
+    /// Makes what `outcome` gave the case's expected output and status.
+    pub fn bless(&self, outcome: &Outcome) -> io::Result<()> {
+        if outcome.timed_out {
+            return Err(io::Error::other(format!("{} ran out of time", self.name)));
+        }
+        let code = outcome
+            .status
+            .ok_or_else(|| io::Error::other(format!("{} was killed by a signal", self.name)))?;
+        fs::write(self.program.with_extension("out"), &outcome.output)?;
+        let status = self.program.with_extension("status");
+        match code {
+            0 => match fs::remove_file(status) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            },
+            code => fs::write(status, format!("{}\n", code)),
+        }
+    }
+}
+
+/// Reads all of `pipe` on another thread.
+fn drain(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        pipe.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    })
+}
+
+/// Unchanged lines shown around each change in a [`diff`].
+const CONTEXT: usize = 3;
+
+/// Past this many line pairs, a [`diff`] only tells where the texts part.
+const MAX_DIFF_PAIRS: usize = 4_000_000;
+
+/// The lines that take `expected` to `actual`: ` ` for a line both have,
+/// `-` for one only `expected` has and `+` for one only `actual` has. Gives
+/// `None` when the texts are too long to compare line by line.
+fn edits<'a>(expected: &[&'a str], actual: &[&'a str]) -> Option<Vec<(char, &'a str)>> {
+    if expected.len().saturating_mul(actual.len()) > MAX_DIFF_PAIRS {
+        return None;
+    }
+    // Longest common subsequences of every pair of suffixes.
+    let width = actual.len() + 1;
+    let mut common = vec![0_u32; (expected.len() + 1) * width];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i * width + j] = if expected[i] == actual[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut edits = Vec::new();
+    while i < expected.len() && j < actual.len() {
+        if expected[i] == actual[j] {
+            edits.push((' ', expected[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if common[(i + 1) * width + j] >= common[i * width + j + 1] {
+            edits.push(('-', expected[i]));
+            i += 1;
+        } else {
+            edits.push(('+', actual[j]));
+            j += 1;
+        }
+    }
+    edits.extend(expected[i..].iter().map(|line| ('-', *line)));
+    edits.extend(actual[j..].iter().map(|line| ('+', *line)));
+    Some(edits)
+}
+
+/// A line diff from `expected` to `actual`, showing each change with a few
+/// lines around it.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.split_inclusive('\n').collect();
+    let new: Vec<&str> = actual.split_inclusive('\n').collect();
+    let mut out = String::from("--- expected\n+++ actual\n");
+    let Some(edits) = edits(&old, &new) else {
+        let line = old.iter().zip(&new).take_while(|(a, b)| a == b).count() + 1;
+        out.push_str(&format!(
+            "(too long to compare; first differs at line {})\n",
+            line
+        ));
+        return out;
+    };
+    let changed: Vec<usize> = (0..edits.len()).filter(|&i| edits[i].0 != ' ').collect();
+    let near = |i: usize| changed.iter().any(|&c| c.abs_diff(i) <= CONTEXT);
+    let mut skipping = false;
+    for (i, (tag, line)) in edits.iter().enumerate() {
+        if !near(i) {
+            if !skipping {
+                out.push_str("...\n");
+            }
+            skipping = true;
+            continue;
+        }
+        skipping = false;
+        out.push(*tag);
+        out.push_str(line);
+        if !line.ends_with('\n') {
+            out.push_str("\n\\ No newline at end\n");
+        }
+    }
+    out
+}
+
+/// Every case under `dir`, in subdirectories too, sorted by name.
+pub fn discover(dir: &Path) -> io::Result<Vec<Case>> {
+    let mut programs = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|extension| extension == "bf") {
+                programs.push(path);
+            }
+        }
+    }
+    programs.sort();
+    programs
+        .iter()
+        .map(|program| {
+            let relative = program.strip_prefix(dir).unwrap_or(program);
+            let name = relative
+                .with_extension("")
+                .iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Case::load(&name, program)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, discover, Outcome};
+    use std::error::Error;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    fn suite(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("befunge-golden-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn cases_are_found_with_their_expectations() -> Result<(), Box<dyn Error>> {
+        let dir = suite("discover");
+        fs::write(dir.join("echo.bf"), "~,@")?;
+        fs::write(dir.join("echo.in"), "x")?;
+        fs::write(dir.join("echo.out"), "x")?;
+        fs::write(dir.join("nested/fail.bf"), "10/@")?;
+        fs::write(dir.join("nested/fail.status"), "1\n")?;
+        fs::write(dir.join("notes.txt"), "not a program")?;
+        let cases = discover(&dir)?;
+        fs::remove_dir_all(&dir)?;
+        let names: Vec<&str> = cases.iter().map(|case| case.name.as_str()).collect();
+        assert_eq!(names, ["echo", "nested/fail"]);
+        assert_eq!(
+            (&cases[0].input[..], &cases[0].output[..]),
+            (&b"x"[..], &b"x"[..])
+        );
+        assert_eq!((cases[0].status, cases[1].status), (0, 1));
+        assert!(cases[1].output.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn mismatches_are_described() -> Result<(), Box<dyn Error>> {
+        let dir = suite("check");
+        fs::write(dir.join("two.bf"), "2.@")?;
+        fs::write(dir.join("two.out"), "2 ")?;
+        let case = discover(&dir)?.remove(0);
+        fs::remove_dir_all(&dir)?;
+        let mut outcome = Outcome {
+            output: b"2 ".to_vec(),
+            status: Some(0),
+            errors: Vec::new(),
+            timed_out: false,
+        };
+        assert_eq!(case.check(&outcome), Ok(()));
+        outcome.output = b"3 ".to_vec();
+        outcome.status = Some(1);
+        outcome.errors = b"oops\n".to_vec();
+        assert_eq!(
+            case.check(&outcome),
+            Err(
+                "exit status 1, expected 0\noutput differs:\n--- expected\n+++ actual\n\
+                 -2 \n\\ No newline at end\n+3 \n\\ No newline at end\n\
+                 standard error:\noops"
+                    .to_string()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cases_that_run_too_long_are_killed() -> Result<(), Box<dyn Error>> {
+        let dir = suite("time-limit");
+        fs::write(dir.join("forever.bf"), "")?;
+        let mut case = discover(&dir)?.remove(0);
+        fs::remove_dir_all(&dir)?;
+        case.time_limit = Duration::from_millis(50);
+        // A shell stands in for an interpreter that never finishes.
+        let hang = ["-c".to_string(), "exec sleep 5".to_string()];
+        let outcome = case.run(Path::new("sh"), &hang)?;
+        assert!(outcome.timed_out);
+        assert_eq!(
+            case.check(&outcome),
+            Err("killed after running for 50ms".to_string())
+        );
+        assert!(case.bless(&outcome).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn diffs_show_changed_lines_in_context() {
+        let expected: String = (1..=12).map(|n| format!("{}\n", n)).collect();
+        let actual = expected.replace("9\n", "nine\n") + "13";
+        assert_eq!(
+            diff(&expected, &actual),
+            "--- expected\n+++ actual\n...\n 6\n 7\n 8\n-9\n+nine\n 10\n 11\n 12\n\
+             +13\n\\ No newline at end\n"
+        );
+    }
+
+    #[test]
+    fn blessing_records_the_outcome() -> Result<(), Box<dyn Error>> {
+        let dir = suite("bless");
+        fs::write(dir.join("seeded.bf"), "?")?;
+        fs::write(dir.join("seeded.args"), "--seed 7\n--max-steps 100")?;
+        fs::write(dir.join("seeded.status"), "2")?;
+        let case = discover(&dir)?.remove(0);
+        assert_eq!(case.args, ["--seed", "7", "--max-steps", "100"]);
+        let outcome = Outcome {
+            output: b"1 ".to_vec(),
+            status: Some(0),
+            errors: Vec::new(),
+            timed_out: false,
+        };
+        case.bless(&outcome)?;
+        let blessed = discover(&dir)?.remove(0);
+        fs::remove_dir_all(&dir)?;
+        assert_eq!((&blessed.output[..], blessed.status), (&b"1 "[..], 0));
+        Ok(())
+    }
+}
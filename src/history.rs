@@ -0,0 +1,74 @@
+//! The undo log behind [`Befunge::step_back`](crate::Befunge::step_back).
+
+use crate::befunge::{Delta, Ip, Mode};
+use std::collections::{HashMap, VecDeque};
+
+/// How the step handed over to the next IP.
+pub(crate) enum Schedule<C> {
+    /// The IP that ran is still current.
+    Stayed,
+    /// The IP that ran went to the back of the queue.
+    Rotated,
+    /// The IP that ran stopped on `@` and was dropped, as it was then.
+    Dropped(Box<Ip<C>>),
+}
+
+/// Everything one step changed, as it was before.
+pub(crate) struct Record<C> {
+    /// The step count before the step, which `k` can raise by more than one.
+    pub(crate) steps: u64,
+    pub(crate) ip: i32,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) delta: Delta,
+    pub(crate) mode: Mode,
+    pub(crate) storage_offset: (i32, i32),
+    /// The stack kept its first `kept` values and lost `popped` above them.
+    pub(crate) kept: usize,
+    pub(crate) popped: Vec<C>,
+    /// Only saved by steps that changed them.
+    pub(crate) stack_stack: Option<Vec<Vec<C>>>,
+    pub(crate) semantics: Option<Box<[Vec<usize>; 26]>>,
+    pub(crate) schedule: Schedule<C>,
+    /// IPs that `t` added to the back of the queue.
+    pub(crate) spawned: i32,
+    /// `(x, y, old value)` for each cell written, oldest first.
+    pub(crate) writes: Vec<(i32, i32, i32)>,
+    /// The step that wrote each cell in `writes` before this one.
+    pub(crate) writers: Vec<Option<u64>>,
+    pub(crate) bounds: ((i32, i32), (i32, i32)),
+    /// Bytes the step read, which stepping back hands back to the input.
+    pub(crate) input: Vec<u8>,
+    pub(crate) exit_code: Option<i32>,
+}
+
+/// The most recent steps' records, and the last step to write each cell.
+pub(crate) struct History<C> {
+    pub(crate) records: VecDeque<Record<C>>,
+    pub(crate) limit: usize,
+    pub(crate) last_write: HashMap<(i32, i32), u64>,
+    /// The running IP's stack before the current step, from `base` up.
+    pub(crate) stack: Vec<C>,
+    pub(crate) base: usize,
+}
+
+impl<C> History<C> {
+    pub(crate) fn new(limit: usize) -> History<C> {
+        History {
+            records: VecDeque::new(),
+            limit,
+            last_write: HashMap::new(),
+            stack: Vec::new(),
+            base: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, record: Record<C>) {
+        if self.records.len() == self.limit {
+            self.records.pop_front();
+        }
+        if self.limit > 0 {
+            self.records.push_back(record);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! A Befunge-93 and Funge-98 interpreter.
+//!
+//! The binary in `src/main.rs` is a thin client over this crate; embedders can
+//! load a [`World`] and drive a [`Befunge`] against any reader and writer.
+
+extern crate rand;
+
+pub mod befunge;
+pub mod cell;
+pub mod compile;
+pub mod config;
+pub mod coverage;
+pub mod debugger;
+pub mod error;
+pub mod fingerprint;
+pub mod flow;
+pub mod golden;
+mod history;
+pub mod observer;
+mod paths;
+pub mod profile;
+pub mod random;
+pub mod snapshot;
+pub mod trace;
+pub mod world;
+
+pub use befunge::{Befunge, Context, Delta, Direction, Handler, Mode, Step};
+pub use cell::{Cell, CellWidth};
+pub use compile::{compile, Target};
+pub use config::{Config, Overflow};
+pub use coverage::Coverage;
+pub use debugger::Debugger;
+pub use error::{Error, ErrorKind, Location, Result};
+pub use fingerprint::Fingerprint;
+pub use flow::{FlowGraph, GraphFormat};
+pub use observer::{Event, Observer};
+pub use profile::{Profile, Report};
+pub use random::{DirectionSource, ScriptedRandom, SeededRandom, ThreadRandom};
+pub use snapshot::Snapshot;
+pub use trace::Trace;
+pub use world::{Standard, World};
+
+pub use num_bigint::BigInt;
@@ -1,52 +1,654 @@
-extern crate rand;
+extern crate befunge_rust;
 
-use befunge::{Befunge, Direction};
+use befunge_rust::golden;
+use befunge_rust::{
+    Befunge, BigInt, Cell, CellWidth, Config, Coverage, Debugger, Direction, DirectionSource,
+    ErrorKind, FlowGraph, GraphFormat, Profile, Report, ScriptedRandom, SeededRandom, Snapshot,
+    Standard, Step, Target, ThreadRandom, Trace, World,
+};
 use std::env;
 use std::fs;
-use std::io::{self, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
 use std::process;
-use world::World;
+use std::str::FromStr;
+use std::time::Duration;
 
-mod befunge;
-mod world;
+/// Opposite corners of a rectangle of cells, least first.
+type Region = ((i32, i32), (i32, i32));
+
+/// How many steps `back` can undo under `--debug`.
+const DEBUG_HISTORY: usize = 100_000;
+
+struct Options {
+    path: String,
+    config: Config,
+    cell: CellWidth,
+    random: Box<dyn DirectionSource>,
+    standard: Standard,
+    debug: bool,
+    input: Option<String>,
+    trace: Option<String>,
+    trace_steps: (u64, u64),
+    trace_region: Option<Region>,
+    trace_stack: usize,
+    /// The file is a snapshot to carry on from rather than a program.
+    resume: bool,
+    checkpoint: Option<String>,
+    checkpoint_every: Option<u64>,
+    profile: Option<Report>,
+    profile_output: Option<String>,
+    /// An lcov-style file that this run's coverage is merged into.
+    coverage: Option<String>,
+    coverage_grid: bool,
+    /// Print the program's flow graph instead of running it.
+    flow_graph: Option<GraphFormat>,
+}
+
+fn usage(program: &str) -> String {
+    format!(
+        "Usage: {} [--std 93|98] [--overflow wrapping|saturating|checked] [--cell i32|i64|big] \
+         [--seed N | --choices ARROWS] [--max-steps N] [--timeout SECONDS] \
+         [--max-output BYTES] [--max-stack N] [--no-cache] [--input FILE] [--debug] \
+         [--trace FILE [--trace-steps FIRST..LAST] [--trace-region X0,Y0,X1,Y1] [--trace-stack N]] \
+         [--checkpoint FILE [--checkpoint-every N]] \
+         [--profile heatmap|csv|json [--profile-output FILE]] \
+         [--coverage FILE [--coverage-grid]] [--flow-graph dot|json] [--resume] [--] <befunge-program-file | snapshot-file>\n\
+         \x20      {} compile [--std 93] [--target c|rust] [--output FILE] <befunge-program-file>\n\
+         \x20      {} test [--bless] [--seed N] [--max-steps N] <directory>\n\
+         To run a program file named compile or test, give its path as ./test or put -- before it.",
+        program, program, program
+    )
+}
+
+fn number<T: FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} requires a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+/// Parses `FIRST..LAST`, where either end may be left out.
+fn step_range(value: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("invalid value '{}' for --trace-steps", value);
+    let (first, last) = value.split_once("..").ok_or_else(invalid)?;
+    let bound = |bound: &str, default| match bound {
+        "" => Ok(default),
+        bound => bound.parse().map_err(|_| invalid()),
+    };
+    Ok((bound(first, 1)?, bound(last, u64::MAX)?))
+}
+
+/// Parses `X0,Y0,X1,Y1`.
+fn region(value: &str) -> Result<Region, String> {
+    let invalid = || format!("invalid value '{}' for --trace-region", value);
+    let numbers = value
+        .split(',')
+        .map(|n| n.trim().parse())
+        .collect::<Result<Vec<i32>, _>>()
+        .map_err(|_| invalid())?;
+    match numbers[..] {
+        [x0, y0, x1, y1] => Ok(((x0.min(x1), y0.min(y1)), (x0.max(x1), y0.max(y1)))),
+        _ => Err(invalid()),
+    }
+}
+
+/// Exit status for a runtime error; each execution limit gets its own.
+fn exit_code(kind: &ErrorKind) -> i32 {
+    match kind {
+        ErrorKind::StepLimitExceeded(_) => 3,
+        ErrorKind::TimeLimitExceeded(_) => 4,
+        ErrorKind::OutputLimitExceeded(_) => 5,
+        ErrorKind::StackLimitExceeded(_) => 6,
+        _ => 1,
+    }
+}
+
+/// Splits `--flag=value` into `--flag value`, so that both are accepted.
+fn split_flags(args: &[String]) -> Vec<String> {
+    args.iter()
+        .flat_map(|arg| match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                vec![flag.to_string(), value.to_string()]
+            }
+            _ => vec![arg.clone()],
+        })
+        .collect()
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let args = split_flags(&args[1..]);
+    let mut path = None;
+    let mut standard = Standard::default();
+    let mut config = Config::default();
+    let mut cell = CellWidth::default();
+    let mut random: Box<dyn DirectionSource> = Box::new(ThreadRandom);
+    let mut debug = false;
+    let mut input = None;
+    let mut trace = None;
+    let mut trace_steps = (1, u64::MAX);
+    let mut trace_region = None;
+    let mut trace_stack = 8;
+    let mut resume = false;
+    let mut checkpoint = None;
+    let mut checkpoint_every = None;
+    let mut profile = None;
+    let mut profile_output = None;
+    let mut coverage = None;
+    let mut coverage_grid = false;
+    let mut flow_graph = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--std" => {
+                let value = iter.next().ok_or("--std requires a value")?;
+                standard = value.parse()?;
+            }
+            "--overflow" => {
+                let value = iter.next().ok_or("--overflow requires a value")?;
+                config = config.overflow(value.parse()?);
+            }
+            "--cell" => {
+                let value = iter.next().ok_or("--cell requires a value")?;
+                cell = value.parse()?;
+            }
+            "--seed" => random = Box::new(SeededRandom::new(number(arg, iter.next())?)),
+            "--choices" => {
+                let value = iter.next().ok_or("--choices requires a value")?;
+                random = Box::new(value.parse::<ScriptedRandom>()?);
+            }
+            "--max-steps" => config = config.max_steps(number(arg, iter.next())?),
+            "--timeout" => {
+                let seconds: f64 = number(arg, iter.next())?;
+                let timeout = Duration::try_from_secs_f64(seconds)
+                    .map_err(|_| format!("invalid value '{}' for {}", seconds, arg))?;
+                config = config.timeout(timeout);
+            }
+            "--max-output" => config = config.max_output(number(arg, iter.next())?),
+            "--max-stack" => config = config.max_stack(number(arg, iter.next())?),
+            "--no-cache" => config = config.uncached(true),
+            "--input" => input = Some(iter.next().ok_or("--input requires a value")?.clone()),
+            "--debug" => debug = true,
+            "--trace" => trace = Some(iter.next().ok_or("--trace requires a value")?.clone()),
+            "--trace-steps" => {
+                trace_steps = step_range(iter.next().ok_or("--trace-steps requires a value")?)?;
+            }
+            "--trace-region" => {
+                let value = iter.next().ok_or("--trace-region requires a value")?;
+                trace_region = Some(region(value)?);
+            }
+            "--trace-stack" => trace_stack = number(arg, iter.next())?,
+            "--resume" => resume = true,
+            "--checkpoint" => {
+                checkpoint = Some(iter.next().ok_or("--checkpoint requires a value")?.clone());
+            }
+            "--checkpoint-every" => checkpoint_every = Some(number(arg, iter.next())?),
+            "--profile" => {
+                let value = iter.next().ok_or("--profile requires a value")?;
+                profile = Some(value.parse()?);
+            }
+            "--profile-output" => {
+                let value = iter.next().ok_or("--profile-output requires a value")?;
+                profile_output = Some(value.clone());
+            }
+            "--coverage" => {
+                coverage = Some(iter.next().ok_or("--coverage requires a value")?.clone());
+            }
+            "--coverage-grid" => coverage_grid = true,
+            "--flow-graph" => {
+                let value = iter.next().ok_or("--flow-graph requires a value")?;
+                flow_graph = Some(value.parse()?);
+            }
+            // What follows is the program file, even one named `compile`
+            // or `test`.
+            "--" => {
+                for arg in iter.by_ref() {
+                    if path.is_some() {
+                        return Err(format!("unexpected argument '{}'", arg));
+                    }
+                    path = Some(arg.clone());
+                }
+            }
+            flag if flag.starts_with("--") => {
+                return Err(format!("unknown option '{}'", flag));
+            }
+            _ if path.is_none() => path = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let path = path.ok_or("missing program file")?;
+    if coverage.is_some() && resume {
+        return Err("--coverage cannot be used with --resume".to_string());
+    }
+    if flow_graph.is_some() && resume {
+        return Err("--flow-graph cannot be used with --resume".to_string());
+    }
+    if coverage_grid && coverage.is_none() {
+        return Err("--coverage-grid requires --coverage".to_string());
+    }
+    Ok(Options {
+        path,
+        config,
+        cell,
+        random,
+        standard,
+        debug,
+        input,
+        trace,
+        trace_steps,
+        trace_region,
+        trace_stack,
+        resume,
+        checkpoint,
+        checkpoint_every,
+        profile,
+        profile_output,
+        coverage,
+        coverage_grid,
+        flow_graph,
+    })
+}
+
+fn fail<C: Cell>(err: befunge_rust::Error<C>) -> ! {
+    match err.location() {
+        Some(location) => eprintln!(
+            "Error executing Befunge program: {}\n  at {}",
+            err.kind(),
+            location
+        ),
+        None => eprintln!("Error executing Befunge program: {}", err),
+    }
+    process::exit(exit_code(err.kind()));
+}
+
+/// Runs to the end, writing a snapshot to `path` every `every` steps and
+/// when a step or time limit stops the program. Limits are checked before an
+/// instruction runs, so that snapshot resumes cleanly.
+fn run_with_checkpoints<C: Cell>(
+    befunge: &mut Befunge<C>,
+    path: &str,
+    every: Option<u64>,
+) -> befunge_rust::Result<(), C> {
+    let save = |befunge: &Befunge<C>| fs::write(path, befunge.snapshot().to_string());
+    loop {
+        match befunge.step() {
+            Ok(Step::Halted) => return Ok(()),
+            Ok(_) => {
+                if every.is_some_and(|n| n > 0 && befunge.steps() % n == 0) {
+                    save(befunge).map_err(ErrorKind::from)?;
+                }
+            }
+            Err(err) => {
+                if let ErrorKind::StepLimitExceeded(_) | ErrorKind::TimeLimitExceeded(_) =
+                    err.kind()
+                {
+                    save(befunge).map_err(ErrorKind::from)?;
+                }
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Adds `coverage` of `program` to the record for it in the file at `path`,
+/// keeping the records of other programs, and returns the merged coverage.
+fn merge_coverage(path: &str, program: &str, coverage: Coverage) -> Result<Coverage, String> {
+    let mut records = match fs::read_to_string(path) {
+        Ok(text) => Coverage::read_lcov(&text)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err.to_string()),
+    };
+    let merged = match records.iter_mut().find(|(name, _)| name == program) {
+        Some((_, existing)) => {
+            existing.merge(&coverage);
+            existing.clone()
+        }
+        None => {
+            records.push((program.to_string(), coverage.clone()));
+            coverage
+        }
+    };
+    let mut out = Vec::new();
+    for (name, record) in &records {
+        record
+            .write_lcov(name, &mut out)
+            .map_err(|err| err.to_string())?;
+    }
+    fs::write(path, out).map_err(|err| err.to_string())?;
+    Ok(merged)
+}
+
+fn execute<C: Cell>(world: &mut World, snapshot: Option<&str>, options: Options) {
+    let stdin = io::stdin();
+    // Under --debug the terminal takes commands, so the program only gets
+    // input from --input.
+    let mut input: Box<dyn BufRead> = match &options.input {
+        Some(path) => match fs::File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(err) => {
+                eprintln!("Error reading file '{}': {}", path, err);
+                process::exit(1);
+            }
+        },
+        None if options.debug => Box::new(io::empty()),
+        None => Box::new(BufReader::new(stdin.lock())),
+    };
+    let mut stdout = io::stdout();
+    // `--trace -` writes the trace to stderr.
+    let mut trace = options.trace.as_ref().map(|path| {
+        let out: Box<dyn Write> = match path.as_str() {
+            "-" => Box::new(io::stderr()),
+            path => match fs::File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(err) => {
+                    eprintln!("Error creating file '{}': {}", path, err);
+                    process::exit(1);
+                }
+            },
+        };
+        let trace = Trace::new(out)
+            .steps(options.trace_steps.0, options.trace_steps.1)
+            .stack_depth(options.trace_stack);
+        match options.trace_region {
+            Some((least, greatest)) => trace.region(least, greatest),
+            None => trace,
+        }
+    });
+    let mut profile = options.profile.map(|_| Profile::new());
+    // The program as loaded, since `p` may change the world as it runs.
+    let program = options.coverage.as_ref().map(|_| world.clone());
+    let mut coverage = program.as_ref().map(Coverage::new);
+
+    let mut befunge = Befunge::<C>::with_stack(
+        world,
+        0,
+        0,
+        Direction::Right,
+        &mut input,
+        &mut stdout,
+        Vec::new(),
+    )
+    .with_config(options.config)
+    .with_random(options.random);
+    if let Some(trace) = &mut trace {
+        befunge = befunge.with_observer(trace);
+    }
+    if let Some(profile) = &mut profile {
+        befunge = befunge.with_observer(profile);
+    }
+    if let Some(coverage) = &mut coverage {
+        befunge = befunge.with_observer(coverage);
+    }
+    if options.debug {
+        befunge = befunge.with_history(DEBUG_HISTORY);
+    }
+    if let Some(snapshot) = snapshot {
+        let snapshot: Snapshot<C> = snapshot.parse().unwrap_or_else(|err| {
+            eprintln!("Error reading snapshot '{}': {}", options.path, err);
+            process::exit(1);
+        });
+        if let Err(err) = befunge.restore(&snapshot) {
+            fail(err);
+        }
+    }
+
+    let result = if options.debug {
+        Debugger::new().run(&mut befunge, &mut io::stdin().lock(), &mut io::stderr())
+    } else if let Some(path) = &options.checkpoint {
+        run_with_checkpoints(&mut befunge, path, options.checkpoint_every)
+    } else {
+        befunge.run()
+    };
+    let exit_code = befunge.exit_code();
+    drop(befunge);
+    if let Some(trace) = trace {
+        let _ = trace.into_inner().flush();
+    }
+    if let (Some(profile), Some(report)) = (profile, options.profile) {
+        let written = match &options.profile_output {
+            Some(path) => fs::File::create(path)
+                .and_then(|file| profile.write(report, world, &mut BufWriter::new(file))),
+            None => profile.write(report, world, &mut io::stderr()),
+        };
+        if let Err(err) = written {
+            eprintln!("Error writing profile: {}", err);
+        }
+    }
+    if let (Some(coverage), Some(program), Some(path)) = (coverage, program, &options.coverage) {
+        let merged = merge_coverage(path, &options.path, coverage).and_then(|merged| match options
+            .coverage_grid
+        {
+            true => merged
+                .write_grid(&program, &mut io::stderr())
+                .map_err(|err| err.to_string()),
+            false => Ok(()),
+        });
+        if let Err(err) = merged {
+            eprintln!("Error writing coverage to '{}': {}", path, err);
+        }
+    }
+    if let Err(err) = result {
+        fail(err);
+    }
+    if let Some(code) = exit_code {
+        process::exit(code);
+    }
+}
+
+/// `compile [--target c|rust] [--output FILE] PROGRAM`, after `compile`.
+fn parse_compile_args(args: &[String]) -> Result<(Target, Option<String>, String), String> {
+    let mut target = Target::default();
+    let mut output = None;
+    let mut path = None;
+    let args = split_flags(args);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--target" => {
+                let value = iter.next().ok_or("--target requires a value")?;
+                target = value.parse()?;
+            }
+            "--std" => {
+                let value = iter.next().ok_or("--std requires a value")?;
+                if value.parse::<Standard>()? != Standard::Befunge93 {
+                    return Err("compile only supports Befunge-93 programs".to_string());
+                }
+            }
+            "--output" | "-o" => {
+                output = Some(iter.next().ok_or("--output requires a value")?.clone());
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option '{}'", flag)),
+            _ if path.is_none() => path = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    Ok((target, output, path.ok_or("missing program file")?))
+}
+
+/// Writes the program compiled to C or Rust to the output file or stdout.
+fn compile_command(args: &[String]) {
+    let (target, output, path) = match parse_compile_args(&args[2..]) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("{}", usage(&args[0]));
+            process::exit(1);
+        }
+    };
+    let world = fs::read_to_string(&path)
+        .map_err(|err| format!("Error reading file '{}': {}", path, err))
+        .and_then(|src| {
+            World::from_source_string(&src)
+                .map_err(|err| format!("Error parsing Befunge program: {}", err))
+        })
+        .and_then(|world| {
+            befunge_rust::compile(&world, &path, target)
+                .map_err(|err| format!("Error compiling Befunge program: {}", err))
+        });
+    let written = world.and_then(|source| match &output {
+        Some(file) => {
+            fs::write(file, source).map_err(|err| format!("Error writing file '{}': {}", file, err))
+        }
+        None => io::stdout()
+            .write_all(source.as_bytes())
+            .map_err(|err| format!("Error writing compiled program: {}", err)),
+    });
+    if let Err(err) = written {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+/// `test [--bless] [--seed N] [--max-steps N] DIRECTORY`, after `test`.
+/// Gives whether to bless, the options every case runs with, and the
+/// directory.
+fn parse_test_args(args: &[String]) -> Result<(bool, Vec<String>, String), String> {
+    let mut bless = false;
+    let mut seed: u64 = 0;
+    let mut max_steps: Option<u64> = None;
+    let mut dir = None;
+    let args = split_flags(args);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            "--seed" => seed = number(arg, iter.next())?,
+            "--max-steps" => max_steps = Some(number(arg, iter.next())?),
+            flag if flag.starts_with('-') => return Err(format!("unknown option '{}'", flag)),
+            _ if dir.is_none() => dir = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    // `?` is always seeded, so that a case gives the same output every run.
+    let mut defaults = vec!["--seed".to_string(), seed.to_string()];
+    if let Some(max_steps) = max_steps {
+        defaults.extend(["--max-steps".to_string(), max_steps.to_string()]);
+    }
+    Ok((bless, defaults, dir.ok_or("missing test directory")?))
+}
+
+/// Runs every golden-file case under the directory given after `test` with
+/// this binary, and reports them as `cargo test` does. Under `--bless`, a
+/// case that does not match has its expected output and status replaced.
+fn test_command(args: &[String]) {
+    let (bless, defaults, dir) = match parse_test_args(&args[2..]) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("{}", usage(&args[0]));
+            process::exit(1);
+        }
+    };
+    let cases = env::current_exe().and_then(|interpreter| {
+        golden::discover(Path::new(&dir)).map(|cases| (interpreter, cases))
+    });
+    let (interpreter, cases) = cases.unwrap_or_else(|err| {
+        eprintln!("Error reading tests in '{}': {}", dir, err);
+        process::exit(1);
+    });
+    let mut failures = Vec::new();
+    let mut blessed = 0;
+    for case in &cases {
+        let checked = case
+            .run(&interpreter, &defaults)
+            .map_err(|err| format!("could not run the interpreter: {}", err))
+            .and_then(|outcome| match case.check(&outcome) {
+                Err(_) if bless => case
+                    .bless(&outcome)
+                    .map(|()| true)
+                    .map_err(|err| format!("could not bless: {}", err)),
+                checked => checked.map(|()| false),
+            });
+        match checked {
+            Ok(false) => println!("test {} ... ok", case.name),
+            Ok(true) => {
+                println!("test {} ... blessed", case.name);
+                blessed += 1;
+            }
+            Err(problem) => {
+                println!("test {} ... FAILED", case.name);
+                failures.push((&case.name, problem));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (name, problem) in &failures {
+            println!("\n---- {} ----\n{}", name, problem);
+        }
+    }
+    let blessed_count = match bless {
+        true => format!("; {} blessed", blessed),
+        false => String::new(),
+    };
+    println!(
+        "\ntest result: {}. {} passed; {} failed{}",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        cases.len() - failures.len() - blessed,
+        failures.len(),
+        blessed_count
+    );
+    if !failures.is_empty() {
+        process::exit(1);
+    }
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <befunge-program-file>", args[0]);
-        process::exit(1);
+    match args.get(1).map(String::as_str) {
+        Some("compile") => {
+            compile_command(&args);
+            return;
+        }
+        Some("test") => {
+            test_command(&args);
+            return;
+        }
+        _ => {}
     }
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("{}", usage(&args[0]));
+            process::exit(1);
+        }
+    };
 
-    let src = match fs::read_to_string(&args[1]) {
+    let src = match fs::read_to_string(&options.path) {
         Ok(content) => content,
         Err(err) => {
-            eprintln!("Error reading file '{}': {}", args[1], err);
+            eprintln!("Error reading file '{}': {}", options.path, err);
             process::exit(1);
         }
     };
 
-    let mut world = match World::from_source_string(&src) {
+    // A resumed program gets its world from the snapshot.
+    let (source, snapshot) = match options.resume {
+        true => ("", Some(src.as_str())),
+        false => (src.as_str(), None),
+    };
+    let mut world = match World::from_source(source, options.standard) {
         Ok(world) => world,
         Err(err) => {
             eprintln!("Error parsing Befunge program: {}", err);
             process::exit(1);
         }
     };
-    let stdin = io::stdin();
-    let mut stdin_lock = BufReader::new(stdin.lock());
-    let mut stdout = io::stdout();
-
-    let mut befunge = Befunge::new(
-        &mut world,
-        0,
-        0,
-        Direction::Right,
-        &mut stdin_lock,
-        &mut stdout,
-    );
-
-    if let Err(err) = befunge.run() {
-        eprintln!("Error executing Befunge program: {}", err);
-        process::exit(1);
+    if let Some(format) = options.flow_graph {
+        let graph = FlowGraph::new(&world).unwrap_or_else(|err| {
+            eprintln!("Error analysing Befunge program: {}", err);
+            process::exit(1);
+        });
+        if let Err(err) = graph.write(format, &world, &mut io::stdout()) {
+            eprintln!("Error writing flow graph: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+    match options.cell {
+        CellWidth::I32 => execute::<i32>(&mut world, snapshot, options),
+        CellWidth::I64 => execute::<i64>(&mut world, snapshot, options),
+        CellWidth::Big => execute::<BigInt>(&mut world, snapshot, options),
     }
 }
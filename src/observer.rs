@@ -0,0 +1,40 @@
+//! Hooks for tools that watch a program run, such as tracers and profilers.
+
+use crate::befunge::{Delta, Mode};
+use std::io;
+use std::time::Duration;
+
+/// One executed instruction.
+#[derive(Debug, Clone, Copy)]
+pub struct Event<'a, C> {
+    /// The number of this instruction, counting from 1.
+    pub step: u64,
+    /// The ID of the IP that ran it.
+    pub ip: i32,
+    pub x: i32,
+    pub y: i32,
+    pub opcode: i32,
+    /// The mode the instruction ran in; in `AsciiPush` it was pushed, not run.
+    pub mode: Mode,
+    /// The delta the IP left the cell with.
+    pub delta: Delta,
+    /// The IP's stack afterwards, bottom first.
+    pub stack: &'a [C],
+    /// Bytes consumed from the input, including the whitespace around an `&`
+    /// number.
+    pub input: &'a [u8],
+    pub output: &'a [u8],
+    /// Cells read by `g`, with the storage offset applied and wrapped on
+    /// the torus.
+    pub gets: &'a [(i32, i32)],
+    /// Cells written by `p`, likewise.
+    pub puts: &'a [(i32, i32)],
+    /// Time spent reading input and writing output.
+    pub io_time: Duration,
+}
+
+/// Receives an [`Event`] for every instruction a [`Befunge`](crate::Befunge)
+/// executes. An error stops the program with [`ErrorKind::Io`](crate::ErrorKind::Io).
+pub trait Observer<C> {
+    fn instruction(&mut self, event: &Event<C>) -> io::Result<()>;
+}
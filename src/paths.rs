@@ -0,0 +1,395 @@
+//! Straight runs of Befunge-93 instructions, decoded once and replayed by
+//! [`Befunge::run`](crate::Befunge::run).
+//!
+//! Each path is also cut into [`Run`]s: stretches of cells that do nothing,
+//! constants and string literals, and `:` before a branch are each done in
+//! one go, falling back to their cells one by one near a step or stack limit.
+
+use crate::befunge::{Delta, Mode};
+use crate::cell::{Arithmetic, Cell};
+use crate::config::Overflow;
+use crate::world::World;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// The IP about to run the cell at `(x, y)`.
+pub(crate) type Key = (i32, i32, Delta, Mode);
+
+/// The longest path decoded at once, so that a loop without branches still
+/// comes back to the interpreter now and then.
+const MAX_LENGTH: usize = 4096;
+
+/// What a decoded cell does to the stack and the outside world; movement is
+/// already worked out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Op {
+    /// Spaces, arrows, `#`, `"` and characters with no meaning still take a
+    /// step.
+    Nop,
+    Push(i32),
+    Arithmetic(Arithmetic),
+    Not,
+    Greater,
+    Duplicate,
+    Swap,
+    Discard,
+    /// `p`, which may end the path early.
+    Put,
+    /// `.`, `,` and `g`, run by the interpreter's own match.
+    Instruction(i32),
+    /// `_`, or `|` if `vertical`; always the last op of a path.
+    Branch {
+        vertical: bool,
+    },
+}
+
+/// A stretch of a path done in one go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Fused {
+    /// Cells that only move the IP.
+    Skip,
+    /// Digits and string characters, with arithmetic on them already done.
+    Push(Box<[i32]>),
+    /// `:` and the branch after it, which then leaves the stack as it was.
+    DuplicateBranch { vertical: bool },
+}
+
+/// Consecutive ops of a path, and what they add up to if they can be fused.
+#[derive(Debug)]
+pub(crate) struct Run {
+    pub(crate) fused: Option<Fused>,
+    /// Indices into [`Path::ops`].
+    pub(crate) ops: Range<usize>,
+    /// How much deeper the stack gets while the ops run one by one.
+    pub(crate) peak: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Decoded {
+    /// Where the IP is, and how it is moving, when the op runs.
+    pub(crate) at: Key,
+    pub(crate) op: Op,
+}
+
+/// The ops from one IP state up to the next cell that needs the full
+/// interpreter: a branch, `@`, input, a host-defined instruction or a cell
+/// that `p` keeps rewriting.
+#[derive(Debug)]
+pub(crate) struct Path {
+    pub(crate) ops: Vec<Decoded>,
+    pub(crate) runs: Vec<Run>,
+    /// Where the IP is once the ops have run, unless they end in a branch.
+    pub(crate) end: Key,
+}
+
+impl Path {
+    fn new(ops: Vec<Decoded>, end: Key) -> Path {
+        Path {
+            runs: fuse(&ops),
+            ops,
+            end,
+        }
+    }
+
+    fn covers(&self, x: i32, y: i32) -> bool {
+        self.ops.iter().any(|d| (d.at.0, d.at.1) == (x, y))
+    }
+}
+
+/// A cell that some cached path runs.
+const COVERED: u8 = 1;
+/// A cell that `p` changed under a cached path. Paths stop short of it from
+/// then on, so that code which keeps rewriting itself is not decoded again
+/// on every turn.
+const VOLATILE: u8 = 2;
+
+/// Decoded paths by where they start, and what is known about each cell of
+/// the torus.
+pub(crate) struct PathCache {
+    paths: HashMap<Key, Rc<Path>>,
+    /// `COVERED` and `VOLATILE` flags, row by row.
+    cells: Vec<u8>,
+    width: usize,
+}
+
+impl PathCache {
+    pub(crate) fn new(world: &World) -> PathCache {
+        PathCache {
+            paths: HashMap::new(),
+            cells: vec![0; world.width() * world.height()],
+            width: world.width(),
+        }
+    }
+
+    fn flags(&mut self, x: i32, y: i32) -> &mut u8 {
+        &mut self.cells[y as usize * self.width + x as usize]
+    }
+
+    /// The path starting at `key`, decoding it from `world` the first time.
+    /// `custom` tells which characters the host has defined.
+    pub(crate) fn path(
+        &mut self,
+        world: &World,
+        key: Key,
+        custom: impl Fn(char) -> bool,
+    ) -> Rc<Path> {
+        if let Some(path) = self.paths.get(&key) {
+            return Rc::clone(path);
+        }
+        let volatile =
+            |x: i32, y: i32| self.cells[y as usize * self.width + x as usize] & VOLATILE != 0;
+        let path = Rc::new(decode(world, key, custom, volatile));
+        for decoded in &path.ops {
+            *self.flags(decoded.at.0, decoded.at.1) |= COVERED;
+        }
+        self.paths.insert(key, Rc::clone(&path));
+        path
+    }
+
+    /// Forgets every path through `(x, y)` after `p` changed it, and tells
+    /// whether `current` was one of them.
+    pub(crate) fn invalidate(&mut self, x: i32, y: i32, current: &Path) -> bool {
+        let flags = self.flags(x, y);
+        if *flags & COVERED == 0 {
+            return false;
+        }
+        *flags = VOLATILE;
+        self.paths.retain(|_, path| !path.covers(x, y));
+        current.covers(x, y)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.paths.clear();
+        self.cells.fill(0);
+    }
+}
+
+/// Decodes from `start` up to a cell that needs the interpreter or is
+/// `volatile`. A loop without branches ends the path when it comes back to
+/// `start`, or is unrolled up to `MAX_LENGTH` if it never does.
+fn decode(
+    world: &World,
+    start: Key,
+    custom: impl Fn(char) -> bool,
+    volatile: impl Fn(i32, i32) -> bool,
+) -> Path {
+    let mut ops = Vec::new();
+    let (mut x, mut y, mut delta, mut mode) = start;
+    while ops.len() < MAX_LENGTH {
+        let at = (x, y, delta, mode);
+        if volatile(x, y) || (at == start && !ops.is_empty()) {
+            return Path::new(ops, at);
+        }
+        let opcode = world.get_cell(x, y);
+        let mut distance = 1;
+        let op = match (mode, u8::try_from(opcode).map(char::from)) {
+            (Mode::AsciiPush, Ok('"')) => {
+                mode = Mode::Interpret;
+                Op::Nop
+            }
+            (Mode::AsciiPush, _) => Op::Push(opcode),
+            (_, Ok('0'..='9')) => Op::Push(opcode - i32::from(b'0')),
+            (_, Ok('+')) => Op::Arithmetic(Arithmetic::Add),
+            (_, Ok('-')) => Op::Arithmetic(Arithmetic::Sub),
+            (_, Ok('*')) => Op::Arithmetic(Arithmetic::Mul),
+            (_, Ok('/')) => Op::Arithmetic(Arithmetic::Div),
+            (_, Ok('%')) => Op::Arithmetic(Arithmetic::Rem),
+            (_, Ok('!')) => Op::Not,
+            (_, Ok('`')) => Op::Greater,
+            (_, Ok(':')) => Op::Duplicate,
+            (_, Ok('\\')) => Op::Swap,
+            (_, Ok('$')) => Op::Discard,
+            (_, Ok('p')) => Op::Put,
+            (_, Ok('.' | ',' | 'g')) => Op::Instruction(opcode),
+            (_, Ok(arrow @ ('>' | '<' | '^' | 'v'))) => {
+                delta = match arrow {
+                    '>' => Delta::new(1, 0),
+                    '<' => Delta::new(-1, 0),
+                    '^' => Delta::new(0, -1),
+                    _ => Delta::new(0, 1),
+                };
+                Op::Nop
+            }
+            (_, Ok('"')) => {
+                mode = Mode::AsciiPush;
+                Op::Nop
+            }
+            (_, Ok('#')) => {
+                distance = 2;
+                Op::Nop
+            }
+            (_, Ok(branch @ ('_' | '|'))) => {
+                let vertical = branch == '|';
+                ops.push(Decoded {
+                    at,
+                    op: Op::Branch { vertical },
+                });
+                return Path::new(ops, at);
+            }
+            (_, Ok('?' | '@' | '&' | '~')) => return Path::new(ops, at),
+            (_, Ok(c)) if custom(c) => return Path::new(ops, at),
+            _ => Op::Nop,
+        };
+        ops.push(Decoded { at, op });
+        for _ in 0..distance {
+            (x, y) = world.next_position(x, y, delta.dx, delta.dy);
+        }
+    }
+    Path::new(ops, (x, y, delta, mode))
+}
+
+/// Cuts `ops` into runs, fusing what can be.
+fn fuse(ops: &[Decoded]) -> Vec<Run> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < ops.len() {
+        let start = i;
+        let (fused, peak) = match (ops[i].op, ops.get(i + 1).map(|d| d.op)) {
+            (Op::Nop, _) => {
+                while ops.get(i).is_some_and(|d| d.op == Op::Nop) {
+                    i += 1;
+                }
+                (Some(Fused::Skip), 0)
+            }
+            (Op::Push(_), _) => {
+                let (values, peak) = constants(ops, &mut i);
+                (Some(Fused::Push(values.into())), peak)
+            }
+            (Op::Duplicate, Some(Op::Branch { vertical })) => {
+                i += 2;
+                // `:` on an empty stack pushes two zeroes.
+                (Some(Fused::DuplicateBranch { vertical }), 2)
+            }
+            _ => {
+                i += 1;
+                (None, 0)
+            }
+        };
+        runs.push(Run {
+            fused,
+            ops: start..i,
+            peak,
+        });
+    }
+    runs
+}
+
+/// Reads pushes from `ops[*i]` on, skipping cells that do nothing and
+/// folding arithmetic on two values pushed here. Gives back the values left
+/// and how deep they got.
+fn constants(ops: &[Decoded], i: &mut usize) -> (Vec<i32>, usize) {
+    let mut values: Vec<i32> = Vec::new();
+    let mut peak = 0;
+    while let Some(decoded) = ops.get(*i) {
+        match decoded.op {
+            Op::Push(value) => {
+                values.push(value);
+                peak = peak.max(values.len());
+            }
+            Op::Nop => {}
+            // Folded only when the result is exact for every cell type.
+            Op::Arithmetic(op) if values.len() >= 2 => {
+                let (b, a) = (values[values.len() - 2], values[values.len() - 1]);
+                match b.arithmetic(op, &a, Overflow::Checked) {
+                    Some(value) => {
+                        values.truncate(values.len() - 2);
+                        values.push(value);
+                    }
+                    None => break,
+                }
+            }
+            _ => break,
+        }
+        *i += 1;
+    }
+    (values, peak)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fused, Op, PathCache};
+    use crate::befunge::{Delta, Mode};
+    use crate::cell::Arithmetic;
+    use crate::world::World;
+    use std::error::Error;
+    use std::rc::Rc;
+
+    const START: (i32, i32, Delta, Mode) = (0, 0, Delta { dx: 1, dy: 0 }, Mode::Interpret);
+
+    #[test]
+    fn paths_follow_arrows_bridges_and_strings_up_to_a_branch() -> Result<(), Box<dyn Error>> {
+        let world = World::from_source_string("1#2\"a\"v\n   x_+<")?;
+        let mut cache = PathCache::new(&world);
+        let path = cache.path(&world, START, |_| false);
+        let ops: Vec<Op> = path.ops.iter().map(|d| d.op).collect();
+        assert_eq!(
+            ops,
+            [
+                Op::Push(1),
+                Op::Nop,
+                Op::Nop,
+                Op::Push(i32::from(b'a')),
+                Op::Nop,
+                Op::Nop,
+                Op::Nop,
+                Op::Arithmetic(Arithmetic::Add),
+                Op::Branch { vertical: false },
+            ]
+        );
+        assert_eq!(path.end, (4, 1, Delta::new(-1, 0), Mode::Interpret));
+        // Host-defined characters end a path too.
+        let world = World::from_source_string("12x")?;
+        let path = cache.path(&world, (1, 0, Delta::new(1, 0), Mode::Interpret), |c| {
+            c == 'x'
+        });
+        assert_eq!(path.ops.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn runs_fuse_spaces_constants_and_duplicated_branches() -> Result<(), Box<dyn Error>> {
+        let world = World::from_source_string("  \"ab\" 23*:_")?;
+        let mut cache = PathCache::new(&world);
+        let path = cache.path(&world, START, |_| false);
+        let runs: Vec<_> = path
+            .runs
+            .iter()
+            .map(|run| (run.fused.clone(), run.ops.clone(), run.peak))
+            .collect();
+        let (a, b) = (i32::from(b'a'), i32::from(b'b'));
+        assert_eq!(
+            runs,
+            [
+                (Some(Fused::Skip), 0..3, 0),
+                (Some(Fused::Push([a, b, 6].into())), 3..10, 4),
+                (Some(Fused::DuplicateBranch { vertical: false }), 10..12, 2),
+            ]
+        );
+        // Division by zero is left for the interpreter to report.
+        let world = World::from_source_string("10/@")?;
+        let path = PathCache::new(&world).path(&world, START, |_| false);
+        assert_eq!(path.runs[0].fused, Some(Fused::Push([1, 0].into())));
+        assert_eq!(path.runs[1].fused, None);
+        Ok(())
+    }
+
+    #[test]
+    fn writes_forget_only_the_paths_through_the_cell() -> Result<(), Box<dyn Error>> {
+        let world = World::from_source_string("12v\n  >3@")?;
+        let mut cache = PathCache::new(&world);
+        let second_key = (3, 1, Delta::new(1, 0), Mode::Interpret);
+        let first = cache.path(&world, START, |_| false);
+        let second = cache.path(&world, second_key, |_| false);
+        assert!(!cache.invalidate(0, 0, &second));
+        assert!(Rc::ptr_eq(
+            &second,
+            &cache.path(&world, second_key, |_| false)
+        ));
+        assert!(!Rc::ptr_eq(&first, &cache.path(&world, START, |_| false)));
+        // The rewritten cell is left to the interpreter from now on.
+        assert!(cache.path(&world, START, |_| false).ops.is_empty());
+        assert!(cache.invalidate(3, 1, &second));
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+//! Execution counts per cell and per opcode, as collected by `--profile`.
+
+use crate::observer::{Event, Observer};
+use crate::trace::json_string;
+use crate::world::World;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// 256-colour backgrounds from cold to hot.
+const HEAT: [u8; 8] = [17, 19, 27, 37, 71, 178, 208, 196];
+
+/// How [`Profile::write`] presents the counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Report {
+    /// The program coloured by how often each cell ran, then a summary.
+    #[default]
+    Heatmap,
+    /// One row per cell that ran or was read or written.
+    Csv,
+    Json,
+}
+
+impl FromStr for Report {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Report, String> {
+        match s {
+            "heatmap" => Ok(Report::Heatmap),
+            "csv" => Ok(Report::Csv),
+            "json" => Ok(Report::Json),
+            _ => Err(format!(
+                "unknown profile format '{}' (expected heatmap, csv or json)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Report::Heatmap => "heatmap",
+            Report::Csv => "csv",
+            Report::Json => "json",
+        })
+    }
+}
+
+/// What happened at one cell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CellCounts {
+    pub executions: u64,
+    /// Times `g` read the cell.
+    pub gets: u64,
+    /// Times `p` wrote the cell.
+    pub puts: u64,
+    /// The opcode last executed here.
+    pub opcode: i32,
+}
+
+/// Counts every instruction it observes.
+#[derive(Debug, Default)]
+pub struct Profile {
+    cells: HashMap<(i32, i32), CellCounts>,
+    opcodes: BTreeMap<i32, u64>,
+    steps: u64,
+    io_time: Duration,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    pub fn cell(&self, x: i32, y: i32) -> CellCounts {
+        self.cells.get(&(x, y)).copied().unwrap_or_default()
+    }
+
+    /// Every cell with a count, row by row.
+    pub fn cells(&self) -> Vec<((i32, i32), CellCounts)> {
+        let mut cells: Vec<_> = self.cells.iter().map(|(&at, &c)| (at, c)).collect();
+        cells.sort_by_key(|&((x, y), _)| (y, x));
+        cells
+    }
+
+    /// How many times `opcode` ran.
+    pub fn opcode(&self, opcode: i32) -> u64 {
+        self.opcodes.get(&opcode).copied().unwrap_or_default()
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Total time spent reading input and writing output.
+    pub fn io_time(&self) -> Duration {
+        self.io_time
+    }
+
+    /// Opcodes by how often they ran, most first.
+    fn hottest_opcodes(&self) -> Vec<(i32, u64)> {
+        let mut opcodes: Vec<_> = self.opcodes.iter().map(|(&op, &n)| (op, n)).collect();
+        opcodes.sort_by_key(|&(op, n)| (std::cmp::Reverse(n), op));
+        opcodes
+    }
+
+    pub fn write(&self, report: Report, world: &World, out: &mut dyn Write) -> io::Result<()> {
+        match report {
+            Report::Heatmap => self.write_heatmap(world, out),
+            Report::Csv => self.write_csv(out),
+            Report::Json => self.write_json(out),
+        }
+    }
+
+    /// Draws `world` with each executed cell's background running from blue
+    /// to red on a log scale; cells only read or written are underlined.
+    fn write_heatmap(&self, world: &World, out: &mut dyn Write) -> io::Result<()> {
+        let max = self.cells.values().map(|c| c.executions).max().unwrap_or(0);
+        world.render_styled(out, world.bounds(), |x, y| {
+            let counts = self.cells.get(&world.wrap(x, y))?;
+            if counts.executions == 0 {
+                return Some("\x1b[4m".to_string());
+            }
+            Some(format!(
+                "\x1b[30;48;5;{}m",
+                HEAT[heat(counts.executions, max)]
+            ))
+        })?;
+        write!(out, "executions 1 ")?;
+        for colour in HEAT {
+            write!(out, "\x1b[48;5;{}m \x1b[0m", colour)?;
+        }
+        writeln!(out, " {}; underlined cells were only read or written", max)?;
+        writeln!(
+            out,
+            "{} steps, {:.3}s in I/O",
+            self.steps,
+            self.io_time.as_secs_f64()
+        )?;
+        let top: Vec<String> = self
+            .hottest_opcodes()
+            .iter()
+            .take(10)
+            .map(|&(opcode, n)| format!("'{}' {}", show(opcode), n))
+            .collect();
+        writeln!(out, "hottest opcodes: {}", top.join(", "))
+    }
+
+    fn write_csv(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "x,y,opcode,executions,gets,puts")?;
+        for ((x, y), c) in self.cells() {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                x, y, c.opcode, c.executions, c.gets, c.puts
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_json(&self, out: &mut dyn Write) -> io::Result<()> {
+        let opcodes: Vec<String> = self
+            .hottest_opcodes()
+            .iter()
+            .map(|&(opcode, n)| {
+                format!(
+                    "{{\"opcode\":{},\"char\":{},\"count\":{}}}",
+                    opcode,
+                    json_string(&show(opcode)),
+                    n
+                )
+            })
+            .collect();
+        let cells: Vec<String> = self
+            .cells()
+            .iter()
+            .map(|((x, y), c)| {
+                format!(
+                    "{{\"x\":{},\"y\":{},\"opcode\":{},\"executions\":{},\"gets\":{},\"puts\":{}}}",
+                    x, y, c.opcode, c.executions, c.gets, c.puts
+                )
+            })
+            .collect();
+        writeln!(
+            out,
+            "{{\"steps\":{},\"io_seconds\":{},\"opcodes\":[{}],\"cells\":[{}]}}",
+            self.steps,
+            self.io_time.as_secs_f64(),
+            opcodes.join(","),
+            cells.join(",")
+        )
+    }
+}
+
+/// Which of the `HEAT` colours `n` executions get, out of `max`.
+fn heat(n: u64, max: u64) -> usize {
+    if max <= 1 {
+        return HEAT.len() - 1;
+    }
+    let scale = (n as f64).ln() / (max as f64).ln();
+    (scale * (HEAT.len() - 1) as f64).round() as usize
+}
+
+/// An opcode as its character, or its number if it is not printable.
+fn show(opcode: i32) -> String {
+    match u8::try_from(opcode) {
+        Ok(byte @ 0x20..=0x7e) => char::from(byte).to_string(),
+        _ => opcode.to_string(),
+    }
+}
+
+impl<C> Observer<C> for Profile {
+    fn instruction(&mut self, event: &Event<C>) -> io::Result<()> {
+        self.steps += 1;
+        self.io_time += event.io_time;
+        *self.opcodes.entry(event.opcode).or_default() += 1;
+        let cell = self.cells.entry((event.x, event.y)).or_default();
+        cell.executions += 1;
+        cell.opcode = event.opcode;
+        for &at in event.gets {
+            self.cells.entry(at).or_default().gets += 1;
+        }
+        for &at in event.puts {
+            self.cells.entry(at).or_default().puts += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CellCounts, Profile, Report};
+    use crate::befunge::{Befunge, Direction};
+    use crate::world::World;
+    use std::error::Error;
+    use std::io::BufReader;
+
+    fn profile(src: &str) -> Result<(Profile, World), Box<dyn Error>> {
+        let mut profile = Profile::new();
+        let mut world = World::from_source_string(src)?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write)
+            .with_observer(&mut profile)
+            .run()?;
+        Ok((profile, world))
+    }
+
+    #[test]
+    fn cells_opcodes_and_cell_traffic_are_counted() -> Result<(), Box<dyn Error>> {
+        // Counts down from 3, keeping the counter in cell (0, 2).
+        let (profile, _) = profile("302p>02g1-:02pv\n    ^         _@")?;
+        assert_eq!(profile.cell(4, 0).executions, 3);
+        assert_eq!(profile.opcode(i32::from(b'p')), 4);
+        assert_eq!(
+            profile.cell(0, 2),
+            CellCounts {
+                executions: 0,
+                gets: 3,
+                puts: 4,
+                opcode: 0,
+            }
+        );
+        assert_eq!(
+            profile.steps(),
+            profile.cells().iter().map(|(_, c)| c.executions).sum()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reports_come_as_a_heatmap_csv_or_json() -> Result<(), Box<dyn Error>> {
+        let (profile, world) = profile("11p@")?;
+        let mut csv = Vec::new();
+        profile.write(Report::Csv, &world, &mut csv)?;
+        assert_eq!(
+            String::from_utf8(csv)?,
+            "x,y,opcode,executions,gets,puts\n0,0,49,1,0,0\n1,0,49,1,0,0\n\
+             2,0,112,1,0,0\n3,0,64,1,0,0\n1,1,0,0,0,1\n"
+        );
+        let mut json = Vec::new();
+        profile.write(Report::Json, &world, &mut json)?;
+        let json = String::from_utf8(json)?;
+        assert!(json.starts_with("{\"steps\":4,\"io_seconds\":"));
+        assert!(json.contains("\"opcodes\":[{\"opcode\":49,\"char\":\"1\",\"count\":2},"));
+        let mut heatmap = Vec::new();
+        profile.write(Report::Heatmap, &world, &mut heatmap)?;
+        let heatmap = String::from_utf8(heatmap)?;
+        assert!(heatmap.starts_with("\x1b[30;48;5;196m1\x1b[0m"));
+        assert!(heatmap.contains("\n \x1b[4m□\x1b[0m "));
+        assert!(heatmap.contains("hottest opcodes: '1' 2, '@' 1, 'p' 1\n"));
+        Ok(())
+    }
+}
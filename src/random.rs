@@ -0,0 +1,261 @@
+use crate::befunge::Direction;
+use std::str::FromStr;
+
+/// Where `?` gets its directions from.
+pub trait DirectionSource {
+    /// Returns `None` once the source has no more directions to give.
+    fn next_direction(&mut self) -> Option<Direction>;
+
+    /// A number in `0..n`, for fingerprints such as FIXP that need more than
+    /// a direction. By default it is read as base-4 digits from
+    /// [`DirectionSource::next_direction`], counting up, down, left and right
+    /// as 0 to 3, so that scripted directions decide it too.
+    fn next_below(&mut self, n: u32) -> Option<u32> {
+        let (mut value, mut range) = (0_u64, 1_u64);
+        while range < u64::from(n) {
+            let digit = match self.next_direction()? {
+                Direction::Up => 0,
+                Direction::Down => 1,
+                Direction::Left => 2,
+                Direction::Right => 3,
+            };
+            value = value * 4 + digit;
+            range *= 4;
+        }
+        Some((value % u64::from(n.max(1))) as u32)
+    }
+
+    /// The source's state as one line of text, for a
+    /// [`Snapshot`](crate::Snapshot). Sources that cannot be saved return
+    /// `None` and are left as they are when a snapshot is restored.
+    fn snapshot(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Rebuilds a source from [`DirectionSource::snapshot`] text.
+pub(crate) fn from_snapshot(saved: &str) -> Result<Box<dyn DirectionSource>, String> {
+    let words: Vec<&str> = saved.split_whitespace().collect();
+    match words[..] {
+        ["thread"] => Ok(Box::new(ThreadRandom)),
+        ["seeded", state] => state
+            .parse()
+            .map(|state| Box::new(SeededRandom::new(state)) as Box<dyn DirectionSource>)
+            .map_err(|_| format!("invalid random state '{}'", saved)),
+        ["scripted", position, ref choices @ ..] => {
+            let mut script: ScriptedRandom = choices.concat().parse()?;
+            script.position = position
+                .parse()
+                .map_err(|_| format!("invalid random state '{}'", saved))?;
+            Ok(Box::new(script))
+        }
+        _ => Err(format!("unknown random source '{}'", saved)),
+    }
+}
+
+/// Picks directions from the thread-local entropy-seeded generator.
+#[derive(Debug, Default)]
+pub struct ThreadRandom;
+
+impl DirectionSource for ThreadRandom {
+    fn next_direction(&mut self) -> Option<Direction> {
+        use rand::{thread_rng, Rng};
+        let mut rng = thread_rng();
+        Some(if rng.gen() {
+            if rng.gen() {
+                Direction::Up
+            } else {
+                Direction::Down
+            }
+        } else if rng.gen() {
+            Direction::Left
+        } else {
+            Direction::Right
+        })
+    }
+
+    fn next_below(&mut self, n: u32) -> Option<u32> {
+        use rand::{thread_rng, Rng};
+        Some(thread_rng().gen_range(0, n.max(1)))
+    }
+
+    fn snapshot(&self) -> Option<String> {
+        Some("thread".to_string())
+    }
+}
+
+/// A SplitMix64 generator. The algorithm is fixed here rather than borrowed
+/// from `rand` so that a seed produces the same directions in every release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        SeededRandom { state: seed }
+    }
+
+    /// The full generator state; `SeededRandom::new(state)` resumes from it.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl DirectionSource for SeededRandom {
+    fn next_direction(&mut self) -> Option<Direction> {
+        Some(match self.next_u64() >> 62 {
+            0 => Direction::Up,
+            1 => Direction::Down,
+            2 => Direction::Left,
+            _ => Direction::Right,
+        })
+    }
+
+    fn next_below(&mut self, n: u32) -> Option<u32> {
+        Some((self.next_u64() % u64::from(n.max(1))) as u32)
+    }
+
+    fn snapshot(&self) -> Option<String> {
+        Some(format!("seeded {}", self.state))
+    }
+}
+
+/// Replays a fixed list of directions, e.g. to walk every branch of a `?`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptedRandom {
+    choices: Vec<Direction>,
+    position: usize,
+}
+
+impl ScriptedRandom {
+    pub fn new(choices: Vec<Direction>) -> ScriptedRandom {
+        ScriptedRandom {
+            choices,
+            position: 0,
+        }
+    }
+
+    /// How many directions have been handed out so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl DirectionSource for ScriptedRandom {
+    fn next_direction(&mut self) -> Option<Direction> {
+        let direction = self.choices.get(self.position).copied()?;
+        self.position += 1;
+        Some(direction)
+    }
+
+    fn snapshot(&self) -> Option<String> {
+        let arrows: String = self
+            .choices
+            .iter()
+            .map(|direction| match direction {
+                Direction::Up => '^',
+                Direction::Down => 'v',
+                Direction::Left => '<',
+                Direction::Right => '>',
+            })
+            .collect();
+        Some(format!("scripted {} {}", self.position, arrows))
+    }
+}
+
+/// Parses the arrow characters `^`, `v`, `<` and `>`; whitespace is ignored.
+impl FromStr for ScriptedRandom {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ScriptedRandom, String> {
+        s.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| match c {
+                '^' => Ok(Direction::Up),
+                'v' => Ok(Direction::Down),
+                '<' => Ok(Direction::Left),
+                '>' => Ok(Direction::Right),
+                _ => Err(format!("'{}' is not one of ^ v < >", c)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(ScriptedRandom::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_snapshot, DirectionSource, ScriptedRandom, SeededRandom};
+    use crate::befunge::Direction;
+
+    #[test]
+    fn equal_seeds_give_equal_directions() {
+        let mut a = SeededRandom::new(42);
+        let mut b = SeededRandom::new(42);
+        let a: Vec<_> = (0..64).map(|_| a.next_direction()).collect();
+        let b: Vec<_> = (0..64).map(|_| b.next_direction()).collect();
+        assert_eq!(a, b);
+        for direction in [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ] {
+            assert!(a.contains(&Some(direction)));
+        }
+    }
+
+    #[test]
+    fn generator_resumes_from_its_state() {
+        let mut a = SeededRandom::new(7);
+        a.next_direction();
+        let mut b = SeededRandom::new(a.state());
+        assert_eq!(a.next_direction(), b.next_direction());
+    }
+
+    #[test]
+    fn sources_resume_from_their_snapshots() -> Result<(), String> {
+        let mut script: ScriptedRandom = "<>^".parse()?;
+        script.next_direction();
+        let mut resumed = from_snapshot(&script.snapshot().unwrap_or_default())?;
+        assert_eq!(resumed.next_direction(), Some(Direction::Right));
+        assert_eq!(resumed.snapshot(), Some("scripted 2 <>^".to_string()));
+        let seeded = SeededRandom::new(9);
+        assert_eq!(from_snapshot("seeded 9")?.snapshot(), seeded.snapshot());
+        assert!(from_snapshot("seeded x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn numbers_come_from_the_same_source() {
+        let mut script: ScriptedRandom = "v<".parse().expect("valid script");
+        assert_eq!(script.next_below(1), Some(0));
+        assert_eq!(script.next_below(16), Some(6));
+        assert_eq!(script.next_below(2), None);
+        let mut a = SeededRandom::new(3);
+        let mut b = SeededRandom::new(3);
+        for _ in 0..64 {
+            let n = a.next_below(10);
+            assert_eq!(n, b.next_below(10));
+            assert!(n.is_some_and(|n| n < 10));
+        }
+    }
+
+    #[test]
+    fn scripted_choices_run_out() {
+        let mut script: ScriptedRandom = "> v".parse().expect("valid script");
+        assert_eq!(script.next_direction(), Some(Direction::Right));
+        assert_eq!(script.next_direction(), Some(Direction::Down));
+        assert_eq!(script.next_direction(), None);
+        assert_eq!(script.position(), 2);
+        assert!("x".parse::<ScriptedRandom>().is_err());
+    }
+}
@@ -0,0 +1,412 @@
+//! Saved interpreter state, as made by [`Befunge::snapshot`](crate::Befunge::snapshot).
+//!
+//! Snapshots are plain text, one item per line:
+//!
+//! ```text
+//! befunge-snapshot 1
+//! space torus 80 25
+//! cell 0 0 38
+//! steps 4
+//! output-bytes 0
+//! input-offset 3
+//! random seeded 42
+//! next-ip 1
+//! ip 0
+//! position 4 0
+//! delta 1 0
+//! mode interpret
+//! storage-offset 0 0
+//! stack 41 1
+//! ```
+//!
+//! Funge-98 space is `space lahey X0 Y0 X1 Y1`, with its bounds. Each IP may
+//! add `soss` lines for the stacks below its own, bottom first, and
+//! `fingerprint L ID...` for the fingerprints that define instruction `L`.
+
+use crate::befunge::{Delta, Ip, Mode};
+use crate::cell::Cell;
+use crate::random;
+use crate::world::{Standard, World, FUNGE_HEIGHT, FUNGE_WIDTH};
+use std::fmt;
+use std::str::FromStr;
+
+const HEADER: &str = "befunge-snapshot 1";
+
+/// A paused program: its world, its IPs and everything else needed to carry
+/// on with [`Befunge::restore`](crate::Befunge::restore).
+#[derive(Clone)]
+pub struct Snapshot<C = i32> {
+    pub(crate) world: World,
+    /// The current IP first, then the others in the order they run. Their
+    /// `semantics` are left empty.
+    pub(crate) ips: Vec<Ip<C>>,
+    /// Each IP's loaded fingerprints, as IDs.
+    pub(crate) semantics: Vec<[Vec<i32>; 26]>,
+    pub(crate) next_ip_id: i32,
+    pub(crate) steps: u64,
+    pub(crate) exit_code: Option<i32>,
+    pub(crate) output_bytes: u64,
+    pub(crate) input_offset: u64,
+    /// [`DirectionSource::snapshot`](crate::DirectionSource::snapshot) text.
+    pub(crate) random: Option<String>,
+}
+
+impl<C> Snapshot<C> {
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// How many bytes of input had been read.
+    pub fn input_offset(&self) -> u64 {
+        self.input_offset
+    }
+}
+
+fn join<T: fmt::Display>(values: &[T]) -> String {
+    values.iter().map(|v| format!(" {}", v)).collect()
+}
+
+impl<C: Cell> fmt::Display for Snapshot<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        match self.world.standard() {
+            Standard::Befunge93 => {
+                let (width, height) = (self.world.width(), self.world.height());
+                writeln!(f, "space torus {} {}", width, height)?;
+            }
+            Standard::Funge98 => {
+                let ((x0, y0), (x1, y1)) = self.world.bounds();
+                writeln!(f, "space lahey {} {} {} {}", x0, y0, x1, y1)?;
+            }
+        }
+        for ((x, y), value) in self.world.cells() {
+            writeln!(f, "cell {} {} {}", x, y, value)?;
+        }
+        writeln!(f, "steps {}", self.steps)?;
+        if let Some(code) = self.exit_code {
+            writeln!(f, "exit-code {}", code)?;
+        }
+        writeln!(f, "output-bytes {}", self.output_bytes)?;
+        writeln!(f, "input-offset {}", self.input_offset)?;
+        if let Some(random) = &self.random {
+            writeln!(f, "random {}", random)?;
+        }
+        writeln!(f, "next-ip {}", self.next_ip_id)?;
+        for (ip, semantics) in self.ips.iter().zip(&self.semantics) {
+            writeln!(f, "ip {}", ip.id)?;
+            writeln!(f, "position {} {}", ip.x, ip.y)?;
+            writeln!(f, "delta {} {}", ip.delta.dx, ip.delta.dy)?;
+            let mode = match ip.mode {
+                Mode::Interpret => "interpret",
+                Mode::AsciiPush => "string",
+            };
+            writeln!(f, "mode {}", mode)?;
+            let (ox, oy) = ip.storage_offset;
+            writeln!(f, "storage-offset {} {}", ox, oy)?;
+            for stack in &ip.stack_stack {
+                writeln!(f, "soss{}", join(stack))?;
+            }
+            writeln!(f, "stack{}", join(&ip.stack))?;
+            for (letter, ids) in ('A'..='Z').zip(semantics) {
+                if !ids.is_empty() {
+                    writeln!(f, "fingerprint {}{}", letter, join(ids))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C: Cell> FromStr for Snapshot<C> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Snapshot<C>, String> {
+        let mut lines = s.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some(HEADER) {
+            return Err(format!("not a snapshot (expected '{}')", HEADER));
+        }
+        let mut world: Option<World> = None;
+        let mut snapshot = Snapshot {
+            world: World::empty_98(),
+            ips: Vec::new(),
+            semantics: Vec::new(),
+            next_ip_id: 1,
+            steps: 0,
+            exit_code: None,
+            output_bytes: 0,
+            input_offset: 0,
+            random: None,
+        };
+        for (n, line) in lines {
+            let invalid = || format!("line {}: invalid '{}'", n + 1, line);
+            let number = |word: &str| word.parse::<i32>().map_err(|_| invalid());
+            let counter = |word: &str| word.parse::<u64>().map_err(|_| invalid());
+            // Befunge-93 space holds bytes, and its IPs stay on it.
+            let torus = world
+                .as_ref()
+                .is_none_or(|world| world.standard() == Standard::Befunge93);
+            let on_torus = |x: i32, y: i32| match &world {
+                Some(world) if torus => {
+                    (0..world.width() as i32).contains(&x)
+                        && (0..world.height() as i32).contains(&y)
+                }
+                Some(_) => true,
+                None => false,
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((&key, args)) = words.split_first() else {
+                continue;
+            };
+            let ip = snapshot.ips.last_mut();
+            match (key, args, ip) {
+                ("space", ["torus", width, height], _) => {
+                    // Befunge-93 space is never larger than 80x25.
+                    let size = |word: &str, max| match word.parse::<usize>() {
+                        Ok(size) if (1..=max).contains(&size) => Ok(size),
+                        _ => Err(invalid()),
+                    };
+                    world = Some(World::empty_torus(
+                        size(width, FUNGE_WIDTH)?,
+                        size(height, FUNGE_HEIGHT)?,
+                    ));
+                }
+                ("space", ["lahey", x0, y0, x1, y1], _) => {
+                    let mut lahey = World::empty_98();
+                    lahey.set_bounds(((number(x0)?, number(y0)?), (number(x1)?, number(y1)?)));
+                    world = Some(lahey);
+                }
+                ("cell", [x, y, value], _) => {
+                    let (x, y, value) = (number(x)?, number(y)?, number(value)?);
+                    if !on_torus(x, y) || torus && !(0..=255).contains(&value) {
+                        return Err(invalid());
+                    }
+                    world.as_mut().ok_or_else(invalid)?.set_cell(x, y, value);
+                }
+                ("steps", [steps], _) => snapshot.steps = counter(steps)?,
+                ("exit-code", [code], _) => snapshot.exit_code = Some(number(code)?),
+                ("output-bytes", [bytes], _) => snapshot.output_bytes = counter(bytes)?,
+                ("input-offset", [bytes], _) => snapshot.input_offset = counter(bytes)?,
+                ("random", [_, ..], _) => {
+                    let saved = args.join(" ");
+                    random::from_snapshot(&saved)?;
+                    snapshot.random = Some(saved);
+                }
+                ("next-ip", [id], _) => snapshot.next_ip_id = number(id)?,
+                ("ip", [id], _) => {
+                    let ip = Ip::new(number(id)?, 0, 0, Delta::new(1, 0), Vec::new());
+                    snapshot.ips.push(ip);
+                    snapshot.semantics.push(Default::default());
+                }
+                ("position", [x, y], Some(ip)) => {
+                    (ip.x, ip.y) = (number(x)?, number(y)?);
+                    if !on_torus(ip.x, ip.y) {
+                        return Err(invalid());
+                    }
+                }
+                ("delta", [dx, dy], Some(ip)) => {
+                    let (dx, dy) = (number(dx)?, number(dy)?);
+                    // Befunge-93 IPs only ever move one cell in a cardinal direction.
+                    if torus && dx.abs() + dy.abs() != 1 {
+                        return Err(invalid());
+                    }
+                    ip.delta = Delta::new(dx, dy);
+                }
+                ("mode", ["interpret"], Some(ip)) => ip.mode = Mode::Interpret,
+                ("mode", ["string"], Some(ip)) => ip.mode = Mode::AsciiPush,
+                ("storage-offset", [x, y], Some(ip)) => {
+                    ip.storage_offset = (number(x)?, number(y)?);
+                }
+                ("soss" | "stack", values, Some(ip)) => {
+                    let values = values
+                        .iter()
+                        .map(|v| v.parse::<C>().map_err(|_| invalid()))
+                        .collect::<Result<Vec<C>, String>>()?;
+                    match key {
+                        "soss" => ip.stack_stack.push(values),
+                        _ => ip.stack = values,
+                    }
+                }
+                ("fingerprint", [letter, ids @ ..], Some(_)) => {
+                    let index = match letter.as_bytes() {
+                        [c @ b'A'..=b'Z'] => usize::from(c - b'A'),
+                        _ => return Err(invalid()),
+                    };
+                    let ids = ids.iter().map(|id| number(id)).collect::<Result<_, _>>()?;
+                    if let Some(semantics) = snapshot.semantics.last_mut() {
+                        semantics[index] = ids;
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        snapshot.world = world.ok_or("snapshot has no 'space' line")?;
+        if snapshot.ips.is_empty() {
+            return Err("snapshot has no IPs".to_string());
+        }
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Snapshot;
+    use crate::befunge::{Befunge, Context, Direction};
+    use crate::error::ErrorKind;
+    use crate::random::SeededRandom;
+    use crate::world::{Standard, World};
+    use std::error::Error;
+    use std::io::{BufReader, Read};
+
+    /// Runs `src` for `steps` steps, saves it as text and finishes it both
+    /// in place and from the parsed snapshot. Returns the two outputs after
+    /// the save point.
+    fn resume(
+        src: &str,
+        standard: Standard,
+        input: &str,
+        steps: u64,
+    ) -> Result<(String, String), Box<dyn Error>> {
+        let mut world = World::from_source(src, standard)?;
+        let mut read = BufReader::new(input.as_bytes());
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write)
+            .with_random(Box::new(SeededRandom::new(3)));
+        for _ in 0..steps {
+            befunge.step()?;
+        }
+        let saved = befunge.snapshot().to_string();
+        befunge.run()?;
+        drop(befunge);
+        let before = write.len();
+
+        let snapshot: Snapshot = saved.parse()?;
+        let mut world = snapshot.world().clone();
+        let mut read = BufReader::new(input.as_bytes());
+        let mut resumed = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut resumed);
+        befunge.restore(&snapshot)?;
+        befunge.run()?;
+        let continued = String::from_utf8(write)?;
+        Ok((
+            continued[continued.len() - before..].to_string(),
+            String::from_utf8(resumed)?,
+        ))
+    }
+
+    #[test]
+    fn a_restored_program_carries_on_where_it_was_saved() -> Result<(), Box<dyn Error>> {
+        // Self-modification, input and `?` after the save point.
+        let src = "&00p~,00g.v\n\n\n\n\n\n\n\n\n     @\n     .\n     1\n  @.3?    <\n     2\n     .\n     @";
+        let (continued, resumed) = resume(src, Standard::Befunge93, "65 xyz", 2)?;
+        assert_eq!(resumed, continued);
+        assert!(resumed.starts_with("x65 "));
+        // Two IPs, the stack stack and a loaded fingerprint.
+        let src = "\"AMOR\"4($$12{M#@t.}..@";
+        let (continued, resumed) = resume(src, Standard::Funge98, "", 16)?;
+        assert_eq!(
+            (continued.as_str(), resumed.as_str()),
+            ("1000 0 0 ", "1000 0 0 ")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn snapshots_round_trip_through_text() -> Result<(), Box<dyn Error>> {
+        let mut world = World::from_source("'Xt0{3q", Standard::Funge98)?;
+        let mut read = BufReader::new(&b""[..]);
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        befunge.run()?;
+        let text = befunge.snapshot().to_string();
+        assert!(text.starts_with("befunge-snapshot 1\nspace lahey 0 0 6 0\ncell 0 0 39\n"));
+        assert!(text.contains("exit-code 3\n"));
+        assert!(text.contains("ip 1\n"));
+        assert!(text.contains("soss 88 0 0\nstack\n"));
+        let snapshot: Snapshot = text.parse()?;
+        assert_eq!(snapshot.to_string(), text);
+        Ok(())
+    }
+
+    #[test]
+    fn input_read_by_handlers_is_skipped_on_restore() -> Result<(), Box<dyn Error>> {
+        fn read_byte(context: &mut Context<i32>) -> Result<(), ErrorKind> {
+            let mut byte = [0];
+            context.input().read_exact(&mut byte)?;
+            context.push(i32::from(byte[0]));
+            Ok(())
+        }
+        let mut world = World::from_source_string("R.R.@")?;
+        let mut read = BufReader::new(&b"ab"[..]);
+        let mut write = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write);
+        befunge.register_instruction('R', read_byte)?;
+        befunge.step()?;
+        befunge.step()?;
+        let saved = befunge.snapshot().to_string();
+        drop(befunge);
+        assert_eq!(write, b"97 ");
+
+        let snapshot: Snapshot = saved.parse()?;
+        let mut world = snapshot.world().clone();
+        let mut read = BufReader::new(&b"ab"[..]);
+        let mut resumed = Vec::new();
+        let mut befunge = Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut resumed);
+        befunge.register_instruction('R', read_byte)?;
+        befunge.restore(&snapshot)?;
+        befunge.run()?;
+        drop(befunge);
+        assert_eq!(String::from_utf8(resumed)?, "98 ");
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_snapshots_are_rejected() {
+        assert!("".parse::<Snapshot>().is_err());
+        assert!("befunge-snapshot 1\nspace torus 80 25\n"
+            .parse::<Snapshot>()
+            .is_err());
+        let bad = "befunge-snapshot 1\nspace torus 80 25\nip 0\nposition x 0\n";
+        assert_eq!(
+            bad.parse::<Snapshot>().err(),
+            Some("line 4: invalid 'position x 0'".to_string())
+        );
+        for space in [
+            "torus 0 0",
+            "torus 80 0",
+            "torus 81 25",
+            "torus 100000 100000",
+        ] {
+            let bad = format!("befunge-snapshot 1\nspace {}\ncell 0 0 64\n", space);
+            assert_eq!(
+                bad.parse::<Snapshot>().err(),
+                Some(format!("line 2: invalid 'space {}'", space))
+            );
+        }
+        for item in [
+            "cell 80 0 64",
+            "cell 0 -1 64",
+            "cell 0 0 256",
+            "position 500 -3",
+            "position 80 0",
+            "position 0 25",
+            "delta 0 0",
+            "delta 2 0",
+            "delta 1 1",
+        ] {
+            let bad = format!(
+                "befunge-snapshot 1\nspace torus 80 25\nip 0\nposition 0 0\n{}\n",
+                item
+            );
+            assert_eq!(
+                bad.parse::<Snapshot>().err(),
+                Some(format!("line 5: invalid '{}'", item))
+            );
+        }
+        let far = "befunge-snapshot 1\nspace lahey 0 0 0 0\nip 0\nposition 500 -3\ndelta 0 7\n";
+        assert!(far.parse::<Snapshot>().is_ok());
+    }
+}
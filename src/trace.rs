@@ -0,0 +1,189 @@
+//! JSON Lines execution traces, as written by `--trace`.
+
+use crate::befunge::Mode;
+use crate::cell::Cell;
+use crate::observer::{Event, Observer};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// Writes one JSON object per executed instruction, e.g.
+///
+/// ```text
+/// {"step":3,"ip":0,"x":2,"y":0,"opcode":43,"char":"+","direction":"right","delta":[1,0],"mode":"interpret","depth":1,"stack":[3]}
+/// ```
+///
+/// `input` and `output` fields are added for instructions that did I/O.
+pub struct Trace<W> {
+    out: W,
+    steps: (u64, u64),
+    region: Option<((i32, i32), (i32, i32))>,
+    stack_depth: usize,
+}
+
+impl<W: Write> Trace<W> {
+    /// Traces every step, with the top 8 values of the stack.
+    pub fn new(out: W) -> Trace<W> {
+        Trace {
+            out,
+            steps: (1, u64::MAX),
+            region: None,
+            stack_depth: 8,
+        }
+    }
+
+    /// Only traces steps `first` to `last`, inclusive.
+    pub fn steps(mut self, first: u64, last: u64) -> Trace<W> {
+        self.steps = (first, last);
+        self
+    }
+
+    /// Only traces instructions inside the rectangle with corners `least`
+    /// and `greatest`, inclusive.
+    pub fn region(mut self, least: (i32, i32), greatest: (i32, i32)) -> Trace<W> {
+        self.region = Some((least, greatest));
+        self
+    }
+
+    /// How many values from the top of the stack each record shows.
+    pub fn stack_depth(mut self, depth: usize) -> Trace<W> {
+        self.stack_depth = depth;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn wants(&self, event: &Event<impl Cell>) -> bool {
+        let (first, last) = self.steps;
+        let in_region = self.region.is_none_or(|((x0, y0), (x1, y1))| {
+            (x0..=x1).contains(&event.x) && (y0..=y1).contains(&event.y)
+        });
+        (first..=last).contains(&event.step) && in_region
+    }
+}
+
+impl<W: Write, C: Cell> Observer<C> for Trace<W> {
+    fn instruction(&mut self, event: &Event<C>) -> io::Result<()> {
+        if !self.wants(event) {
+            return Ok(());
+        }
+        let mut record = format!(
+            "{{\"step\":{},\"ip\":{},\"x\":{},\"y\":{},\"opcode\":{}",
+            event.step, event.ip, event.x, event.y, event.opcode
+        );
+        if let Some(c) = u32::try_from(event.opcode).ok().and_then(char::from_u32) {
+            let _ = write!(
+                record,
+                ",\"char\":{}",
+                json_string(c.encode_utf8(&mut [0; 4]))
+            );
+        }
+        if event.delta.direction().is_some() {
+            let _ = write!(record, ",\"direction\":\"{}\"", event.delta);
+        }
+        let mode = match event.mode {
+            Mode::Interpret => "interpret",
+            Mode::AsciiPush => "string",
+        };
+        let top = event.stack.len().saturating_sub(self.stack_depth);
+        let stack: Vec<String> = event.stack[top..].iter().map(C::to_string).collect();
+        let _ = write!(
+            record,
+            ",\"delta\":[{},{}],\"mode\":\"{}\",\"depth\":{},\"stack\":[{}]",
+            event.delta.dx,
+            event.delta.dy,
+            mode,
+            event.stack.len(),
+            stack.join(",")
+        );
+        if !event.input.is_empty() {
+            let input = String::from_utf8_lossy(event.input);
+            let _ = write!(record, ",\"input\":{}", json_string(&input));
+        }
+        if !event.output.is_empty() {
+            let output = String::from_utf8_lossy(event.output);
+            let _ = write!(record, ",\"output\":{}", json_string(&output));
+        }
+        writeln!(self.out, "{}}}", record)
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Trace;
+    use crate::befunge::{Befunge, Direction};
+    use crate::world::World;
+    use std::error::Error;
+    use std::io::BufReader;
+
+    fn trace(src: &str, input: &[u8], trace: Trace<Vec<u8>>) -> Result<String, Box<dyn Error>> {
+        let mut trace = trace;
+        let mut world = World::from_source_string(src)?;
+        let mut read = BufReader::new(input);
+        let mut write = Vec::new();
+        Befunge::new(&mut world, 0, 0, Direction::Right, &mut read, &mut write)
+            .with_observer(&mut trace)
+            .run()?;
+        Ok(String::from_utf8(trace.into_inner())?)
+    }
+
+    #[test]
+    fn every_instruction_gets_a_record() -> Result<(), Box<dyn Error>> {
+        let lines = trace("&1+.@", b"41", Trace::new(Vec::new()))?;
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            "{\"step\":1,\"ip\":0,\"x\":0,\"y\":0,\"opcode\":38,\"char\":\"&\",\
+             \"direction\":\"right\",\"delta\":[1,0],\"mode\":\"interpret\",\
+             \"depth\":1,\"stack\":[41],\"input\":\"41\"}"
+        );
+        assert!(lines[3].ends_with("\"depth\":0,\"stack\":[],\"output\":\"42 \"}"));
+        Ok(())
+    }
+
+    #[test]
+    fn records_can_be_limited_to_steps_regions_and_stack_tops() -> Result<(), Box<dyn Error>> {
+        let src = "123v\n@.$<";
+        let lines = trace(src, b"", Trace::new(Vec::new()).steps(2, 3).stack_depth(1))?;
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("{\"step\":2,"));
+        assert!(lines[1].contains("\"depth\":3,\"stack\":[3]"));
+        let lines = trace(src, b"", Trace::new(Vec::new()).region((0, 1), (3, 1)))?;
+        assert_eq!(lines.lines().count(), 4);
+        assert!(lines.lines().all(|line| line.contains("\"y\":1,")));
+        Ok(())
+    }
+
+    #[test]
+    fn string_mode_and_quotes_are_escaped() -> Result<(), Box<dyn Error>> {
+        let lines = trace("\"\\\"@", b"", Trace::new(Vec::new()))?;
+        let lines: Vec<&str> = lines.lines().collect();
+        assert!(lines[0].contains("\"char\":\"\\\"\",\"direction\""));
+        assert!(
+            lines[1].contains("\"char\":\"\\\\\",") && lines[1].contains("\"mode\":\"string\"")
+        );
+        Ok(())
+    }
+}
@@ -1,12 +1,87 @@
-use std::io;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
 
-const FUNGE_WIDTH: usize = 80;
-const FUNGE_HEIGHT: usize = 25;
+pub(crate) const FUNGE_WIDTH: usize = 80;
+pub(crate) const FUNGE_HEIGHT: usize = 25;
+const SPACE: i32 = b' ' as i32;
 
+/// The language standard a program is loaded and run under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Standard {
+    /// A fixed 80x25 torus of bytes.
+    #[default]
+    Befunge93,
+    /// Unbounded Lahey-space of `i32` cells.
+    Funge98,
+}
+
+impl FromStr for Standard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Standard, String> {
+        match s {
+            "93" => Ok(Standard::Befunge93),
+            "98" => Ok(Standard::Funge98),
+            _ => Err(format!("unknown standard '{}' (expected 93 or 98)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Standard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Standard::Befunge93 => "93",
+            Standard::Funge98 => "98",
+        })
+    }
+}
+
+#[derive(Clone)]
+enum Space {
+    Torus {
+        width: usize,
+        height: usize,
+        field: Vec<Vec<u8>>,
+    },
+    /// Only non-space cells are stored. `least`/`greatest` bound every cell
+    /// that has ever held a non-space value; they grow but never shrink, which
+    /// Funge-98 allows.
+    Lahey {
+        cells: HashMap<(i32, i32), i32>,
+        /// The x of every stored cell by row, and its y by column, for
+        /// finding the next one along a line without walking the spaces.
+        rows: BTreeMap<i32, BTreeSet<i32>>,
+        columns: BTreeMap<i32, BTreeSet<i32>>,
+        least: (i32, i32),
+        greatest: (i32, i32),
+    },
+}
+
+#[derive(Clone)]
 pub struct World {
-    width: usize,
-    height: usize,
-    field: Vec<Vec<u8>>,
+    space: Space,
+    /// While journaling, every cell written with the value it held before.
+    journal: Option<Vec<(i32, i32, i32)>>,
+}
+
+fn div_floor(a: i64, b: i64) -> i64 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn div_ceil(a: i64, b: i64) -> i64 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) == (b < 0) {
+        q + 1
+    } else {
+        q
+    }
 }
 
 impl World {
@@ -14,12 +89,32 @@ impl World {
         coord.rem_euclid(size as i32) as usize
     }
 
+    pub fn standard(&self) -> Standard {
+        match self.space {
+            Space::Torus { .. } => Standard::Befunge93,
+            Space::Lahey { .. } => Standard::Funge98,
+        }
+    }
+
     pub fn width(&self) -> usize {
-        self.width
+        let ((x0, _), (x1, _)) = self.bounds();
+        (i64::from(x1) - i64::from(x0) + 1).max(0) as usize
     }
 
     pub fn height(&self) -> usize {
-        self.height
+        let ((_, y0), (_, y1)) = self.bounds();
+        (i64::from(y1) - i64::from(y0) + 1).max(0) as usize
+    }
+
+    /// The least and greatest points of the program; on the torus these are
+    /// its corners.
+    pub fn bounds(&self) -> ((i32, i32), (i32, i32)) {
+        match &self.space {
+            Space::Torus { width, height, .. } => ((0, 0), (*width as i32 - 1, *height as i32 - 1)),
+            Space::Lahey {
+                least, greatest, ..
+            } => (*least, *greatest),
+        }
     }
 
     pub fn from_source_string(source: &str) -> io::Result<World> {
@@ -47,13 +142,65 @@ impl World {
         }
 
         Ok(World {
-            width: FUNGE_WIDTH,
-            height: FUNGE_HEIGHT,
-            field: world,
+            journal: None,
+            space: Space::Torus {
+                width: FUNGE_WIDTH,
+                height: FUNGE_HEIGHT,
+                field: world,
+            },
         })
     }
 
-    #[allow(dead_code)]
+    /// Loads a program into unbounded Funge-98 space with its first character
+    /// at the origin. Lines may end in `\n`, `\r\n` or `\r`, and form feeds are
+    /// ignored.
+    pub fn from_source_string_98(source: &str) -> World {
+        let mut world = World::empty_98();
+        let source = source.replace("\r\n", "\n").replace('\r', "\n");
+        for (y, line) in source.split('\n').enumerate() {
+            let bytes = line.bytes().filter(|&b| b != b'\x0c');
+            for (x, byte) in bytes.enumerate() {
+                if byte != b' ' {
+                    world.set_cell(x as i32, y as i32, i32::from(byte));
+                }
+            }
+        }
+        world
+    }
+
+    /// Loads `source` under the given standard.
+    pub fn from_source(source: &str, standard: Standard) -> io::Result<World> {
+        match standard {
+            Standard::Befunge93 => World::from_source_string(source),
+            Standard::Funge98 => Ok(World::from_source_string_98(source)),
+        }
+    }
+
+    /// A torus of spaces.
+    pub(crate) fn empty_torus(width: usize, height: usize) -> World {
+        World {
+            journal: None,
+            space: Space::Torus {
+                width,
+                height,
+                field: vec![vec![b' '; width]; height],
+            },
+        }
+    }
+
+    pub(crate) fn empty_98() -> World {
+        World {
+            journal: None,
+            space: Space::Lahey {
+                cells: HashMap::new(),
+                rows: BTreeMap::new(),
+                columns: BTreeMap::new(),
+                least: (i32::MAX, i32::MAX),
+                greatest: (i32::MIN, i32::MIN),
+            },
+        }
+    }
+
     pub fn from_random(width: usize, height: usize) -> World {
         use rand::{thread_rng, Rng};
         let mut rng = thread_rng();
@@ -66,51 +213,392 @@ impl World {
             world.push(line);
         }
         World {
-            width,
-            height,
-            field: world,
+            journal: None,
+            space: Space::Torus {
+                width,
+                height,
+                field: world,
+            },
         }
     }
 
-    #[allow(dead_code)]
-    pub fn println(&self) {
-        for line in &self.field {
-            for &elem in line {
-                if (0x20..=0x7e).contains(&elem) {
-                    print!("{}", char::from(elem));
+    /// Writes the program to `out`, one row per line, with non-printable cells
+    /// shown as `□`.
+    pub fn render(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.render_highlighted(out, &[])
+    }
+
+    /// Like [`World::render`], with the cells at `highlight` shown in reverse
+    /// video.
+    pub fn render_highlighted(
+        &self,
+        out: &mut dyn Write,
+        highlight: &[(i32, i32)],
+    ) -> io::Result<()> {
+        self.render_styled(out, self.bounds(), |x, y| {
+            highlight.contains(&(x, y)).then_some("\x1b[7m")
+        })
+    }
+
+    /// Like [`World::render`] for the cells from corner to corner of `area`,
+    /// starting each cell for which `style` gives an ANSI escape sequence
+    /// with it.
+    pub(crate) fn render_styled<S: AsRef<str>>(
+        &self,
+        out: &mut dyn Write,
+        area: ((i32, i32), (i32, i32)),
+        style: impl Fn(i32, i32) -> Option<S>,
+    ) -> io::Result<()> {
+        let ((x0, y0), (x1, y1)) = area;
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let style = style(x, y);
+                if let Some(style) = &style {
+                    write!(out, "{}", style.as_ref())?;
+                }
+                let cell = self.get_cell(x, y);
+                if (0x20..=0x7e).contains(&cell) {
+                    write!(out, "{}", char::from(cell as u8))?;
                 } else {
-                    print!("□");
+                    write!(out, "□")?;
+                }
+                if style.is_some() {
+                    write!(out, "\x1b[0m")?;
                 }
             }
-            println!();
+            writeln!(out)?;
         }
+        Ok(())
     }
 
+    /// The part of the program, at most 80x25, that has `(x, y)` as near its
+    /// middle as the bounds allow. Smaller programs are shown whole.
+    pub(crate) fn window(&self, (x, y): (i32, i32)) -> ((i32, i32), (i32, i32)) {
+        let ((x0, y0), (x1, y1)) = self.bounds();
+        let axis = |at: i32, lo: i32, hi: i32, size: usize| {
+            let (at, lo, hi, size) = (i64::from(at), i64::from(lo), i64::from(hi), size as i64);
+            if hi - lo < size {
+                return (lo as i32, hi as i32);
+            }
+            let start = (at - size / 2).clamp(lo, hi - size + 1);
+            (start as i32, (start + size - 1) as i32)
+        };
+        let (x0, x1) = axis(x, x0, x1, FUNGE_WIDTH);
+        let (y0, y1) = axis(y, y0, y1, FUNGE_HEIGHT);
+        ((x0, y0), (x1, y1))
+    }
+
+    pub fn println(&self) {
+        // Like `print!`, this only fails when stdout itself is gone.
+        let _ = self.render(&mut io::stdout());
+    }
+
+    /// The byte at `(x, y)`. Funge-98 cells are truncated to their low byte.
     pub fn get(&self, x: usize, y: usize) -> u8 {
-        self.field[y % self.height][x % self.width]
+        match &self.space {
+            Space::Torus {
+                width,
+                height,
+                field,
+            } => field[y % height][x % width],
+            Space::Lahey { .. } => self.get_cell(x as i32, y as i32) as u8,
+        }
     }
 
     pub fn get_signed(&self, x: i32, y: i32) -> u8 {
-        self.get(
-            Self::wrap_signed_coordinate(x, self.width),
-            Self::wrap_signed_coordinate(y, self.height),
-        )
+        match &self.space {
+            Space::Torus { width, height, .. } => self.get(
+                Self::wrap_signed_coordinate(x, *width),
+                Self::wrap_signed_coordinate(y, *height),
+            ),
+            Space::Lahey { .. } => self.get_cell(x, y) as u8,
+        }
     }
 
     pub fn set(&mut self, x: usize, y: usize, value: u8) {
-        self.field[y % self.height][x % self.width] = value;
+        match &mut self.space {
+            Space::Torus {
+                width,
+                height,
+                field,
+            } => {
+                let (x, y) = (x % *width, y % *height);
+                if let Some(journal) = &mut self.journal {
+                    journal.push((x as i32, y as i32, i32::from(field[y][x])));
+                }
+                field[y][x] = value;
+            }
+            Space::Lahey { .. } => self.set_cell(x as i32, y as i32, i32::from(value)),
+        }
     }
 
     pub fn set_signed(&mut self, x: i32, y: i32, value: u8) {
-        let x = Self::wrap_signed_coordinate(x, self.width);
-        let y = Self::wrap_signed_coordinate(y, self.height);
-        self.set(x, y, value);
+        match &self.space {
+            Space::Torus { width, height, .. } => {
+                let x = Self::wrap_signed_coordinate(x, *width);
+                let y = Self::wrap_signed_coordinate(y, *height);
+                self.set(x, y, value);
+            }
+            Space::Lahey { .. } => self.set_cell(x, y, i32::from(value)),
+        }
+    }
+
+    /// The cell at `(x, y)`: a byte on the torus, where the coordinates wrap,
+    /// or any `i32` in Funge-98 space, where unset cells are spaces.
+    pub fn get_cell(&self, x: i32, y: i32) -> i32 {
+        match &self.space {
+            Space::Torus { .. } => i32::from(self.get_signed(x, y)),
+            Space::Lahey { cells, .. } => cells.get(&(x, y)).copied().unwrap_or(SPACE),
+        }
+    }
+
+    /// Stores `value` at `(x, y)`. The torus keeps only the low byte.
+    pub fn set_cell(&mut self, x: i32, y: i32, value: i32) {
+        // The torus journals in `set`.
+        if let (Space::Lahey { cells, .. }, Some(journal)) = (&self.space, &mut self.journal) {
+            journal.push((x, y, cells.get(&(x, y)).copied().unwrap_or(SPACE)));
+        }
+        match &mut self.space {
+            Space::Torus { .. } => self.set_signed(x, y, value as u8),
+            Space::Lahey {
+                cells,
+                rows,
+                columns,
+                least,
+                greatest,
+            } => {
+                if value == SPACE {
+                    if cells.remove(&(x, y)).is_some() {
+                        for (index, line, at) in [(rows, y, x), (columns, x, y)] {
+                            if let Some(set) = index.get_mut(&line) {
+                                set.remove(&at);
+                                if set.is_empty() {
+                                    index.remove(&line);
+                                }
+                            }
+                        }
+                    }
+                } else {
+                    cells.insert((x, y), value);
+                    rows.entry(y).or_default().insert(x);
+                    columns.entry(x).or_default().insert(y);
+                    *least = (least.0.min(x), least.1.min(y));
+                    *greatest = (greatest.0.max(x), greatest.1.max(y));
+                }
+            }
+        }
+    }
+
+    /// Where `(x, y)` is stored: the torus wraps it, Funge-98 space does not.
+    pub(crate) fn wrap(&self, x: i32, y: i32) -> (i32, i32) {
+        match &self.space {
+            Space::Torus { width, height, .. } => (
+                Self::wrap_signed_coordinate(x, *width) as i32,
+                Self::wrap_signed_coordinate(y, *height) as i32,
+            ),
+            Space::Lahey { .. } => (x, y),
+        }
+    }
+
+    /// How many cells Funge-98 space stores; every cell of the torus counts.
+    pub(crate) fn stored(&self) -> usize {
+        match &self.space {
+            Space::Torus { width, height, .. } => width * height,
+            Space::Lahey { cells, .. } => cells.len(),
+        }
+    }
+
+    /// Every cell that is not a space, row by row.
+    pub(crate) fn cells(&self) -> Vec<((i32, i32), i32)> {
+        let mut cells: Vec<_> = match &self.space {
+            Space::Torus { field, .. } => field
+                .iter()
+                .enumerate()
+                .flat_map(|(y, row)| {
+                    row.iter()
+                        .enumerate()
+                        .map(move |(x, &byte)| ((x as i32, y as i32), i32::from(byte)))
+                })
+                .filter(|&(_, value)| value != SPACE)
+                .collect(),
+            Space::Lahey { cells, .. } => cells.iter().map(|(&at, &value)| (at, value)).collect(),
+        };
+        cells.sort_by_key(|&((x, y), _)| (y, x));
+        cells
+    }
+
+    /// Sets the bounds of Funge-98 space; the torus ignores this.
+    pub(crate) fn set_bounds(&mut self, bounds: ((i32, i32), (i32, i32))) {
+        if let Space::Lahey {
+            least, greatest, ..
+        } = &mut self.space
+        {
+            (*least, *greatest) = bounds;
+        }
+    }
+
+    /// Takes on the cells of `other`, while going on journaling as before.
+    pub(crate) fn load(&mut self, other: &World) {
+        self.space = other.space.clone();
+    }
+
+    /// Starts or stops recording every write as `(x, y, old value)`.
+    pub(crate) fn set_journaling(&mut self, on: bool) {
+        self.journal = on.then(Vec::new);
+    }
+
+    /// The writes journaled since the last call, oldest first.
+    pub(crate) fn take_journal(&mut self) -> Vec<(i32, i32, i32)> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Reverts journaled `writes` and restores the bounds from before them.
+    pub(crate) fn undo_writes(
+        &mut self,
+        writes: &[(i32, i32, i32)],
+        bounds: ((i32, i32), (i32, i32)),
+    ) {
+        let journal = self.journal.take();
+        for &(x, y, old) in writes.iter().rev() {
+            self.set_cell(x, y, old);
+        }
+        self.journal = journal;
+        self.set_bounds(bounds);
+    }
+
+    /// Where an IP at `(x, y)` moving by `(dx, dy)` ends up after one move.
+    ///
+    /// The torus wraps each coordinate. Funge-98 space uses Lahey-space
+    /// wrapping: an IP about to leave the program's bounds continues from the
+    /// far side of the bounds on the same line, as if it had flown backwards.
+    pub fn next_position(&self, x: i32, y: i32, dx: i32, dy: i32) -> (i32, i32) {
+        match &self.space {
+            Space::Torus { width, height, .. } => (
+                Self::wrap_signed_coordinate(x.wrapping_add(dx), *width) as i32,
+                Self::wrap_signed_coordinate(y.wrapping_add(dy), *height) as i32,
+            ),
+            Space::Lahey {
+                least, greatest, ..
+            } => {
+                let next = (x.wrapping_add(dx), y.wrapping_add(dy));
+                let inside = least.0 <= next.0
+                    && next.0 <= greatest.0
+                    && least.1 <= next.1
+                    && next.1 <= greatest.1;
+                if inside || (dx, dy) == (0, 0) {
+                    return next;
+                }
+                // A line that misses the program entirely flies forever.
+                self.furthest_back_in_bounds(x, y, dx, dy).unwrap_or(next)
+            }
+        }
+    }
+
+    /// The first cell after `(x, y)` along `(dx, dy)` that is not a space,
+    /// wrapping as [`World::next_position`] does; `(x, y)` itself when it is
+    /// the only one. `None` if the IP's whole path is spaces.
+    pub(crate) fn next_stored(&self, x: i32, y: i32, dx: i32, dy: i32) -> Option<(i32, i32)> {
+        let Space::Lahey {
+            cells,
+            rows,
+            columns,
+            ..
+        } = &self.space
+        else {
+            let (mut cx, mut cy) = (x, y);
+            for _ in 0..self.width() * self.height() {
+                (cx, cy) = self.next_position(cx, cy, dx, dy);
+                if self.get_cell(cx, cy) != SPACE {
+                    return Some((cx, cy));
+                }
+            }
+            return None;
+        };
+        if (dx, dy) == (0, 0) {
+            return cells.contains_key(&(x, y)).then_some((x, y));
+        }
+        // Along a row or column, the stored cells come in the order the IP
+        // meets them: those ahead, then from the far side back round to `(x, y)`.
+        let line = |index: &BTreeMap<i32, BTreeSet<i32>>, line: i32, at: i32, d: i32| {
+            let set = index.get(&line)?;
+            let on_path = |&&other: &&i32| (i64::from(other) - i64::from(at)) % i64::from(d) == 0;
+            if d > 0 {
+                set.range(at.saturating_add(1)..)
+                    .chain(set.range(..=at))
+                    .find(on_path)
+                    .copied()
+            } else {
+                set.range(..at)
+                    .rev()
+                    .chain(set.range(at..).rev())
+                    .find(on_path)
+                    .copied()
+            }
+        };
+        if dy == 0 {
+            return line(rows, y, x, dx).map(|cx| (cx, y));
+        }
+        if dx == 0 {
+            return line(columns, x, y, dy).map(|cy| (x, cy));
+        }
+        // Otherwise, how many moves ahead each stored cell on the path is.
+        let ahead = cells.keys().filter_map(|&(cx, cy)| {
+            let (ox, oy) = (i64::from(cx) - i64::from(x), i64::from(cy) - i64::from(y));
+            let t = ox / i64::from(dx);
+            (ox % i64::from(dx) == 0 && oy == t * i64::from(dy)).then_some((t, (cx, cy)))
+        });
+        let (mut nearest, mut furthest_back) = (None, None);
+        for (t, at) in ahead {
+            if t > 0 && nearest.is_none_or(|(n, _)| t < n) {
+                nearest = Some((t, at));
+            }
+            if furthest_back.is_none_or(|(n, _)| t < n) {
+                furthest_back = Some((t, at));
+            }
+        }
+        nearest.or(furthest_back).map(|(_, at)| at)
+    }
+
+    /// The in-bounds point `(x - t*dx, y - t*dy)` with the largest `t`.
+    fn furthest_back_in_bounds(&self, x: i32, y: i32, dx: i32, dy: i32) -> Option<(i32, i32)> {
+        let ((x0, y0), (x1, y1)) = self.bounds();
+        let mut t_min = i64::MIN;
+        let mut t_max = i64::MAX;
+        for (p, d, lo, hi) in [(x, dx, x0, x1), (y, dy, y0, y1)] {
+            let (p, d, lo, hi) = (i64::from(p), i64::from(d), i64::from(lo), i64::from(hi));
+            if lo > hi {
+                return None;
+            }
+            if d == 0 {
+                if p < lo || p > hi {
+                    return None;
+                }
+                continue;
+            }
+            // lo <= p - t*d <= hi
+            let (from, to) = if d > 0 {
+                (div_ceil(p - hi, d), div_floor(p - lo, d))
+            } else {
+                (div_ceil(p - lo, d), div_floor(p - hi, d))
+            };
+            t_min = t_min.max(from);
+            t_max = t_max.min(to);
+        }
+        if t_min > t_max {
+            return None;
+        }
+        let x = i64::from(x) - t_max * i64::from(dx);
+        let y = i64::from(y) - t_max * i64::from(dy);
+        Some((x as i32, y as i32))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::World;
+    use super::{Space, Standard, World};
 
     #[test]
     fn hello_world_program() -> std::io::Result<()> {
@@ -166,6 +654,8 @@ mod tests {
         world.set_signed(-1, -1, b'!');
         assert_eq!(world.get(79, 24), b'!');
         assert_eq!(world.get_signed(-1, -1), b'!');
+        assert_eq!(world.next_position(79, 0, 1, 0), (0, 0));
+        assert_eq!(world.next_position(0, 0, 0, -1), (0, 24));
         Ok(())
     }
 
@@ -192,9 +682,81 @@ mod tests {
         let world = World::from_random(width, height);
         assert_eq!(world.width(), width);
         assert_eq!(world.height(), height);
-        assert_eq!(world.field.len(), height);
-        for belt in &world.field {
-            assert_eq!(belt.len(), width);
+        match &world.space {
+            Space::Torus { field, .. } => {
+                assert_eq!(field.len(), height);
+                for belt in field {
+                    assert_eq!(belt.len(), width);
+                }
+            }
+            Space::Lahey { .. } => panic!("random programs are tori"),
         }
     }
+
+    #[test]
+    fn funge_98_sources_are_unbounded_and_sparse() -> std::io::Result<()> {
+        let wide = format!("{}@\r\nv\x0c>", " ".repeat(200));
+        let mut world = World::from_source(&wide, Standard::Funge98)?;
+        assert_eq!(world.standard(), Standard::Funge98);
+        assert_eq!(world.get_cell(200, 0), i32::from(b'@'));
+        assert_eq!(world.get_cell(1, 1), i32::from(b'>'));
+        assert_eq!(world.bounds(), ((0, 0), (200, 1)));
+        world.set_cell(-5, -7, 1_000_000);
+        assert_eq!(world.get_cell(-5, -7), 1_000_000);
+        assert_eq!(world.get_cell(-5_000, 9_000), i32::from(b' '));
+        assert_eq!(world.bounds(), ((-5, -7), (200, 1)));
+        assert_eq!(world.width(), 206);
+        assert_eq!(world.height(), 9);
+        Ok(())
+    }
+
+    #[test]
+    fn the_next_stored_cell_is_found_along_any_line() {
+        let mut world = World::from_source_string_98("a b\n\n  c");
+        world.set_cell(1_000_000, 0, 100);
+        assert_eq!(world.next_stored(0, 0, 1, 0), Some((2, 0)));
+        assert_eq!(world.next_stored(2, 0, 1, 0), Some((1_000_000, 0)));
+        assert_eq!(world.next_stored(1_000_000, 0, 1, 0), Some((0, 0)));
+        assert_eq!(world.next_stored(0, 0, -1, 0), Some((1_000_000, 0)));
+        assert_eq!(world.next_stored(0, 0, 2, 0), Some((2, 0)));
+        assert_eq!(world.next_stored(1, 0, 2, 0), None);
+        assert_eq!(world.next_stored(2, 1, 0, 1), Some((2, 2)));
+        assert_eq!(world.next_stored(2, 2, 0, 1), Some((2, 0)));
+        assert_eq!(world.next_stored(2, 2, 0, -1), Some((2, 0)));
+        assert_eq!(world.next_stored(1, 1, 1, 1), Some((2, 2)));
+        assert_eq!(world.next_stored(2, 2, 1, 1), Some((0, 0)));
+        assert_eq!(world.next_stored(0, 0, 0, 0), Some((0, 0)));
+        // Cells cleared back to spaces are forgotten.
+        world.set_cell(1_000_000, 0, i32::from(b' '));
+        assert_eq!(world.next_stored(2, 0, 1, 0), Some((0, 0)));
+    }
+
+    #[test]
+    fn windows_are_cut_round_a_point_within_the_bounds() {
+        let mut world = World::from_source_string_98("@");
+        assert_eq!(world.window((0, 0)), ((0, 0), (0, 0)));
+        world.set_cell(1_000_000, -1_000, 1);
+        assert_eq!(world.window((0, 0)), ((0, -24), (79, 0)));
+        assert_eq!(world.window((500, -500)), ((460, -512), (539, -488)));
+        assert_eq!(
+            world.window((2_000_000, -5)),
+            ((999_921, -24), (1_000_000, 0))
+        );
+        let torus = World::from_source_string("@").unwrap();
+        assert_eq!(torus.window((40, 12)), torus.bounds());
+    }
+
+    #[test]
+    fn lahey_space_wraps_to_the_far_side_of_the_bounds() {
+        let world = World::from_source_string_98("abc\nd\nefgh");
+        assert_eq!(world.bounds(), ((0, 0), (3, 2)));
+        assert_eq!(world.next_position(3, 0, 1, 0), (0, 0));
+        assert_eq!(world.next_position(0, 1, -1, 0), (3, 1));
+        assert_eq!(world.next_position(1, 0, 0, -1), (1, 2));
+        // Diagonals fly back along their own line.
+        assert_eq!(world.next_position(3, 2, 1, 1), (1, 0));
+        assert_eq!(world.next_position(2, 2, 2, -1), (2, 2));
+        // Lines that miss the program entirely never wrap.
+        assert_eq!(world.next_position(10, 10, 1, 0), (11, 10));
+    }
 }
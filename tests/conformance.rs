@@ -0,0 +1,23 @@
+//! Runs the Befunge-93 conformance suite in `tests/conformance` against the
+//! built binary, as `befunge_rust test tests/conformance` does.
+
+use befunge_rust::golden;
+use std::path::Path;
+
+#[test]
+fn conformance_suite() {
+    let interpreter = Path::new(env!("CARGO_BIN_EXE_befunge_rust"));
+    let suite = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let cases = golden::discover(&suite).expect("the suite can be read");
+    assert!(!cases.is_empty());
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|case| {
+            let outcome = case.run(interpreter, &[]).expect("the interpreter runs");
+            case.check(&outcome)
+                .err()
+                .map(|problem| format!("{}: {}", case.name, problem))
+        })
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
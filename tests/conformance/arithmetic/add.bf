@@ -0,0 +1 @@
+23+.@
@@ -0,0 +1 @@
+72/.09-2/.@
@@ -0,0 +1 @@
+73%.09-4%.@
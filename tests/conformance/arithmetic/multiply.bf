@@ -0,0 +1 @@
+67*.@
@@ -0,0 +1 @@
+88*:*:*:*:*.@
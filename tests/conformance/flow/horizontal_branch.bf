@@ -0,0 +1,4 @@
+1#v_2.@
+  3
+  .
+  @
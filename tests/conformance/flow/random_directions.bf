@@ -0,0 +1,25 @@
+?7.@                                                                         @.7
+7
+.
+@
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+@
+.
+7
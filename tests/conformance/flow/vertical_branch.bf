@@ -0,0 +1,8 @@
+v
+1@
+ .
+ 5
+>|
+ 6
+ .
+ @
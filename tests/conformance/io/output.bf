@@ -0,0 +1 @@
+05-."!",@
@@ -0,0 +1 @@
+21`.12`.22`.@
@@ -0,0 +1 @@
+85*2*0g.055*g.01-0g.@
@@ -0,0 +1 @@
+"A"00p00g,@
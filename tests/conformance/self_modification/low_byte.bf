@@ -0,0 +1 @@
+99*9*9*00p00g.@
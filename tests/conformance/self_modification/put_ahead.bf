@@ -0,0 +1 @@
+"9"80p  0.@
@@ -0,0 +1,3 @@
+0>1+:.:5`#v_v
+ ^          <
+ ^  p11"@"<
@@ -0,0 +1 @@
+111111111111111111111111111111111111111111111111111111111111111111111111111111111
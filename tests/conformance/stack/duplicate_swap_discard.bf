@@ -0,0 +1 @@
+12\..3:..45$.@
@@ -0,0 +1 @@
+p00g.@
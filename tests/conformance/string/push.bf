@@ -0,0 +1 @@
+"olleh",,,,,@
@@ -0,0 +1,25 @@
+v.
+ @
+
+
+
+>v
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+ 6
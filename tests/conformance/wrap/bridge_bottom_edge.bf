@@ -0,0 +1,25 @@
+v@
+ 2
+ .
+ @
+>v
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+ #
@@ -0,0 +1,2 @@
+                                                                            v
+a",,,@                                                                      >"b
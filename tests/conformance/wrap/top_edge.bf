@@ -0,0 +1,25 @@
+^
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+@
+.
+9